@@ -0,0 +1,79 @@
+//! Throughput of the lexer over large generated sources, next to the regex
+//! based tokenizer it replaced, run with `cargo bench --bench lexer`.
+
+use compiler::{lex::string_tokenizer, utility::FileId};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+mod regex_tokenizer;
+
+/// Source made of `functions` functions using every kind of token, along
+/// with comments and non-ASCII text inside them and in string literals.
+fn generate_source(functions: usize) -> String {
+  let mut source = String::from("/* generated for benchmarking — ünïcödé */\n");
+  for idx in 0..functions {
+    source.push_str(&format!(
+      "static unsigned long counter_{idx} = {idx};\n\
+       // function number {idx}, naïve on purpose\n\
+       double function_{idx}(int first, float *second, char third) {{\n  \
+         struct point {{ int x; long y; }} p = {{ {idx}, -{idx} }};\n  \
+         double scale = 1.5e3 + .25f - {idx}.0;\n  \
+         char *name = \"function \\\"{idx}\\\" ✓\\n\";\n  \
+         for (int i = 0; i <= first && i != 42 || !third; i++) {{\n    \
+           counter_{idx} += (i << 2) % 7 ^ (i >> 1) | ~i & 0;\n    \
+           second[i] *= scale / (p.x ? p.y : '\\x41');\n  \
+         }}\n  \
+         return first >= 0 ? scale : -scale; /* done */\n\
+       }}\n"
+    ));
+  }
+  source
+}
+
+/// Check that both tokenizers read the same tokens out of `source`, so the
+/// numbers compare the same work.
+fn assert_same_tokens(source: &str) {
+  let tokens = string_tokenizer(source, FileId(0)).unwrap();
+  let baseline = regex_tokenizer::string_tokenizer(source, FileId(0)).unwrap();
+  assert_eq!(tokens.len(), baseline.len());
+  for (token, expected) in tokens.iter().zip(&baseline) {
+    assert_eq!((&token.token, token.span), (&expected.token, expected.span));
+  }
+}
+
+fn lexer(c: &mut Criterion) {
+  let mut group = c.benchmark_group("lexer");
+  for functions in [100, 1_000, 10_000] {
+    let source = generate_source(functions);
+    group.throughput(Throughput::Bytes(source.len() as u64));
+    group.bench_with_input(
+      BenchmarkId::from_parameter(functions),
+      &source,
+      |bencher, source| {
+        bencher.iter(|| string_tokenizer(black_box(source), FileId(0)).unwrap());
+      },
+    );
+  }
+  group.finish();
+
+  // the baseline compiles its regular expressions for every token and takes
+  // seconds for the smallest source already
+  let mut group = c.benchmark_group("regex_tokenizer");
+  let source = generate_source(100);
+  assert_same_tokens(&source);
+  group.throughput(Throughput::Bytes(source.len() as u64));
+  group.bench_with_input(
+    BenchmarkId::from_parameter(100),
+    &source,
+    |bencher, source| {
+      bencher.iter(|| regex_tokenizer::string_tokenizer(black_box(source), FileId(0)).unwrap());
+    },
+  );
+  group.finish();
+}
+
+criterion_group! {
+  name = benches;
+  config = Criterion::default().sample_size(10);
+  targets = lexer
+}
+criterion_main!(benches);
@@ -0,0 +1,358 @@
+//! The regex based tokenizer the hand-written lexer replaced, kept as the
+//! baseline of the lexer benchmark. It knows nothing of integer suffixes,
+//! other bases or the tokens of the preprocessor.
+
+use std::rc::Rc;
+
+use compiler::{
+  lex::{BinaryOperator, IntegerKind, SpannedToken, Token},
+  utility::{FileId, Span, SyntaxError},
+};
+use regex::Regex;
+use tracing::trace;
+
+/// Token read at `span`, where line starts and whitespace are not tracked.
+fn spanned(token: Token, span: Span) -> SpannedToken {
+  SpannedToken {
+    token,
+    span,
+    first_on_line: false,
+    whitespace_before: false,
+  }
+}
+
+fn parse_literal_token(value: &str) -> (Option<Token>, usize) {
+  // a floating literal has a fraction or an exponent, `1.` and `.5` included
+  let floating_regex = Regex::new(
+    "^(?:\\d+\\.\\d*(?:[eE][+-]?\\d+)?|\\.\\d+(?:[eE][+-]?\\d+)?|\\d+[eE][+-]?\\d+)([fF]?)",
+  )
+  .unwrap();
+  if let Some(captures) = floating_regex.captures(value) {
+    let (literal, suffix) = (captures.get(0).unwrap(), captures.get(1).unwrap());
+    let digits = &value[..suffix.start()];
+    let token = if suffix.is_empty() {
+      Token::DoubleLiteral(digits.parse().expect("floating parse"))
+    } else {
+      Token::FloatLiteral(digits.parse::<f32>().expect("floating parse") as f64)
+    };
+    return (Some(token), literal.end());
+  }
+
+  let literal_regex = Regex::new("^\\w+").unwrap();
+  let number_regex = Regex::new("^\\d+").unwrap();
+
+  match literal_regex.find(value) {
+    Some(literal_match) => {
+      let token = if let Some(keyword) = Token::keywords(literal_match.as_str()) {
+        keyword
+      } else if let Some(number_match) = number_regex.find(literal_match.as_str()) {
+        Token::Integer(
+          number_match.as_str().parse().expect("integer parse"),
+          IntegerKind::Int,
+        )
+      } else {
+        Token::Identifier(Rc::new(literal_match.as_str().to_owned()))
+      };
+
+      (Some(token), literal_match.end())
+    }
+    None => (None, 0),
+  }
+}
+
+/// Value of the escape sequence at the start of `value`, which follows a
+/// backslash, along with the number of bytes it takes.
+fn parse_escape(value: &[u8]) -> Result<(u8, usize), SyntaxError> {
+  let simple = match value.first() {
+    Some(b'n') => b'\n',
+    Some(b't') => b'\t',
+    Some(b'r') => b'\r',
+    Some(b'a') => 0x07,
+    Some(b'b') => 0x08,
+    Some(b'f') => 0x0c,
+    Some(b'v') => 0x0b,
+    Some(&byte @ (b'\\' | b'\'' | b'"' | b'?')) => byte,
+    Some(b'0'..=b'7') => {
+      let digits = value
+        .iter()
+        .take(3)
+        .take_while(|byte| (b'0'..=b'7').contains(*byte))
+        .count();
+      let code = u32::from_str_radix(std::str::from_utf8(&value[..digits]).unwrap(), 8).unwrap();
+      let code = u8::try_from(code).map_err(|_| {
+        SyntaxError::new_lex_error("octal escape sequence out of range".to_string())
+      })?;
+      return Ok((code, digits));
+    }
+    Some(b'x') => {
+      let digits = value[1..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_hexdigit())
+        .count();
+      if digits == 0 {
+        return Err(SyntaxError::new_lex_error(
+          "\\x used with no following hex digits".to_string(),
+        ));
+      }
+      let code = u64::from_str_radix(std::str::from_utf8(&value[1..=digits]).unwrap(), 16)
+        .ok()
+        .and_then(|code| u8::try_from(code).ok())
+        .ok_or_else(|| {
+          SyntaxError::new_lex_error("hex escape sequence out of range".to_string())
+        })?;
+      return Ok((code, digits + 1));
+    }
+    Some(&other) => {
+      return Err(SyntaxError::new_lex_error(format!(
+        "unknown escape sequence \\{}",
+        other as char
+      )))
+    }
+    None => {
+      return Err(SyntaxError::new_lex_error(
+        "unterminated escape sequence".to_string(),
+      ))
+    }
+  };
+  Ok((simple, 1))
+}
+
+/// Parse the character or string literal at the start of `value`, which
+/// starts with its opening quote, returning the token and its length.
+fn parse_quoted_token(value: &str) -> Result<(Token, usize), SyntaxError> {
+  let bytes = value.as_bytes();
+  let quote = bytes[0];
+  let mut contents = vec![];
+  let mut idx = 1;
+  loop {
+    match bytes.get(idx) {
+      Some(&byte) if byte == quote => break,
+      Some(b'\\') => {
+        let (code, len) = parse_escape(&bytes[idx + 1..]).map_err(|err| err.with_code("E0103"))?;
+        contents.push(code);
+        idx += len + 1;
+      }
+      Some(b'\n') | None => {
+        let kind = if quote == b'"' { "string" } else { "character" };
+        return Err(
+          SyntaxError::new_lex_error(format!("missing terminating {} character", quote as char))
+            .with_code("E0102")
+            .with_help(format!("{} literals end on the line they start", kind)),
+        );
+      }
+      Some(&byte) => {
+        contents.push(byte);
+        idx += 1;
+      }
+    }
+  }
+
+  let token = match (quote, &contents[..]) {
+    (b'"', _) => Token::StringLiteral(Rc::new(contents)),
+    // `char` is signed, so bytes from 0x80 up are negative
+    (_, &[byte]) => Token::CharLiteral(byte as i8 as i64),
+    (_, []) => {
+      return Err(
+        SyntaxError::new_lex_error("empty character literal".to_string()).with_code("E0103"),
+      )
+    }
+    _ => {
+      return Err(
+        SyntaxError::new_lex_error("multi-character character literal".to_string())
+          .with_code("E0103"),
+      )
+    }
+  };
+  Ok((token, idx + 1))
+}
+
+/// Parse the three character token at the start of `value`.
+fn parse_triple_token(value: Option<&str>) -> (Option<Token>, usize) {
+  let token = match value {
+    Some("...") => Token::Ellipsis,
+    Some("<<=") => Token::CompoundAssignment(BinaryOperator::ShiftLeft),
+    Some(">>=") => Token::CompoundAssignment(BinaryOperator::ShiftRight),
+    _ => return (None, 0),
+  };
+  (Some(token), 3)
+}
+
+fn parse_compound_token(value: Option<&str>) -> (Option<Token>, usize) {
+  match value {
+    Some(compound_value) => {
+      let compound_token = match compound_value {
+        "<<" => Token::ShiftLeft,
+        ">>" => Token::ShiftRight,
+        "++" => Token::Increment,
+        "--" => Token::Decrement,
+        "+=" => Token::CompoundAssignment(BinaryOperator::Addition),
+        "-=" => Token::CompoundAssignment(BinaryOperator::Minus),
+        "*=" => Token::CompoundAssignment(BinaryOperator::Multiplication),
+        "/=" => Token::CompoundAssignment(BinaryOperator::Division),
+        "%=" => Token::CompoundAssignment(BinaryOperator::Remainder),
+        "&=" => Token::CompoundAssignment(BinaryOperator::BitwiseAnd),
+        "|=" => Token::CompoundAssignment(BinaryOperator::BitwiseOr),
+        "^=" => Token::CompoundAssignment(BinaryOperator::BitwiseXor),
+        "&&" => Token::And,
+        "||" => Token::Or,
+        "==" => Token::Equal,
+        "!=" => Token::NotEqual,
+        "<=" => Token::LessThanOrEqual,
+        ">=" => Token::GreaterThanOrEqual,
+        "->" => Token::Arrow,
+        _ => return (None, 0),
+      };
+
+      (Some(compound_token), 2)
+    }
+    None => (None, 0),
+  }
+}
+
+fn try_parse_simple_token(value: char) -> Result<(Option<Token>, usize), SyntaxError> {
+  if value.is_whitespace() {
+    return Ok((None, 1));
+  }
+
+  let token = match value {
+    '{' => Token::OpenBrace,
+    '}' => Token::CloseBrace,
+    '(' => Token::OpenParenthesis,
+    ')' => Token::CloseParenthesis,
+    '[' => Token::OpenBracket,
+    ']' => Token::CloseBracket,
+    ';' => Token::SemiColon,
+    ',' => Token::Comma,
+    '-' => Token::Negation,
+    '~' => Token::BitwiseComplement,
+    '!' => Token::LogicalNegation,
+    '+' => Token::Addition,
+    '*' => Token::Multiplication,
+    '/' => Token::Division,
+    '%' => Token::Remainder,
+    '&' => Token::Ampersand,
+    '|' => Token::Pipe,
+    '^' => Token::Caret,
+    '<' => Token::LessThan,
+    '>' => Token::GreaterThan,
+    '=' => Token::Assignment,
+    '?' => Token::QuestionMark,
+    ':' => Token::Colon,
+    '.' => Token::Dot,
+    val => {
+      return Err(SyntaxError::new_lex_error(format!(
+        "unidentifiable character {}",
+        val
+      )))
+    }
+  };
+
+  Ok((Some(token), 1))
+}
+
+/// Length of the comment at the start of `value` if it starts with one,
+/// either a `//` comment running up to the end of the line or a `/* */`
+/// comment which may span lines.
+fn comment_length(value: &str) -> Result<Option<usize>, SyntaxError> {
+  if value.starts_with("//") {
+    return Ok(Some(value.find('\n').unwrap_or(value.len())));
+  }
+  if !value.starts_with("/*") {
+    return Ok(None);
+  }
+  match value[2..].find("*/") {
+    Some(end) => Ok(Some(end + 4)),
+    None => Err(
+      SyntaxError::new_lex_error("unterminated comment".to_string())
+        .with_code("E0104")
+        .with_help("close the comment with `*/`".to_string()),
+    ),
+  }
+}
+
+/// Tokenize the whole source text of `file`, skipping whitespace and
+/// comments.
+pub fn string_tokenizer(value: &str, file: FileId) -> Result<Vec<SpannedToken>, SyntaxError> {
+  let mut tokens = Vec::new();
+  let mut end_idx = 0;
+  let byte_array = value.as_bytes();
+  // 1-based line number and byte offset of the first character of the line
+  // `end_idx` is on, used to build token spans
+  let (mut line, mut line_start) = (1, 0);
+  let span_of = |start: usize, increment: usize, line: usize, line_start: usize| {
+    Span::new(file, start, start + increment, line, start - line_start + 1)
+  };
+
+  while end_idx < value.len() {
+    let span_of = move |start: usize, increment: usize| span_of(start, increment, line, line_start);
+    if byte_array[end_idx] == b'\n' {
+      end_idx += 1;
+      line += 1;
+      line_start = end_idx;
+    } else if let Some(increment) =
+      comment_length(&value[end_idx..]).map_err(|err| err.with_span(span_of(end_idx, 2)))?
+    {
+      trace!("comment end index is now {}", end_idx + increment);
+      // block comments may span lines
+      let comment = &value[end_idx..end_idx + increment];
+      if let Some(last) = comment.rfind('\n') {
+        line += comment.matches('\n').count();
+        line_start = end_idx + last + 1;
+      }
+      end_idx += increment;
+    } else if let b'\'' | b'"' = byte_array[end_idx] {
+      let line_end = value[end_idx..]
+        .find('\n')
+        .map_or(value.len(), |idx| end_idx + idx);
+      let (token, increment) = parse_quoted_token(&value[end_idx..])
+        .map_err(|err| err.with_span(span_of(end_idx, line_end - end_idx)))?;
+      trace!(
+        "quoted match for token {} end index is now {}",
+        token,
+        end_idx + increment
+      );
+      tokens.push(spanned(token, span_of(end_idx, increment)));
+      end_idx += increment;
+    } else if let (Some(token), increment) = parse_triple_token(value.get(end_idx..end_idx + 3)) {
+      trace!(
+        "triple match for token {} end index is now {}",
+        token,
+        end_idx + increment
+      );
+      tokens.push(spanned(token, span_of(end_idx, increment)));
+      end_idx += increment;
+    } else if let (Some(literal_value), increment) = parse_literal_token(&value[end_idx..]) {
+      trace!(
+        "literal match for token {} end index is now {}",
+        literal_value,
+        end_idx + increment
+      );
+      tokens.push(spanned(literal_value, span_of(end_idx, increment)));
+      end_idx += increment;
+    } else if let (Some(compound_value), increment) =
+      parse_compound_token(value.get(end_idx..end_idx + 2))
+    {
+      trace!(
+        "compound match for token {} end index is now {}",
+        compound_value,
+        end_idx
+      );
+      tokens.push(spanned(compound_value, span_of(end_idx, increment)));
+      end_idx += increment;
+    } else {
+      let (simple_token, increment) = try_parse_simple_token(byte_array[end_idx] as char)
+        .map_err(|err| err.with_span(span_of(end_idx, 1)).with_code("E0101"))?;
+      trace!(
+        "simple match for token {:?} end index is now {}",
+        simple_token,
+        end_idx
+      );
+      if let Some(simple_value) = simple_token {
+        tokens.push(spanned(simple_value, span_of(end_idx, increment)));
+      }
+      end_idx += increment;
+    }
+  }
+
+  Ok(tokens)
+}
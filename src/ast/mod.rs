@@ -1,5 +1,7 @@
 pub mod model;
 pub mod parser;
+pub mod types;
 
 pub use model::Scanner;
 pub use parser::*;
+pub use types::Type;
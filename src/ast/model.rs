@@ -1,62 +1,169 @@
-use std::{fmt, rc::Rc, slice::Iter};
+use std::{fmt, rc::Rc};
 
-use itertools::MultiPeek;
+use itertools::Itertools;
 
+use super::Type;
 use crate::{
-  lex::{BinaryOperator, Token, UnaryOperator},
-  utility::SyntaxError,
+  lex::{BinaryOperator, SpannedToken, Token, UnaryOperator},
+  utility::{Span, SyntaxError},
 };
 
 /// Scanner implementation to handle iterative parsing of [`Token`] values.
 pub struct Scanner<'a> {
-  tokens: MultiPeek<Iter<'a, Token>>,
+  tokens: &'a [SpannedToken],
+  position: usize,
+  peek_offset: usize,
 }
 
 impl<'a> Scanner<'a> {
-  /// Create new [`Token`] scanner from [`MultiPeek`] iterator.
-  pub fn new(tokens: MultiPeek<Iter<'a, Token>>) -> Self {
-    Self { tokens: tokens }
+  /// Create new [`Token`] scanner over lexed tokens.
+  pub fn new(tokens: &'a [SpannedToken]) -> Self {
+    Self {
+      tokens,
+      position: 0,
+      peek_offset: 0,
+    }
   }
 
-  /// Call peek on tokens without advancing itself, [`MultiPeek::next`] resets peek pointer
-  pub fn peek(&mut self) -> Option<&Token> {
-    match self.tokens.peek() {
-      Some(val) => Some(*val),
-      None => None,
-    }
+  /// Call peek on tokens without advancing itself, every call moves the peek pointer
+  /// one token further until [`Self::pop`] or [`Self::reset_peek`] resets it.
+  pub fn peek(&mut self) -> Option<&'a Token> {
+    let token = self.tokens.get(self.position + self.peek_offset)?;
+    self.peek_offset += 1;
+    Some(&token.token)
   }
 
-  /// Calls [`MultiPeek::next`] and returns the found token.
+  /// Pops the next token and returns it.
   /// If no token is found throws [`SyntaxError`] with `error_message`.
   ///
   /// This resets the pointer of [`Self::peek`].
   pub fn pop(&mut self, error_message: &str) -> Result<&'a Token, SyntaxError> {
-    match self.tokens.next() {
-      Some(val) => Ok(val),
-      None => Err(SyntaxError::new_parse_error(error_message.to_string())),
+    self.peek_offset = 0;
+    match self.tokens.get(self.position) {
+      Some(val) => {
+        self.position += 1;
+        Ok(&val.token)
+      }
+      None => Err(SyntaxError::new_parse_error(error_message.to_string()).with_span(self.span())),
     }
   }
 
-  /// Calls [`Self::pop`] and completes successfully if popped token matches
-  /// else throws [`SyntaxError`] with `error_message`.
+  /// Pops the next token and completes successfully if it matches `token_type`
+  /// else throws [`SyntaxError`] with `error_message`, leaving the mismatched
+  /// token in place so error recovery can see it.
   ///
   /// This resets the pointer of [`Self::peek`].
   pub fn take(&mut self, token_type: Token, error_message: &str) -> Result<(), SyntaxError> {
-    let token = self.pop(error_message)?;
-    if *token == token_type {
-      return Ok(());
+    self.reset_peek();
+    match self.peek() {
+      Some(token) if *token == token_type => {
+        self.pop(error_message)?;
+        Ok(())
+      }
+      _ => {
+        self.reset_peek();
+        Err(SyntaxError::new_parse_error(error_message.to_string()).with_span(self.span()))
+      }
     }
-    Err(SyntaxError::new_parse_error(error_message.to_string()))
+  }
+
+  /// Put back the most recently popped token, used when a token was popped
+  /// speculatively and turned out not to belong to the current rule.
+  ///
+  /// This resets the pointer of [`Self::peek`].
+  pub fn backtrack(&mut self) {
+    self.peek_offset = 0;
+    self.position = self.position.saturating_sub(1);
   }
 
   /// This resets the pointer of [`Self::peek`].
   pub fn reset_peek(&mut self) {
-    self.tokens.reset_peek();
+    self.peek_offset = 0;
+  }
+
+  /// Span of the next token to be popped, when input is exhausted this points
+  /// right after the last token.
+  pub fn span(&self) -> Span {
+    match self.tokens.get(self.position) {
+      Some(val) => val.span,
+      None => self
+        .tokens
+        .last()
+        .map(|val| val.span.after())
+        .unwrap_or_default(),
+    }
+  }
+
+  /// Span of the most recently popped token.
+  pub fn previous_span(&self) -> Span {
+    match self.position.checked_sub(1) {
+      Some(idx) => self.tokens[idx].span,
+      None => self.span(),
+    }
+  }
+
+  /// Span stretching from `start` up to the most recently popped token.
+  pub fn span_from(&self, start: Span) -> Span {
+    start.to(self.previous_span())
   }
 }
 
-pub enum Expression {
-  Const(i32),
+pub struct Expression {
+  pub kind: ExpressionKind,
+  /// Filled in by the semantic pass.
+  pub ty: Option<Type>,
+  pub span: Span,
+}
+
+impl Expression {
+  pub fn new(kind: ExpressionKind, span: Span) -> Self {
+    Self {
+      kind,
+      ty: None,
+      span,
+    }
+  }
+
+  pub fn with_type(mut self, ty: Type) -> Self {
+    self.ty = Some(ty);
+    self
+  }
+
+  /// Whether the expression designates an object, which may be assigned to
+  /// or have its address taken.
+  pub fn is_lvalue(&self) -> bool {
+    match &self.kind {
+      ExpressionKind::Var { .. }
+      | ExpressionKind::String(_)
+      | ExpressionKind::Subscript { .. }
+      | ExpressionKind::Unary {
+        op: UnaryOperator::Dereference,
+        ..
+      } => true,
+      // a member of a structure returned by a call is not an object
+      ExpressionKind::Member { exp, .. } => exp.is_lvalue(),
+      _ => false,
+    }
+  }
+
+  /// Type of a checked expression.
+  pub fn ty(&self) -> &Type {
+    self
+      .ty
+      .as_ref()
+      .expect("expression types are set by the semantic pass")
+  }
+}
+
+pub enum ExpressionKind {
+  /// Integer constant of an integer type, held as its bits like
+  /// [`Token::Integer`].
+  Const(i64, Type),
+  /// Floating constant of type `float` or `double`, already rounded to it.
+  ConstFloat(f64, Type),
+  /// String literal without its terminating null character, an array of
+  /// `char` stored in read-only memory.
+  String(Rc<Vec<u8>>),
   Unary {
     op: UnaryOperator,
     exp: Box<Expression>,
@@ -66,73 +173,376 @@ pub enum Expression {
     op: BinaryOperator,
     exp2: Box<Expression>,
   },
+  /// Assignment of `exp` to the object designated by the lvalue `target`.
   Assign {
-    name: Rc<String>,
+    target: Box<Expression>,
+    exp: Box<Expression>,
+  },
+  /// `target op= exp`, also `++` and `--` with `exp` the constant 1. The
+  /// result is the new value of `target` unless `postfix`, then the old one.
+  CompoundAssign {
+    op: BinaryOperator,
+    target: Box<Expression>,
     exp: Box<Expression>,
+    postfix: bool,
+    /// Type `op` is carried out in, filled in by the semantic pass.
+    operation_type: Option<Type>,
+  },
+  /// `exp1, exp2`, evaluating `exp1` only for its side effects.
+  Comma {
+    exp1: Box<Expression>,
+    exp2: Box<Expression>,
   },
   Var {
     name: Rc<String>,
   },
+  FunctionCall {
+    name: Rc<String>,
+    args: Vec<Expression>,
+  },
+  Conditional {
+    condition: Box<Expression>,
+    then: Box<Expression>,
+    otherwise: Box<Expression>,
+  },
+  /// Conversion of `exp` to `target`, either written out or inserted by the
+  /// semantic pass for implicit conversions.
+  Cast {
+    target: Type,
+    exp: Box<Expression>,
+  },
+  /// `exp[index]`, the element `index` places after where `exp` points.
+  Subscript {
+    exp: Box<Expression>,
+    index: Box<Expression>,
+  },
+  /// `exp.member`, `exp->member` is parsed as a member of `*exp`.
+  Member {
+    exp: Box<Expression>,
+    member: Rc<String>,
+  },
+  /// `sizeof` of an expression, which is not evaluated.
+  SizeOf(Box<Expression>),
+  SizeOfType(Type),
 }
 
 impl fmt::Display for Expression {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    match self {
-      Expression::Const(val) => write!(f, "Int({})", val),
-      Expression::Unary { op, exp } => write!(f, "{:?}[{}]", op, exp),
-      Expression::Binary { exp1, op, exp2 } => write!(f, "{{{}}}{:?}{{{}}}", exp1, op, exp2),
-      Expression::Assign { name, exp } => write!(f, "({} = [{}])", name, exp),
-      Expression::Var { name } => write!(f, "({})", name),
+    match &self.kind {
+      ExpressionKind::Const(val, Type::Int) => write!(f, "Const({})", val),
+      ExpressionKind::Const(val, ty) => write!(f, "Const({} {})", val, ty),
+      ExpressionKind::ConstFloat(val, ty) => write!(f, "Const({:?} {})", val, ty),
+      ExpressionKind::String(val) => write!(f, "{:?}", String::from_utf8_lossy(val)),
+      ExpressionKind::Unary { op, exp } => write!(f, "{:?}[{}]", op, exp),
+      ExpressionKind::Binary { exp1, op, exp2 } => write!(f, "{{{}}}{:?}{{{}}}", exp1, op, exp2),
+      ExpressionKind::Assign { target, exp } => write!(f, "({} = [{}])", target, exp),
+      ExpressionKind::CompoundAssign {
+        op,
+        target,
+        exp,
+        postfix: false,
+        ..
+      } => write!(f, "({} {:?}= [{}])", target, op, exp),
+      ExpressionKind::CompoundAssign {
+        op, target, exp, ..
+      } => write!(f, "({} POSTFIX {:?}= [{}])", target, op, exp),
+      ExpressionKind::Comma { exp1, exp2 } => write!(f, "({}, {})", exp1, exp2),
+      ExpressionKind::Var { name } => write!(f, "({})", name),
+      ExpressionKind::FunctionCall { name, args } => {
+        write!(f, "CALL {}({})", name, args.iter().join(", "))
+      }
+      ExpressionKind::Conditional {
+        condition,
+        then,
+        otherwise,
+      } => write!(f, "({} ? {} : {})", condition, then, otherwise),
+      ExpressionKind::Cast { target, exp } => write!(f, "({})[{}]", target, exp),
+      ExpressionKind::Subscript { exp, index } => write!(f, "{}[{}]", exp, index),
+      ExpressionKind::Member { exp, member } => write!(f, "{}.{}", exp, member),
+      ExpressionKind::SizeOf(exp) => write!(f, "SIZEOF[{}]", exp),
+      ExpressionKind::SizeOfType(ty) => write!(f, "SIZEOF({})", ty),
     }
   }
 }
 
-pub enum Statement {
+pub struct Statement {
+  pub kind: StatementKind,
+  pub span: Span,
+}
+
+impl Statement {
+  pub fn new(kind: StatementKind, span: Span) -> Self {
+    Self { kind, span }
+  }
+}
+
+pub enum StatementKind {
   Return(Expression),
   Exp(Expression),
-  Declare {
-    name: Rc<String>,
-    exp: Option<Expression>,
+  Declare(VariableDeclaration),
+  DeclareStruct(StructDeclaration),
+  If {
+    condition: Expression,
+    then: Box<Statement>,
+    otherwise: Option<Box<Statement>>,
   },
+  Compound(Vec<Statement>),
+  While {
+    condition: Expression,
+    body: Box<Statement>,
+  },
+  DoWhile {
+    body: Box<Statement>,
+    condition: Expression,
+  },
+  For {
+    /// Either a [`StatementKind::Declare`] or an [`StatementKind::Exp`].
+    init: Option<Box<Statement>>,
+    condition: Option<Expression>,
+    post: Option<Expression>,
+    body: Box<Statement>,
+  },
+  Break,
+  Continue,
+  /// The empty statement `;`.
+  Null,
 }
 
 impl fmt::Display for Statement {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    match self {
-      Statement::Return(val) => write!(f, "RETURN {}", val),
-      Statement::Declare { name, exp } => {
-        if let Some(val) = exp {
-          return write!(f, "INT {} = {}", name, val);
+    match &self.kind {
+      StatementKind::Return(val) => write!(f, "RETURN {}", val),
+      StatementKind::Declare(declaration) => write!(f, "{}", declaration),
+      StatementKind::DeclareStruct(declaration) => write!(f, "{}", declaration),
+      StatementKind::Exp(val) => write!(f, "{}", val),
+      StatementKind::If {
+        condition,
+        then,
+        otherwise,
+      } => {
+        write!(f, "IF {} THEN {}", condition, then)?;
+        if let Some(otherwise) = otherwise {
+          write!(f, " ELSE {}", otherwise)?;
         }
-
-        write!(f, "INT {}", name)
+        Ok(())
+      }
+      StatementKind::Compound(statements) => write!(f, "{{ {} }}", statements.iter().join("; ")),
+      StatementKind::While { condition, body } => write!(f, "WHILE {} DO {}", condition, body),
+      StatementKind::DoWhile { body, condition } => write!(f, "DO {} WHILE {}", body, condition),
+      StatementKind::For {
+        init,
+        condition,
+        post,
+        body,
+      } => {
+        write!(f, "FOR (")?;
+        if let Some(init) = init {
+          write!(f, "{}", init)?;
+        }
+        write!(f, "; ")?;
+        if let Some(condition) = condition {
+          write!(f, "{}", condition)?;
+        }
+        write!(f, "; ")?;
+        if let Some(post) = post {
+          write!(f, "{}", post)?;
+        }
+        write!(f, ") {}", body)
       }
-      Self::Exp(val) => write!(f, "{}", val),
+      StatementKind::Break => write!(f, "BREAK"),
+      StatementKind::Continue => write!(f, "CONTINUE"),
+      StatementKind::Null => write!(f, "NULL"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageClass {
+  Static,
+  Extern,
+}
+
+/// Initialiser of a variable, either a single expression or a brace enclosed
+/// list initialising the elements of an array in order.
+pub enum Initializer {
+  Single(Expression),
+  Compound(Vec<Initializer>, Span),
+}
+
+impl Initializer {
+  pub fn span(&self) -> Span {
+    match self {
+      Initializer::Single(exp) => exp.span,
+      Initializer::Compound(_, span) => *span,
+    }
+  }
+}
+
+impl fmt::Display for Initializer {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Initializer::Single(exp) => write!(f, "{}", exp),
+      Initializer::Compound(initializers, _) => write!(f, "{{{}}}", initializers.iter().join(", ")),
+    }
+  }
+}
+
+pub struct VariableDeclaration {
+  pub name: Rc<String>,
+  pub ty: Type,
+  pub init: Option<Initializer>,
+  pub storage: Option<StorageClass>,
+  pub span: Span,
+}
+
+impl fmt::Display for VariableDeclaration {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(storage) = self.storage {
+      write!(f, "{:?} ", storage)?;
+    }
+    if let Some(val) = &self.init {
+      return write!(f, "{} {} = {}", self.ty, self.name, val);
+    }
+
+    write!(f, "{} {}", self.ty, self.name)
+  }
+}
+
+pub struct Parameter {
+  /// Parameter names may be omitted in prototypes, e.g. `int foo(int);`.
+  pub name: Option<Rc<String>>,
+  pub ty: Type,
+  pub span: Span,
+}
+
+impl fmt::Display for Parameter {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.name {
+      Some(name) => write!(f, "{} {}", self.ty, name),
+      None => write!(f, "{}", self.ty),
     }
   }
 }
 
 pub struct Function {
   pub name: Rc<String>,
-  pub body: Vec<Statement>,
+  pub return_type: Type,
+  pub storage: Option<StorageClass>,
+  pub params: Vec<Parameter>,
+  /// Whether the parameters end with `...`, only allowed in prototypes.
+  pub variadic: bool,
+  /// `None` for a prototype which only declares the function.
+  pub body: Option<Vec<Statement>>,
+  pub span: Span,
 }
 
 impl fmt::Display for Function {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    write!(f, "FUN INT {}:\n  params: ()\n  body:\n", self.name)?;
-    for x in self.body.iter() {
-      write!(f, "    {}\n", x)?;
+    writeln!(
+      f,
+      "FUN {} {}:\n  params: ({}{})",
+      self.return_type,
+      self.name,
+      self.params.iter().join(", "),
+      if self.variadic { ", ..." } else { "" }
+    )?;
+    if let Some(body) = &self.body {
+      writeln!(f, "  body:")?;
+      for x in body.iter() {
+        writeln!(f, "    {}", x)?;
+      }
     }
     Ok(())
   }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StructKind {
+  Struct,
+  Union,
+}
+
+impl StructKind {
+  /// Type named by `tag` declared with this keyword.
+  pub fn with_tag(self, tag: Rc<String>) -> Type {
+    match self {
+      StructKind::Struct => Type::Struct(tag),
+      StructKind::Union => Type::Union(tag),
+    }
+  }
+}
+
+pub struct MemberDeclaration {
+  pub name: Rc<String>,
+  pub ty: Type,
+  pub span: Span,
+}
+
+impl fmt::Display for MemberDeclaration {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} {}", self.ty, self.name)
+  }
+}
+
+/// Declaration of a structure or union tag, `struct point;` or a definition
+/// listing the members like `struct point { int x; int y; };`.
+pub struct StructDeclaration {
+  pub kind: StructKind,
+  pub tag: Rc<String>,
+  /// `None` for a declaration which leaves the type incomplete.
+  pub members: Option<Vec<MemberDeclaration>>,
+  pub span: Span,
+}
+
+impl fmt::Display for StructDeclaration {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.kind.with_tag(self.tag.clone()))?;
+    if let Some(members) = &self.members {
+      write!(f, " {{ {} }}", members.iter().join("; "))?;
+    }
+    Ok(())
+  }
+}
+
+/// Declarations which may appear at file scope of a translation unit.
+pub enum ExternalDeclaration {
+  Function(Function),
+  Variable(VariableDeclaration),
+  Struct(StructDeclaration),
+}
+
+impl fmt::Display for ExternalDeclaration {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ExternalDeclaration::Function(func) => write!(f, "{}", func),
+      ExternalDeclaration::Variable(var) => writeln!(f, "{}", var),
+      ExternalDeclaration::Struct(declaration) => writeln!(f, "{}", declaration),
+    }
+  }
+}
+
 pub struct Program {
-  pub func: Function,
+  pub declarations: Vec<ExternalDeclaration>,
+}
+
+impl Program {
+  /// Functions declared or defined in the program, in source order.
+  pub fn functions(&self) -> impl Iterator<Item = &Function> {
+    self
+      .declarations
+      .iter()
+      .filter_map(|declaration| match declaration {
+        ExternalDeclaration::Function(func) => Some(func),
+        ExternalDeclaration::Variable(_) | ExternalDeclaration::Struct(_) => None,
+      })
+  }
 }
 
 impl fmt::Display for Program {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    write!(f, "{}", self.func)
+    for declaration in self.declarations.iter() {
+      write!(f, "{}", declaration)?;
+    }
+    Ok(())
   }
 }
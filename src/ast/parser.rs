@@ -1,240 +1,1279 @@
 use std::rc::Rc;
 
-use super::model::*;
+use super::{model::*, Type};
 use crate::{
   lex::{BinaryOperator, Token, UnaryOperator},
-  utility::SyntaxError,
+  utility::{Diagnostics, Span, SyntaxError},
 };
 
 fn parse_factor(tokens: &mut Scanner) -> Result<Expression, SyntaxError> {
+  let start = tokens.span();
   let token = tokens.pop("token not found for factor")?;
   match token {
     Token::OpenParenthesis => {
-      let inner_exp = parse_expression(tokens)?;
-      tokens.take(Token::CloseParenthesis, "parenthesis not balanced")?;
-      return Ok(inner_exp);
+      let is_cast = tokens.peek().is_some_and(Token::is_type_specifier);
+      tokens.reset_peek();
+      if is_cast {
+        let target = parse_type_name(tokens)?;
+        tokens
+          .take(Token::CloseParenthesis, "parenthesis not balanced")
+          .map_err(|err| err.with_code("E0201"))?;
+        let inner_exp = parse_factor(tokens)?;
+        return Ok(Expression::new(
+          ExpressionKind::Cast {
+            target,
+            exp: Box::new(inner_exp),
+          },
+          tokens.span_from(start),
+        ));
+      }
+
+      let mut inner_exp = parse_expression(tokens)?;
+      tokens
+        .take(Token::CloseParenthesis, "parenthesis not balanced")
+        .map_err(|err| {
+          err
+            .with_code("E0201")
+            .with_help("add the missing `)`".to_string())
+        })?;
+      inner_exp.span = tokens.span_from(start);
+      parse_postfix(tokens, inner_exp)
     }
-    Token::Negation | Token::BitwiseComplement | Token::LogicalNegation => {
+    Token::Negation
+    | Token::BitwiseComplement
+    | Token::LogicalNegation
+    | Token::Ampersand
+    | Token::Multiplication => {
       let op = UnaryOperator::try_from(token.clone())?;
       let inner_exp = parse_factor(tokens)?;
-      return Ok(Expression::Unary {
-        op: op,
-        exp: Box::new(inner_exp),
-      });
+      Ok(Expression::new(
+        ExpressionKind::Unary {
+          op,
+          exp: Box::new(inner_exp),
+        },
+        tokens.span_from(start),
+      ))
+    }
+    Token::Increment | Token::Decrement => {
+      let target = parse_factor(tokens)?;
+      let span = tokens.span_from(start);
+      Ok(increment(token, target, false, span))
+    }
+    Token::Sizeof => parse_sizeof(tokens, start),
+    Token::Identifier(val) => {
+      let exp = if let Some(Token::OpenParenthesis) = tokens.peek() {
+        let args = parse_call_arguments(tokens)?;
+        Expression::new(
+          ExpressionKind::FunctionCall {
+            name: Rc::clone(val),
+            args,
+          },
+          tokens.span_from(start),
+        )
+      } else {
+        tokens.reset_peek();
+        Expression::new(
+          ExpressionKind::Var {
+            name: Rc::clone(val),
+          },
+          start,
+        )
+      };
+      parse_postfix(tokens, exp)
+    }
+    Token::Integer(val, kind) => {
+      let exp = Expression::new(ExpressionKind::Const(*val, Type::from(*kind)), start);
+      parse_postfix(tokens, exp)
+    }
+    Token::DoubleLiteral(val) => {
+      let exp = Expression::new(ExpressionKind::ConstFloat(*val, Type::Double), start);
+      parse_postfix(tokens, exp)
+    }
+    Token::FloatLiteral(val) => {
+      let exp = Expression::new(ExpressionKind::ConstFloat(*val, Type::Float), start);
+      parse_postfix(tokens, exp)
+    }
+    Token::CharLiteral(val) => {
+      let exp = Expression::new(ExpressionKind::Const(*val, Type::Int), start);
+      parse_postfix(tokens, exp)
+    }
+    Token::StringLiteral(val) => {
+      // adjacent string literals are concatenated
+      let mut value = val.to_vec();
+      while let Some(Token::StringLiteral(next)) = tokens.peek() {
+        value.extend_from_slice(next);
+        tokens.pop("")?;
+      }
+      tokens.reset_peek();
+      let exp = Expression::new(
+        ExpressionKind::String(Rc::new(value)),
+        tokens.span_from(start),
+      );
+      parse_postfix(tokens, exp)
+    }
+    _ => {
+      // leave the token for error recovery to synchronise on
+      tokens.backtrack();
+      Err(SyntaxError::new_parse_error("invalid tokens for factor".to_string()).with_span(start))
     }
-    Token::Identifier(val) => Ok(Expression::Var {
-      name: Rc::clone(val),
-    }),
-    Token::Integer(val) => Ok(Expression::Const(*val)),
-    _ => Err(SyntaxError::new_parse_error("invalid tokens for factor".to_string())),
   }
 }
 
-fn parse_sub_function(
-  tokens: &mut Scanner,
-  sub_exp: fn(&mut Scanner) -> Result<Expression, SyntaxError>,
-  operators: &[BinaryOperator],
-) -> Result<Expression, SyntaxError> {
-  let mut exp = sub_exp(tokens)?;
+/// `++target` or `--target` as the compound assignment it is short for,
+/// `target++` and `target--` if `postfix`.
+fn increment(token: &Token, target: Expression, postfix: bool, span: Span) -> Expression {
+  let op = match token {
+    Token::Increment => BinaryOperator::Addition,
+    _ => BinaryOperator::Minus,
+  };
+  Expression::new(
+    ExpressionKind::CompoundAssign {
+      op,
+      target: Box::new(target),
+      exp: Box::new(Expression::new(ExpressionKind::Const(1, Type::Int), span)),
+      postfix,
+      operation_type: None,
+    },
+    span,
+  )
+}
 
-  while let Some(val) = tokens.peek() {
-    if let Ok(bin_op) = BinaryOperator::try_from(val.clone()) {
-      if operators.contains(&bin_op) {
-        // not expecting error here
-        tokens.pop("").unwrap();
-        let next_exp = sub_exp(tokens)?;
-        exp = Expression::Binary {
-          exp1: Box::new(exp),
-          op: bin_op,
-          exp2: Box::new(next_exp),
+/// Parse the `[ <exp> ]` subscripts, `.` or `->` member accesses and `++` or
+/// `--` following `exp`, which bind tighter than any unary operator.
+fn parse_postfix(tokens: &mut Scanner, mut exp: Expression) -> Result<Expression, SyntaxError> {
+  loop {
+    let start = exp.span;
+    let kind = match tokens.peek() {
+      Some(Token::OpenBracket) => {
+        tokens.pop("")?;
+        let index = parse_expression(tokens)?;
+        tokens
+          .take(Token::CloseBracket, "bracket not balanced")
+          .map_err(|err| {
+            err
+              .with_code("E0201")
+              .with_help("add the missing `]`".to_string())
+          })?;
+        ExpressionKind::Subscript {
+          exp: Box::new(exp),
+          index: Box::new(index),
         }
-      } else {
-        break;
       }
-    } else {
-      break;
-    }
+      Some(Token::Dot) => {
+        tokens.pop("")?;
+        ExpressionKind::Member {
+          exp: Box::new(exp),
+          member: parse_member_name(tokens)?,
+        }
+      }
+      Some(Token::Arrow) => {
+        tokens.pop("")?;
+        // `p->member` is the member of the structure `p` points to
+        let pointee = Expression::new(
+          ExpressionKind::Unary {
+            op: UnaryOperator::Dereference,
+            exp: Box::new(exp),
+          },
+          start,
+        );
+        ExpressionKind::Member {
+          exp: Box::new(pointee),
+          member: parse_member_name(tokens)?,
+        }
+      }
+      Some(token @ (Token::Increment | Token::Decrement)) => {
+        tokens.pop("")?;
+        exp = increment(token, exp, true, tokens.span_from(start));
+        continue;
+      }
+      _ => break,
+    };
+    exp = Expression::new(kind, tokens.span_from(start));
   }
   tokens.reset_peek();
 
   Ok(exp)
 }
 
-fn parse_term(tokens: &mut Scanner) -> Result<Expression, SyntaxError> {
-  parse_sub_function(
-    tokens,
-    parse_factor,
-    &[BinaryOperator::Multiplication, BinaryOperator::Division],
-  )
-}
-
-fn parse_additive_expression(tokens: &mut Scanner) -> Result<Expression, SyntaxError> {
-  parse_sub_function(
-    tokens,
-    parse_term,
-    &[BinaryOperator::Addition, BinaryOperator::Minus],
-  )
+/// Parse the name of a member following `.` or `->`.
+fn parse_member_name(tokens: &mut Scanner) -> Result<Rc<String>, SyntaxError> {
+  let span = tokens.span();
+  match tokens.pop("expected a member name")? {
+    Token::Identifier(name) => Ok(Rc::clone(name)),
+    _ => {
+      tokens.backtrack();
+      Err(SyntaxError::new_parse_error("expected a member name".to_string()).with_span(span))
+    }
+  }
 }
 
-fn parse_relational_expression(tokens: &mut Scanner) -> Result<Expression, SyntaxError> {
-  parse_sub_function(
-    tokens,
-    parse_additive_expression,
-    &[
-      BinaryOperator::LessThan,
-      BinaryOperator::LessThanOrEqual,
-      BinaryOperator::GreaterThan,
-      BinaryOperator::GreaterThanOrEqual,
-    ],
-  )
-}
+/// Parse the operand of `sizeof` following the keyword, either a
+/// parenthesised type name or an expression.
+fn parse_sizeof(tokens: &mut Scanner, start: Span) -> Result<Expression, SyntaxError> {
+  let is_type = matches!(tokens.peek(), Some(Token::OpenParenthesis))
+    && tokens.peek().is_some_and(Token::is_type_specifier);
+  tokens.reset_peek();
+  if !is_type {
+    let exp = parse_factor(tokens)?;
+    return Ok(Expression::new(
+      ExpressionKind::SizeOf(Box::new(exp)),
+      tokens.span_from(start),
+    ));
+  }
 
-fn parse_equality_expression(tokens: &mut Scanner) -> Result<Expression, SyntaxError> {
-  parse_sub_function(
-    tokens,
-    parse_relational_expression,
-    &[BinaryOperator::Equal, BinaryOperator::NotEqual],
-  )
+  tokens.pop("")?;
+  let ty = parse_type_name(tokens)?;
+  let ty = parse_array_dimensions(tokens, ty)?;
+  tokens
+    .take(Token::CloseParenthesis, "parenthesis not balanced")
+    .map_err(|err| err.with_code("E0201"))?;
+  Ok(Expression::new(
+    ExpressionKind::SizeOfType(ty),
+    tokens.span_from(start),
+  ))
 }
 
-fn parse_logical_and_expression(tokens: &mut Scanner) -> Result<Expression, SyntaxError> {
-  parse_sub_function(tokens, parse_equality_expression, &[BinaryOperator::And])
-}
+/// Parse a parenthesised, comma separated argument list of a function call.
+fn parse_call_arguments(tokens: &mut Scanner) -> Result<Vec<Expression>, SyntaxError> {
+  tokens.take(
+    Token::OpenParenthesis,
+    "invalid token, type should be OpenParenthesis",
+  )?;
 
-fn parse_expression(tokens: &mut Scanner) -> Result<Expression, SyntaxError> {
-  if let Some(val) = tokens.peek() {
-    if let Token::Identifier(id) = val {
-      let identifier = Rc::clone(id);
+  let mut args = vec![];
+  if let Some(Token::CloseParenthesis) = tokens.peek() {
+    tokens.pop("")?;
+    return Ok(args);
+  }
+  tokens.reset_peek();
 
-      if let Some(Token::Assignment) = tokens.peek() {
-        // only pop identifier if assignment token found
-        tokens.pop("")?;
-        tokens.take(
-          Token::Assignment,
-          "expecting an assignment operator over here",
-        )?;
-
-        let inner_exp = parse_expression(tokens)?;
-        return Ok(Expression::Assign {
-          name: identifier,
-          exp: Box::new(inner_exp),
-        });
+  loop {
+    args.push(parse_assignment_expression(tokens)?);
+    let span = tokens.span();
+    match tokens.pop("expected `,` or `)` after function argument")? {
+      Token::Comma => continue,
+      Token::CloseParenthesis => break,
+      _ => {
+        tokens.backtrack();
+        return Err(
+          SyntaxError::new_parse_error("expected `,` or `)` after function argument".to_string())
+            .with_span(span)
+            .with_code("E0201"),
+        );
       }
     }
+  }
+
+  Ok(args)
+}
+
+/// Precedence levels of the binary, conditional, assignment and comma
+/// operators from loosest to tightest, operators on the same level share an
+/// entry.
+const PRECEDENCE: &[&[Token]] = &[
+  &[Token::Comma],
+  &[Token::Assignment],
+  &[Token::QuestionMark],
+  &[Token::Or],
+  &[Token::And],
+  &[Token::Pipe],
+  &[Token::Caret],
+  &[Token::Ampersand],
+  &[Token::Equal, Token::NotEqual],
+  &[
+    Token::LessThan,
+    Token::LessThanOrEqual,
+    Token::GreaterThan,
+    Token::GreaterThanOrEqual,
+  ],
+  &[Token::ShiftLeft, Token::ShiftRight],
+  &[Token::Addition, Token::Negation],
+  &[Token::Multiplication, Token::Division, Token::Remainder],
+];
+
+/// Level of an assignment in [`PRECEDENCE`], the loosest an operand of a
+/// function call or an initialiser may be as those are comma separated.
+const ASSIGNMENT_PRECEDENCE: usize = 1;
 
+/// Index of the level of `token` in [`PRECEDENCE`], `None` when it does not
+/// continue an expression. Compound assignments share the level of `=`.
+fn precedence(token: &Token) -> Option<usize> {
+  if let Token::CompoundAssignment(_) = token {
+    return Some(ASSIGNMENT_PRECEDENCE);
+  }
+  PRECEDENCE.iter().position(|level| level.contains(token))
+}
+
+/// Parse an expression whose operators all bind at least as tight as the
+/// level `min_precedence`. Operators are left associative, assignments and
+/// conditionals right associative by parsing their right operand at their
+/// own level instead of the next one.
+fn parse_binary_expression(
+  tokens: &mut Scanner,
+  min_precedence: usize,
+) -> Result<Expression, SyntaxError> {
+  let start = tokens.span();
+  if tokens.peek().is_none() {
+    return Err(
+      SyntaxError::new_parse_error("expected tokens for expression".to_string()).with_span(start),
+    );
+  }
+  tokens.reset_peek();
+
+  let mut exp = parse_factor(tokens)?;
+  while let Some(token) = tokens.peek() {
     tokens.reset_peek();
-    return parse_sub_function(tokens, parse_logical_and_expression, &[BinaryOperator::Or]);
+    let Some(level) = precedence(token).filter(|level| *level >= min_precedence) else {
+      break;
+    };
+    tokens.pop("")?;
+
+    // whether the target of an assignment is an lvalue is left to the
+    // semantic pass
+    let kind = match token {
+      Token::Assignment => ExpressionKind::Assign {
+        target: Box::new(exp),
+        exp: Box::new(parse_binary_expression(tokens, level)?),
+      },
+      Token::CompoundAssignment(op) => ExpressionKind::CompoundAssign {
+        op: *op,
+        target: Box::new(exp),
+        exp: Box::new(parse_binary_expression(tokens, level)?),
+        postfix: false,
+        operation_type: None,
+      },
+      Token::QuestionMark => {
+        // anything may go between `?` and `:`, as if parenthesised
+        let then = parse_expression(tokens)?;
+        tokens.take(Token::Colon, "expecting `:` in conditional expression")?;
+        ExpressionKind::Conditional {
+          condition: Box::new(exp),
+          then: Box::new(then),
+          otherwise: Box::new(parse_binary_expression(tokens, level)?),
+        }
+      }
+      Token::Comma => ExpressionKind::Comma {
+        exp1: Box::new(exp),
+        exp2: Box::new(parse_binary_expression(tokens, level + 1)?),
+      },
+      _ => ExpressionKind::Binary {
+        exp1: Box::new(exp),
+        op: BinaryOperator::try_from(token.clone())?,
+        exp2: Box::new(parse_binary_expression(tokens, level + 1)?),
+      },
+    };
+    exp = Expression::new(kind, tokens.span_from(start));
   }
 
-  Err(SyntaxError::new_parse_error("expected tokens for expression".to_string()))
+  Ok(exp)
+}
+
+/// Parse a full expression, which may contain the comma operator.
+fn parse_expression(tokens: &mut Scanner) -> Result<Expression, SyntaxError> {
+  parse_binary_expression(tokens, 0)
+}
+
+/// Parse an assignment, the operands of a function call and initialisers
+/// which are separated by commas themselves.
+fn parse_assignment_expression(tokens: &mut Scanner) -> Result<Expression, SyntaxError> {
+  parse_binary_expression(tokens, ASSIGNMENT_PRECEDENCE)
 }
 
 fn parse_return_statement(tokens: &mut Scanner) -> Result<Statement, SyntaxError> {
+  let start = tokens.span();
   tokens.take(Token::Return, "expected return token")?;
   let expression = parse_expression(tokens)?;
   tokens.take(Token::SemiColon, "invalid token, type should be SemiColon")?;
-  Ok(Statement::Return(expression))
+  Ok(Statement::new(
+    StatementKind::Return(expression),
+    tokens.span_from(start),
+  ))
 }
 
-fn parse_int_declaration_statement(tokens: &mut Scanner) -> Result<Statement, SyntaxError> {
-  tokens.take(Token::Int, "expected int token")?;
-  let token = tokens.pop("token not found")?;
+/// Type named by a list of type specifiers, `None` when they do not form a
+/// valid type, e.g. `short long`.
+fn specifier_type(specifiers: &[&Token]) -> Option<Type> {
+  let count = |token: Token| specifiers.iter().filter(|&&val| *val == token).count();
+  let (char, short, int, long) = (
+    count(Token::Char),
+    count(Token::Short),
+    count(Token::Int),
+    count(Token::Long),
+  );
+  let (signed, unsigned) = (count(Token::Signed), count(Token::Unsigned));
+  match (count(Token::Float), count(Token::Double)) {
+    (0, 0) => {}
+    (1, 0) if specifiers.len() == 1 => return Some(Type::Float),
+    (0, 1) if specifiers.len() == 1 => return Some(Type::Double),
+    _ => return None,
+  }
 
-  if let Token::Identifier(identifier_name) = token {
-    let exp = if let Some(Token::Assignment) = tokens.peek() {
-      tokens.take(Token::Assignment, "")?;
-      Some(parse_expression(tokens)?)
-    } else {
-      None
+  let widths = char + short + long.min(1);
+  if specifiers.is_empty() || widths > 1 || signed + unsigned > 1 {
+    return None;
+  }
+  if char + short + int > 1 || long > 2 || (char == 1 && int == 1) {
+    return None;
+  }
+
+  let unsigned = unsigned == 1;
+  Some(match (char, short, long) {
+    (1, _, _) if unsigned => Type::UChar,
+    (1, _, _) => Type::Char,
+    (_, 1, _) if unsigned => Type::UShort,
+    (_, 1, _) => Type::Short,
+    (_, _, 1..) if unsigned => Type::ULong,
+    (_, _, 1..) => Type::Long,
+    _ if unsigned => Type::UInt,
+    _ => Type::Int,
+  })
+}
+
+/// Parse the tag following `struct` or `union`.
+fn parse_tag(tokens: &mut Scanner) -> Result<Rc<String>, SyntaxError> {
+  let span = tokens.span();
+  match tokens.pop("expected a tag")? {
+    Token::Identifier(tag) => Ok(Rc::clone(tag)),
+    _ => {
+      tokens.backtrack();
+      Err(
+        SyntaxError::new_parse_error("expected a tag after `struct` or `union`".to_string())
+          .with_span(span),
+      )
+    }
+  }
+}
+
+/// Parse the specifiers starting a declaration, type specifiers such as
+/// `unsigned long` in any order or a `struct <tag>` along with an optional
+/// storage class.
+fn parse_specifiers(tokens: &mut Scanner) -> Result<(Type, Option<StorageClass>), SyntaxError> {
+  let start = tokens.span();
+  let mut specifiers = vec![];
+  let mut aggregates = vec![];
+  let mut storage = None;
+  while let Some(token) = tokens.peek() {
+    if !token.is_specifier() {
+      break;
+    }
+    let span = tokens.span();
+    tokens.pop("")?;
+    let class = match token {
+      Token::Static => StorageClass::Static,
+      Token::Extern => StorageClass::Extern,
+      Token::Struct => {
+        aggregates.push(Type::Struct(parse_tag(tokens)?));
+        continue;
+      }
+      Token::Union => {
+        aggregates.push(Type::Union(parse_tag(tokens)?));
+        continue;
+      }
+      _ => {
+        specifiers.push(token);
+        continue;
+      }
     };
+    if storage.is_some() {
+      return Err(
+        SyntaxError::new_parse_error("multiple storage classes in declaration".to_string())
+          .with_span(span),
+      );
+    }
+    storage = Some(class);
+  }
+  tokens.reset_peek();
 
-    tokens.take(
-      Token::SemiColon,
-      "expecting a semicolon at end of declaration",
-    )?;
+  match (
+    aggregates.pop(),
+    specifiers.is_empty() && aggregates.is_empty(),
+  ) {
+    (Some(ty), true) => return Ok((ty, storage)),
+    (Some(_), false) => {
+      return Err(
+        SyntaxError::new_parse_error("invalid combination of type specifiers".to_string())
+          .with_span(tokens.span_from(start)),
+      )
+    }
+    (None, _) => {}
+  }
+  if specifiers.is_empty() {
+    return Err(
+      SyntaxError::new_parse_error("expected a type specifier".to_string())
+        .with_span(tokens.span()),
+    );
+  }
+  let ty = specifier_type(&specifiers).ok_or_else(|| {
+    SyntaxError::new_parse_error("invalid combination of type specifiers".to_string())
+      .with_span(tokens.span_from(start))
+  })?;
+  Ok((ty, storage))
+}
 
-    return Ok(Statement::Declare {
-      name: Rc::clone(identifier_name),
-      exp: exp,
-    });
+/// Parse the `*`s of a pointer declarator, each deriving a pointer to the
+/// type before it.
+fn parse_pointers(tokens: &mut Scanner, mut ty: Type) -> Result<Type, SyntaxError> {
+  while let Some(Token::Multiplication) = tokens.peek() {
+    tokens.pop("")?;
+    ty = Type::Pointer(Box::new(ty));
   }
+  tokens.reset_peek();
+  Ok(ty)
+}
 
-  Err(SyntaxError::new_parse_error(
-    "expected a identifier".to_string(),
-  ))
+/// Parse the `[ <int> ]` suffixes of an array declarator, the leftmost
+/// giving the number of elements of the outermost array.
+fn parse_array_dimensions(tokens: &mut Scanner, ty: Type) -> Result<Type, SyntaxError> {
+  let mut dimensions = vec![];
+  while let Some(Token::OpenBracket) = tokens.peek() {
+    tokens.pop("")?;
+    let span = tokens.span();
+    match tokens.pop("expected the size of the array")? {
+      Token::Integer(len, _) if *len > 0 => dimensions.push(*len as usize),
+      _ => {
+        tokens.backtrack();
+        return Err(
+          SyntaxError::new_parse_error(
+            "array size must be a positive integer constant".to_string(),
+          )
+          .with_span(span),
+        );
+      }
+    }
+    tokens
+      .take(Token::CloseBracket, "bracket not balanced")
+      .map_err(|err| err.with_code("E0201"))?;
+  }
+  tokens.reset_peek();
+
+  Ok(
+    dimensions
+      .into_iter()
+      .rev()
+      .fold(ty, |ty, len| Type::Array(Box::new(ty), len)),
+  )
 }
 
-fn parse_statement(tokens: &mut Scanner) -> Result<Statement, SyntaxError> {
-  if let Some(val) = tokens.peek() {
-    return match val {
-      Token::Return => parse_return_statement(tokens),
-      Token::Int => parse_int_declaration_statement(tokens),
+/// Parse the type of a cast or parameter, e.g. `unsigned long *`, where
+/// storage classes are not allowed.
+fn parse_type_name(tokens: &mut Scanner) -> Result<Type, SyntaxError> {
+  let start = tokens.span();
+  match parse_specifiers(tokens)? {
+    (ty, None) => parse_pointers(tokens, ty),
+    (_, Some(_)) => Err(
+      SyntaxError::new_parse_error("storage class not allowed here".to_string())
+        .with_span(tokens.span_from(start)),
+    ),
+  }
+}
+
+/// Parse an initialiser, `<exp>` or a brace enclosed list of initialisers
+/// which may end with a comma.
+fn parse_initializer(tokens: &mut Scanner) -> Result<Initializer, SyntaxError> {
+  let start = tokens.span();
+  if tokens.peek() != Some(&Token::OpenBrace) {
+    tokens.reset_peek();
+    return Ok(Initializer::Single(parse_assignment_expression(tokens)?));
+  }
+  tokens.pop("")?;
+
+  let mut initializers = vec![];
+  loop {
+    if let Some(Token::CloseBrace) = tokens.peek() {
+      tokens.pop("")?;
+      break;
+    }
+    tokens.reset_peek();
+    initializers.push(parse_initializer(tokens)?);
+
+    let span = tokens.span();
+    match tokens.pop("expected `,` or `}` after initialiser")? {
+      Token::Comma => continue,
+      Token::CloseBrace => break,
       _ => {
-        tokens.reset_peek();
-        let exp = parse_expression(tokens)?;
-        tokens.take(Token::SemiColon, "expecting a semi colon")?;
-        Ok(Statement::Exp(exp))
+        tokens.backtrack();
+        return Err(
+          SyntaxError::new_parse_error("expected `,` or `}` after initialiser".to_string())
+            .with_span(span),
+        );
       }
     }
   }
+  if initializers.is_empty() {
+    return Err(
+      SyntaxError::new_parse_error("empty initialiser list".to_string())
+        .with_span(tokens.span_from(start)),
+    );
+  }
 
-  Err(SyntaxError::new_parse_error(
-    "expected tokens for statement".to_string(),
-  ))
+  Ok(Initializer::Compound(initializers, tokens.span_from(start)))
+}
+
+/// Parse the part of a variable declaration following its name,
+/// `[ "[" "]" ] { "[" <int> "]" } [ "=" <initializer> ] ";"`.
+fn parse_variable_declaration_rest(
+  tokens: &mut Scanner,
+  start: Span,
+  name: Rc<String>,
+  ty: Type,
+  storage: Option<StorageClass>,
+) -> Result<VariableDeclaration, SyntaxError> {
+  // the initialiser gives the length of an array whose first dimension is empty
+  let is_unsized = matches!(
+    (tokens.peek(), tokens.peek()),
+    (Some(Token::OpenBracket), Some(Token::CloseBracket))
+  );
+  tokens.reset_peek();
+  if is_unsized {
+    tokens.pop("")?;
+    tokens.pop("")?;
+  }
+  let ty = match parse_array_dimensions(tokens, ty)? {
+    ty if is_unsized => Type::Array(Box::new(ty), 0),
+    ty => ty,
+  };
+  let init = if let Some(Token::Assignment) = tokens.peek() {
+    tokens.take(Token::Assignment, "")?;
+    Some(parse_initializer(tokens)?)
+  } else {
+    None
+  };
+
+  tokens.take(
+    Token::SemiColon,
+    "expecting a semicolon at end of declaration",
+  )?;
+
+  Ok(VariableDeclaration {
+    name,
+    ty,
+    init,
+    storage,
+    span: tokens.span_from(start),
+  })
 }
 
-fn parse_function(tokens: &mut Scanner) -> Result<Function, SyntaxError> {
-  // int
-  tokens.take(Token::Int, "invalid token, type should be Int")?;
+/// Whether the next tokens declare a structure or union tag rather than an
+/// object of that type, i.e. `struct <tag>` is followed by `{` or `;`.
+fn is_struct_declaration(tokens: &mut Scanner) -> bool {
+  let result = matches!(tokens.peek(), Some(Token::Struct | Token::Union))
+    && matches!(tokens.peek(), Some(Token::Identifier(_)))
+    && matches!(tokens.peek(), Some(Token::OpenBrace | Token::SemiColon));
+  tokens.reset_peek();
+  result
+}
 
-  // identifier
+/// Parse `struct <tag> [ "{" { <type-name> <id> { "[" <int> "]" } ";" } "}" ] ";"`
+/// or the same with `union`.
+fn parse_struct_declaration(tokens: &mut Scanner) -> Result<StructDeclaration, SyntaxError> {
+  let start = tokens.span();
+  let kind = match tokens.pop("expected `struct` or `union`")? {
+    Token::Union => StructKind::Union,
+    _ => StructKind::Struct,
+  };
+  let tag = parse_tag(tokens)?;
+
+  let members = if let Some(Token::OpenBrace) = tokens.peek() {
+    tokens.pop("")?;
+    let mut members = vec![];
+    while tokens.peek() != Some(&Token::CloseBrace) {
+      tokens.reset_peek();
+      let member_start = tokens.span();
+      let ty = parse_type_name(tokens)?;
+      let name = parse_member_name(tokens)?;
+      let ty = parse_array_dimensions(tokens, ty)?;
+      tokens
+        .take(Token::SemiColon, "expecting a semicolon after member")
+        .map_err(|err| err.with_code("E0202"))?;
+      members.push(MemberDeclaration {
+        name,
+        ty,
+        span: tokens.span_from(member_start),
+      });
+    }
+    tokens.pop("")?;
+    if members.is_empty() {
+      return Err(
+        SyntaxError::new_parse_error(format!("{} has no members", kind.with_tag(tag)))
+          .with_span(tokens.span_from(start)),
+      );
+    }
+    Some(members)
+  } else {
+    tokens.reset_peek();
+    None
+  };
+
+  tokens
+    .take(
+      Token::SemiColon,
+      "expecting a semicolon at end of declaration",
+    )
+    .map_err(|err| {
+      err
+        .with_code("E0202")
+        .with_help("declare variables of the type in a declaration of their own".to_string())
+    })?;
+  Ok(StructDeclaration {
+    kind,
+    tag,
+    members,
+    span: tokens.span_from(start),
+  })
+}
+
+fn parse_declaration_statement(tokens: &mut Scanner) -> Result<Statement, SyntaxError> {
+  let start = tokens.span();
+  if is_struct_declaration(tokens) {
+    let declaration = parse_struct_declaration(tokens)?;
+    return Ok(Statement::new(
+      StatementKind::DeclareStruct(declaration),
+      tokens.span_from(start),
+    ));
+  }
+  let (ty, storage) = parse_specifiers(tokens)?;
+  let ty = parse_pointers(tokens, ty)?;
+  let identifier_span = tokens.span();
   let token = tokens.pop("token not found")?;
-  let func_name = if let Token::Identifier(val) = token {
-    Rc::clone(val)
+
+  if let Token::Identifier(identifier_name) = token {
+    let declaration =
+      parse_variable_declaration_rest(tokens, start, Rc::clone(identifier_name), ty, storage)?;
+    return Ok(Statement::new(
+      StatementKind::Declare(declaration),
+      tokens.span_from(start),
+    ));
+  }
+
+  tokens.backtrack();
+  Err(SyntaxError::new_parse_error("expected a identifier".to_string()).with_span(identifier_span))
+}
+
+fn parse_if_statement(
+  tokens: &mut Scanner,
+  diagnostics: &mut Diagnostics,
+) -> Result<Statement, SyntaxError> {
+  let start = tokens.span();
+  tokens.take(Token::If, "expected if token")?;
+  tokens.take(
+    Token::OpenParenthesis,
+    "invalid token, type should be OpenParenthesis",
+  )?;
+  let condition = parse_expression(tokens)?;
+  tokens
+    .take(Token::CloseParenthesis, "parenthesis not balanced")
+    .map_err(|err| err.with_code("E0201"))?;
+  let then = parse_statement(tokens, diagnostics)?;
+
+  // an else always binds to the closest if
+  let otherwise = if let Some(Token::Else) = tokens.peek() {
+    tokens.pop("")?;
+    Some(Box::new(parse_statement(tokens, diagnostics)?))
   } else {
-    return Err(SyntaxError::new_parse_error(
-    "invalid token, type should be Identifier".to_string(),
-    ))
+    tokens.reset_peek();
+    None
   };
 
-  // open parenthesis
+  Ok(Statement::new(
+    StatementKind::If {
+      condition,
+      then: Box::new(then),
+      otherwise,
+    },
+    tokens.span_from(start),
+  ))
+}
+
+/// Parse an optional expression followed by `terminator`, as found in the
+/// clauses of a `for` statement.
+fn parse_optional_expression(
+  tokens: &mut Scanner,
+  terminator: Token,
+  error_message: &str,
+) -> Result<Option<Expression>, SyntaxError> {
+  if tokens.peek() == Some(&terminator) {
+    tokens.pop("")?;
+    return Ok(None);
+  }
+  tokens.reset_peek();
+
+  let exp = parse_expression(tokens)?;
+  tokens.take(terminator, error_message)?;
+  Ok(Some(exp))
+}
+
+fn parse_while_statement(
+  tokens: &mut Scanner,
+  diagnostics: &mut Diagnostics,
+) -> Result<Statement, SyntaxError> {
+  let start = tokens.span();
+  tokens.take(Token::While, "expected while token")?;
   tokens.take(
     Token::OpenParenthesis,
     "invalid token, type should be OpenParenthesis",
   )?;
+  let condition = parse_expression(tokens)?;
+  tokens
+    .take(Token::CloseParenthesis, "parenthesis not balanced")
+    .map_err(|err| err.with_code("E0201"))?;
+  let body = parse_statement(tokens, diagnostics)?;
 
-  // close parenthesis
+  Ok(Statement::new(
+    StatementKind::While {
+      condition,
+      body: Box::new(body),
+    },
+    tokens.span_from(start),
+  ))
+}
+
+fn parse_do_while_statement(
+  tokens: &mut Scanner,
+  diagnostics: &mut Diagnostics,
+) -> Result<Statement, SyntaxError> {
+  let start = tokens.span();
+  tokens.take(Token::Do, "expected do token")?;
+  let body = parse_statement(tokens, diagnostics)?;
+  tokens.take(Token::While, "expecting while after do body")?;
   tokens.take(
-    Token::CloseParenthesis,
-    "invalid token, type should be CloseParenthesis",
+    Token::OpenParenthesis,
+    "invalid token, type should be OpenParenthesis",
   )?;
+  let condition = parse_expression(tokens)?;
+  tokens
+    .take(Token::CloseParenthesis, "parenthesis not balanced")
+    .map_err(|err| err.with_code("E0201"))?;
+  tokens.take(Token::SemiColon, "expecting a semicolon after do while")?;
 
-  // open braces
+  Ok(Statement::new(
+    StatementKind::DoWhile {
+      body: Box::new(body),
+      condition,
+    },
+    tokens.span_from(start),
+  ))
+}
+
+fn parse_for_statement(
+  tokens: &mut Scanner,
+  diagnostics: &mut Diagnostics,
+) -> Result<Statement, SyntaxError> {
+  let start = tokens.span();
+  tokens.take(Token::For, "expected for token")?;
+  tokens.take(
+    Token::OpenParenthesis,
+    "invalid token, type should be OpenParenthesis",
+  )?;
+
+  let init_start = tokens.span();
+  let init = if tokens.peek().is_some_and(Token::is_specifier) {
+    tokens.reset_peek();
+    let init = parse_declaration_statement(tokens)?;
+    if let StatementKind::Declare(VariableDeclaration {
+      storage: Some(_), ..
+    }) = init.kind
+    {
+      return Err(
+        SyntaxError::new_parse_error(
+          "storage class not allowed in a for loop initialiser".to_string(),
+        )
+        .with_span(init.span),
+      );
+    }
+    Some(Box::new(init))
+  } else {
+    tokens.reset_peek();
+    parse_optional_expression(
+      tokens,
+      Token::SemiColon,
+      "expecting a semicolon after for init",
+    )?
+    .map(|exp| {
+      Box::new(Statement::new(
+        StatementKind::Exp(exp),
+        tokens.span_from(init_start),
+      ))
+    })
+  };
+  let condition = parse_optional_expression(
+    tokens,
+    Token::SemiColon,
+    "expecting a semicolon after for condition",
+  )?;
+  let post =
+    parse_optional_expression(tokens, Token::CloseParenthesis, "parenthesis not balanced")?;
+  let body = parse_statement(tokens, diagnostics)?;
+
+  Ok(Statement::new(
+    StatementKind::For {
+      init,
+      condition,
+      post,
+      body: Box::new(body),
+    },
+    tokens.span_from(start),
+  ))
+}
+
+/// Parse an item of a block, either a declaration or a statement.
+fn parse_block_item(
+  tokens: &mut Scanner,
+  diagnostics: &mut Diagnostics,
+) -> Result<Statement, SyntaxError> {
+  if tokens.peek().is_some_and(Token::is_specifier) {
+    tokens.reset_peek();
+    return parse_declaration_statement(tokens);
+  }
+  tokens.reset_peek();
+
+  parse_statement(tokens, diagnostics)
+}
+
+/// Parse `{ { <block-item> } }`, errors inside the block are recorded in
+/// `diagnostics` and parsing resumes with the next block item.
+fn parse_block(
+  tokens: &mut Scanner,
+  diagnostics: &mut Diagnostics,
+) -> Result<Vec<Statement>, SyntaxError> {
   tokens.take(Token::OpenBrace, "invalid token, type should be OpenBrace")?;
 
   let mut statements: Vec<Statement> = vec![];
   while let Some(val) = tokens.peek() {
-    if *val != Token::CloseBrace {
-      tokens.reset_peek();
-      let func_body = parse_statement(tokens)?;
-      statements.push(func_body);
+    if *val == Token::CloseBrace || diagnostics.is_full() {
+      break;
+    }
+    tokens.reset_peek();
+    match parse_block_item(tokens, diagnostics) {
+      Ok(statement) => statements.push(statement),
+      Err(err) => {
+        diagnostics.push(err);
+        synchronize_statement(tokens);
+      }
     }
   }
+  tokens.reset_peek();
 
-  // close braces
   tokens.take(
     Token::CloseBrace,
     "invalid token, type should be CloseBrace",
   )?;
 
+  Ok(statements)
+}
+
+fn parse_statement(
+  tokens: &mut Scanner,
+  diagnostics: &mut Diagnostics,
+) -> Result<Statement, SyntaxError> {
+  let start = tokens.span();
+  if let Some(val) = tokens.peek() {
+    return match val {
+      Token::Return => parse_return_statement(tokens),
+      Token::If => parse_if_statement(tokens, diagnostics),
+      Token::While => parse_while_statement(tokens, diagnostics),
+      Token::Do => parse_do_while_statement(tokens, diagnostics),
+      Token::For => parse_for_statement(tokens, diagnostics),
+      Token::Break | Token::Continue => {
+        let kind = match tokens.pop("")? {
+          Token::Break => StatementKind::Break,
+          _ => StatementKind::Continue,
+        };
+        tokens
+          .take(Token::SemiColon, "expecting a semi colon")
+          .map_err(|err| err.with_code("E0202"))?;
+        Ok(Statement::new(kind, tokens.span_from(start)))
+      }
+      Token::SemiColon => {
+        tokens.pop("")?;
+        Ok(Statement::new(StatementKind::Null, start))
+      }
+      Token::OpenBrace => {
+        let statements = parse_block(tokens, diagnostics)?;
+        Ok(Statement::new(
+          StatementKind::Compound(statements),
+          tokens.span_from(start),
+        ))
+      }
+      token if token.is_specifier() => Err(
+        SyntaxError::new_parse_error("a declaration is not allowed here".to_string())
+          .with_span(start),
+      ),
+      _ => {
+        tokens.reset_peek();
+        let exp = parse_expression(tokens)?;
+        tokens
+          .take(Token::SemiColon, "expecting a semi colon")
+          .map_err(|err| {
+            err
+              .with_code("E0202")
+              .with_help("statements end with `;`".to_string())
+          })?;
+        Ok(Statement::new(
+          StatementKind::Exp(exp),
+          tokens.span_from(start),
+        ))
+      }
+    };
+  }
+
+  Err(SyntaxError::new_parse_error("expected tokens for statement".to_string()).with_span(start))
+}
+
+/// Parse a parameter list, returning the parameters and whether it ends with
+/// `...` which accepts any number of further arguments.
+fn parse_parameters(tokens: &mut Scanner) -> Result<(Vec<Parameter>, bool), SyntaxError> {
+  tokens.take(
+    Token::OpenParenthesis,
+    "invalid token, type should be OpenParenthesis",
+  )?;
+
+  let mut params: Vec<Parameter> = vec![];
+  match (tokens.peek(), tokens.peek()) {
+    (Some(Token::CloseParenthesis), _) => {
+      tokens.pop("")?;
+      return Ok((params, false));
+    }
+    (Some(Token::Void), Some(Token::CloseParenthesis)) => {
+      tokens.pop("")?;
+      tokens.pop("")?;
+      return Ok((params, false));
+    }
+    _ => tokens.reset_peek(),
+  }
+
+  loop {
+    let start = tokens.span();
+    if let Some(Token::Ellipsis) = tokens.peek() {
+      tokens.pop("")?;
+      if params.is_empty() {
+        return Err(
+          SyntaxError::new_parse_error("a named parameter is required before `...`".to_string())
+            .with_span(start),
+        );
+      }
+      tokens
+        .take(Token::CloseParenthesis, "expected `)` after `...`")
+        .map_err(|err| err.with_code("E0201"))?;
+      return Ok((params, true));
+    }
+    tokens.reset_peek();
+    let ty = parse_type_name(tokens)?;
+    let name = match tokens.peek() {
+      Some(Token::Identifier(name)) => {
+        let span = tokens.span();
+        tokens.pop("")?;
+        if params.iter().any(|param| param.name.as_ref() == Some(name)) {
+          return Err(
+            SyntaxError::new_parse_error(format!("duplicate parameter {}", name)).with_span(span),
+          );
+        }
+        Some(Rc::clone(name))
+      }
+      _ => {
+        tokens.reset_peek();
+        None
+      }
+    };
+    // array parameters are adjusted to pointers to their first element
+    let is_unsized = matches!(
+      (tokens.peek(), tokens.peek()),
+      (Some(Token::OpenBracket), Some(Token::CloseBracket))
+    );
+    tokens.reset_peek();
+    if is_unsized {
+      tokens.pop("")?;
+      tokens.pop("")?;
+    }
+    let ty = match parse_array_dimensions(tokens, ty)? {
+      ty if is_unsized => Type::Pointer(Box::new(ty)),
+      Type::Array(element, _) => Type::Pointer(element),
+      ty => ty,
+    };
+    params.push(Parameter {
+      name,
+      ty,
+      span: tokens.span_from(start),
+    });
+
+    let span = tokens.span();
+    match tokens.pop("expected `,` or `)` after parameter")? {
+      Token::Comma => continue,
+      Token::CloseParenthesis => break,
+      _ => {
+        tokens.backtrack();
+        return Err(
+          SyntaxError::new_parse_error("expected `,` or `)` after parameter".to_string())
+            .with_span(span),
+        );
+      }
+    }
+  }
+
+  Ok((params, false))
+}
+
+/// Panic mode recovery after a failed statement: skip tokens up to and
+/// including the next `;` or a block closed by `}`, stopping before a `}`
+/// which closes the enclosing block.
+fn synchronize_statement(tokens: &mut Scanner) {
+  let mut depth = 0;
+  while let Some(token) = tokens.peek() {
+    match token {
+      Token::CloseBrace if depth == 0 => break,
+      Token::CloseBrace => depth -= 1,
+      Token::OpenBrace => depth += 1,
+      _ => {}
+    }
+    let token = tokens.pop("").cloned();
+    if depth == 0 && matches!(token, Ok(Token::SemiColon) | Ok(Token::CloseBrace)) {
+      break;
+    }
+  }
+  tokens.reset_peek();
+}
+
+/// Panic mode recovery after a failed function: skip tokens until the braces
+/// of the function body are balanced again, or past the `;` ending a prototype.
+fn synchronize_function(tokens: &mut Scanner) {
+  let mut depth = 0;
+  while let Ok(token) = tokens.pop("") {
+    match token {
+      Token::SemiColon if depth == 0 => break,
+      Token::OpenBrace => depth += 1,
+      Token::CloseBrace if depth <= 1 => break,
+      Token::CloseBrace => depth -= 1,
+      _ => {}
+    }
+  }
+}
+
+/// Parse the part of a function declaration following its name, the
+/// parameter list and either a body or the `;` ending a prototype.
+fn parse_function_rest(
+  tokens: &mut Scanner,
+  diagnostics: &mut Diagnostics,
+  start: Span,
+  name: Rc<String>,
+  return_type: Type,
+  storage: Option<StorageClass>,
+) -> Result<Function, SyntaxError> {
+  // parameters
+  let (params, variadic) = parse_parameters(tokens)?;
+
+  // prototype
+  if let Some(Token::SemiColon) = tokens.peek() {
+    tokens.pop("")?;
+    return Ok(Function {
+      name,
+      return_type,
+      storage,
+      params,
+      variadic,
+      body: None,
+      span: tokens.span_from(start),
+    });
+  }
+  tokens.reset_peek();
+
+  if variadic {
+    return Err(
+      SyntaxError::new_parse_error(
+        "definitions of variadic functions are not supported".to_string(),
+      )
+      .with_span(tokens.span_from(start))
+      .with_help(
+        "declare a prototype ending in `...` for a function defined elsewhere".to_string(),
+      ),
+    );
+  }
+  if let Some(param) = params.iter().find(|param| param.name.is_none()) {
+    return Err(
+      SyntaxError::new_parse_error("parameter name omitted in function definition".to_string())
+        .with_span(param.span),
+    );
+  }
+
+  if tokens.peek() != Some(&Token::OpenBrace) {
+    tokens.reset_peek();
+    return Err(
+      SyntaxError::new_parse_error(
+        "invalid token, type should be OpenBrace or SemiColon".to_string(),
+      )
+      .with_span(tokens.span()),
+    );
+  }
+  tokens.reset_peek();
+  let statements = parse_block(tokens, diagnostics)?;
+
   Ok(Function {
-    name: func_name,
-    body: statements,
+    name,
+    return_type,
+    storage,
+    params,
+    variadic: false,
+    body: Some(statements),
+    span: tokens.span_from(start),
   })
 }
 
-pub fn parse_program(tokens: &mut Scanner) -> Result<Program, SyntaxError> {
-  let function = parse_function(tokens)?;
+/// Parse a file scope declaration of a function, a variable or a structure.
+fn parse_external_declaration(
+  tokens: &mut Scanner,
+  diagnostics: &mut Diagnostics,
+) -> Result<ExternalDeclaration, SyntaxError> {
+  let start = tokens.span();
+  if is_struct_declaration(tokens) {
+    return Ok(ExternalDeclaration::Struct(parse_struct_declaration(
+      tokens,
+    )?));
+  }
+  let (ty, storage) = parse_specifiers(tokens)?;
+  let ty = parse_pointers(tokens, ty)?;
+
+  // identifier
+  let identifier_span = tokens.span();
+  let token = tokens.pop("token not found")?;
+  let name = if let Token::Identifier(val) = token {
+    Rc::clone(val)
+  } else {
+    tokens.backtrack();
+    return Err(
+      SyntaxError::new_parse_error("invalid token, type should be Identifier".to_string())
+        .with_span(identifier_span),
+    );
+  };
+
+  if let Some(Token::OpenParenthesis) = tokens.peek() {
+    tokens.reset_peek();
+    let function = parse_function_rest(tokens, diagnostics, start, name, ty, storage)?;
+    return Ok(ExternalDeclaration::Function(function));
+  }
+  tokens.reset_peek();
+
+  let variable = parse_variable_declaration_rest(tokens, start, name, ty, storage)?;
+  Ok(ExternalDeclaration::Variable(variable))
+}
+
+/// Parse a whole translation unit, recovering from errors so that every
+/// [`SyntaxError`] up to `error_limit` is reported at once.
+pub fn parse_program(tokens: &mut Scanner, error_limit: usize) -> Result<Program, Diagnostics> {
+  let mut diagnostics = Diagnostics::with_limit(error_limit);
+  let mut declarations = vec![];
+
+  while tokens.peek().is_some() && !diagnostics.is_full() {
+    tokens.reset_peek();
+    match parse_external_declaration(tokens, &mut diagnostics) {
+      Ok(declaration) => declarations.push(declaration),
+      Err(err) => {
+        diagnostics.push(err);
+        synchronize_function(tokens);
+      }
+    }
+  }
 
-  Ok(Program { func: function })
+  if diagnostics.is_empty() {
+    Ok(Program { declarations })
+  } else {
+    Err(diagnostics)
+  }
 }
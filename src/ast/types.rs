@@ -0,0 +1,190 @@
+use std::{fmt, rc::Rc};
+
+use crate::lex::IntegerKind;
+
+/// Type of a value, declaration or expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+  Char,
+  UChar,
+  Short,
+  UShort,
+  Int,
+  UInt,
+  Long,
+  ULong,
+  /// IEEE 754 single precision.
+  Float,
+  /// IEEE 754 double precision.
+  Double,
+  /// Pointer to a value of the boxed type.
+  Pointer(Box<Type>),
+  /// Fixed number of elements of the boxed type, 0 until the semantic pass
+  /// takes the length of an array declared with `[]` from its initialiser.
+  Array(Box<Type>, usize),
+  /// Structure with the given tag, its members are looked up in the
+  /// `TypeTable` of the program.
+  Struct(Rc<String>),
+  /// Union with the given tag, laid out like a structure whose members all
+  /// start at offset 0.
+  Union(Rc<String>),
+}
+
+impl Type {
+  /// Size of a value of this type in bytes. Structures and unions, also as
+  /// array elements, are sized by the `TypeTable` holding their layout.
+  pub fn size(&self) -> i64 {
+    match self {
+      Type::Char | Type::UChar => 1,
+      Type::Short | Type::UShort => 2,
+      Type::Int | Type::UInt | Type::Float => 4,
+      Type::Long | Type::ULong | Type::Double | Type::Pointer(_) => 8,
+      Type::Array(element, len) => element.size().saturating_mul(*len as i64),
+      Type::Struct(_) | Type::Union(_) => panic!("size of {} depends on its declaration", self),
+    }
+  }
+
+  pub fn is_array(&self) -> bool {
+    matches!(self, Type::Array(..))
+  }
+
+  /// Whether this is a structure or union type.
+  pub fn is_aggregate(&self) -> bool {
+    matches!(self, Type::Struct(_) | Type::Union(_))
+  }
+
+  /// Whether values of this type fit a register, which excludes arrays,
+  /// structures and unions.
+  pub fn is_scalar(&self) -> bool {
+    !self.is_array() && !self.is_aggregate()
+  }
+
+  /// Whether this is an integer or floating type.
+  pub fn is_arithmetic(&self) -> bool {
+    self.is_scalar() && !self.is_pointer()
+  }
+
+  pub fn is_integer(&self) -> bool {
+    self.is_arithmetic() && !self.is_floating()
+  }
+
+  pub fn is_floating(&self) -> bool {
+    matches!(self, Type::Float | Type::Double)
+  }
+
+  pub fn is_pointer(&self) -> bool {
+    matches!(self, Type::Pointer(_))
+  }
+
+  /// Type of the value a pointer points to, `None` for other types.
+  pub fn pointee(&self) -> Option<&Type> {
+    match self {
+      Type::Pointer(pointee) => Some(pointee),
+      _ => None,
+    }
+  }
+
+  pub fn is_signed(&self) -> bool {
+    matches!(self, Type::Char | Type::Short | Type::Int | Type::Long)
+  }
+
+  /// Type after the integer promotions, integer types narrower than `int`
+  /// become `int` as it can represent all of their values.
+  pub fn promote(&self) -> Type {
+    if self.is_integer() && self.size() < Type::Int.size() {
+      Type::Int
+    } else {
+      self.clone()
+    }
+  }
+
+  /// Type both operands of an arithmetic operator are converted to, following
+  /// the usual arithmetic conversions.
+  pub fn common(&self, other: &Type) -> Type {
+    let (lhs, rhs) = (self.promote(), other.promote());
+    if lhs == Type::Double || rhs == Type::Double {
+      Type::Double
+    } else if lhs == Type::Float || rhs == Type::Float {
+      Type::Float
+    } else if lhs == rhs {
+      lhs
+    } else if lhs.size() != rhs.size() {
+      // the wider type can represent every value of the narrower one
+      if lhs.size() > rhs.size() {
+        lhs
+      } else {
+        rhs
+      }
+    } else if lhs.is_signed() {
+      rhs
+    } else {
+      lhs
+    }
+  }
+
+  /// Integer `value` converted to this integer type, wrapping around like a
+  /// cast does.
+  pub fn convert(&self, value: i64) -> i64 {
+    match self {
+      Type::Char => value as i8 as i64,
+      Type::UChar => value as u8 as i64,
+      Type::Short => value as i16 as i64,
+      Type::UShort => value as u16 as i64,
+      Type::Int => value as i32 as i64,
+      Type::UInt => value as u32 as i64,
+      Type::Long
+      | Type::ULong
+      | Type::Float
+      | Type::Double
+      | Type::Pointer(_)
+      | Type::Array(..)
+      | Type::Struct(_)
+      | Type::Union(_) => value,
+    }
+  }
+}
+
+impl From<IntegerKind> for Type {
+  fn from(kind: IntegerKind) -> Self {
+    match kind {
+      IntegerKind::Int => Type::Int,
+      IntegerKind::UInt => Type::UInt,
+      IntegerKind::Long => Type::Long,
+      IntegerKind::ULong => Type::ULong,
+    }
+  }
+}
+
+impl fmt::Display for Type {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      Type::Pointer(pointee) if pointee.is_pointer() => return write!(f, "{}*", pointee),
+      Type::Pointer(pointee) => return write!(f, "{} *", pointee),
+      Type::Array(..) => {
+        // the dimensions follow the element type, outermost first
+        let (mut element, mut dimensions) = (self, String::new());
+        while let Type::Array(inner, len) = element {
+          match len {
+            0 => dimensions.push_str("[]"),
+            len => dimensions.push_str(&format!("[{}]", len)),
+          }
+          element = inner;
+        }
+        return write!(f, "{}{}", element, dimensions);
+      }
+      Type::Struct(tag) => return write!(f, "struct {}", tag),
+      Type::Union(tag) => return write!(f, "union {}", tag),
+      Type::Char => "char",
+      Type::UChar => "unsigned char",
+      Type::Short => "short",
+      Type::UShort => "unsigned short",
+      Type::Int => "int",
+      Type::UInt => "unsigned int",
+      Type::Long => "long",
+      Type::ULong => "unsigned long",
+      Type::Float => "float",
+      Type::Double => "double",
+    };
+    write!(f, "{}", name)
+  }
+}
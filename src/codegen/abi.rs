@@ -0,0 +1,309 @@
+//! Passing values between functions following the System V x86-64 ABI.
+//!
+//! Values are classified by their eightbytes, the 8-byte chunks they are
+//! split into, where structures of up to two eightbytes travel in registers
+//! and larger ones in memory. An eightbyte holding only `float` or `double`
+//! values goes in an SSE register, any other in a general purpose one.
+
+use super::ARGUMENT_REGISTERS;
+use crate::{
+  ast::Type,
+  semantic::{round_up, TypeTable},
+};
+
+/// System V x86-64 registers carrying the first eight floating arguments.
+static SSE_ARGUMENT_REGISTERS: [&str; 8] = [
+  "%xmm0", "%xmm1", "%xmm2", "%xmm3", "%xmm4", "%xmm5", "%xmm6", "%xmm7",
+];
+
+/// Kind of register an eightbyte of a value travels in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Eightbyte {
+  Integer,
+  Sse,
+}
+
+/// How a value is passed to or returned from a function.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Class {
+  /// In one register per eightbyte of the value.
+  Registers(Vec<Eightbyte>),
+  /// On the stack for arguments, returned values are written to memory the
+  /// caller passes a pointer to in `%rdi`.
+  Memory,
+}
+
+pub(super) fn classify(ty: &Type, types: &TypeTable) -> Class {
+  if ty.is_floating() {
+    return Class::Registers(vec![Eightbyte::Sse]);
+  }
+  if !ty.is_aggregate() {
+    return Class::Registers(vec![Eightbyte::Integer]);
+  }
+  match types.size(ty) {
+    size if size > 16 => Class::Memory,
+    size => {
+      let mut classes = vec![Eightbyte::Sse; (round_up(size, 8) / 8) as usize];
+      classify_members(ty, 0, types, &mut classes);
+      Class::Registers(classes)
+    }
+  }
+}
+
+/// Mark the eightbytes of `classes` overlapping a value of type `ty` at
+/// `offset` which is not floating as [`Eightbyte::Integer`].
+fn classify_members(ty: &Type, offset: i64, types: &TypeTable, classes: &mut [Eightbyte]) {
+  match ty {
+    Type::Array(element, len) => {
+      for idx in 0..*len as i64 {
+        classify_members(element, offset + idx * types.size(element), types, classes);
+      }
+    }
+    Type::Struct(_) | Type::Union(_) => {
+      for member in types
+        .layout(ty)
+        .map(|layout| &layout.members[..])
+        .unwrap_or_default()
+      {
+        classify_members(&member.ty, offset + member.offset, types, classes);
+      }
+    }
+    ty if !ty.is_floating() => classes[(offset / 8) as usize] = Eightbyte::Integer,
+    _ => {}
+  }
+}
+
+/// Whether a function returning `ty` takes the address to write the value
+/// to as a hidden first argument.
+pub(super) fn returns_in_memory(ty: &Type, types: &TypeTable) -> bool {
+  ty.is_aggregate() && classify(ty, types) == Class::Memory
+}
+
+/// Registers a value of type `ty` is returned in, one per eightbyte, `None`
+/// when it is returned in memory.
+pub(super) fn return_registers(ty: &Type, types: &TypeTable) -> Option<Vec<&'static str>> {
+  let Class::Registers(classes) = classify(ty, types) else {
+    return None;
+  };
+  let (mut integer, mut sse) = (["%rax", "%rdx"].into_iter(), ["%xmm0", "%xmm1"].into_iter());
+  classes
+    .iter()
+    .map(|class| match class {
+      Eightbyte::Integer => integer.next(),
+      Eightbyte::Sse => sse.next(),
+    })
+    .collect()
+}
+
+/// Where an argument is passed.
+#[derive(Debug, Clone)]
+pub(super) enum Placement {
+  /// In the given registers, one per eightbyte.
+  Registers(Vec<&'static str>),
+  /// On the stack at an offset from the first stack argument.
+  Stack(i64),
+}
+
+/// Placement of arguments of types `params`, along with the number of bytes
+/// they take on the stack. An argument whose eightbytes do not all fit in
+/// the registers left goes on the stack as a whole, later arguments may
+/// still use the registers.
+pub(super) fn place_arguments(
+  params: &[Type],
+  hidden_return: bool,
+  types: &TypeTable,
+) -> (Vec<Placement>, i64) {
+  let (mut next_integer, mut next_sse) = (hidden_return as usize, 0);
+  let mut stack_size = 0;
+  let placements = params
+    .iter()
+    .map(|ty| {
+      if let Class::Registers(classes) = classify(ty, types) {
+        let integers = classes
+          .iter()
+          .filter(|&&class| class == Eightbyte::Integer)
+          .count();
+        let sses = classes.len() - integers;
+        if next_integer + integers <= ARGUMENT_REGISTERS.len()
+          && next_sse + sses <= SSE_ARGUMENT_REGISTERS.len()
+        {
+          let registers = classes
+            .iter()
+            .map(|class| match class {
+              Eightbyte::Integer => {
+                next_integer += 1;
+                ARGUMENT_REGISTERS[next_integer - 1]
+              }
+              Eightbyte::Sse => {
+                next_sse += 1;
+                SSE_ARGUMENT_REGISTERS[next_sse - 1]
+              }
+            })
+            .collect();
+          return Placement::Registers(registers);
+        }
+      }
+      let offset = stack_size;
+      stack_size += round_up(types.size(ty), 8);
+      Placement::Stack(offset)
+    })
+    .collect();
+  (placements, stack_size)
+}
+
+pub(super) fn is_sse(register: &str) -> bool {
+  register.starts_with("%xmm")
+}
+
+/// Instructions pushing the eightbyte in `register`.
+pub(super) fn push_register(register: &str) -> String {
+  match is_sse(register) {
+    true => format!("sub\t$8, %rsp\nmovq\t{}, (%rsp)", register),
+    false => format!("push\t{}", register),
+  }
+}
+
+/// Instructions popping an eightbyte into `register`.
+pub(super) fn pop_register(register: &str) -> String {
+  match is_sse(register) {
+    true => format!("movq\t(%rsp), {}\nadd\t$8, %rsp", register),
+    false => format!("pop\t{}", register),
+  }
+}
+
+/// Instructions loading the `size` bytes at `offset` from the address in
+/// `base` into `register`, without reading past them as they may be the end
+/// of a mapping. `base` must not be `register`, which is clobbered along with
+/// `%r11` and, for an SSE register, `%rsi`.
+pub(super) fn load_eightbyte(base: &str, offset: i64, size: i64, register: &str) -> String {
+  if is_sse(register) {
+    return format!(
+      "{}\nmovq\t%rsi, {}",
+      load_eightbyte(base, offset, size, "%rsi"),
+      register
+    );
+  }
+  if size >= 8 {
+    return format!("mov\t{}({}), {}", offset, base, register);
+  }
+  // assemble the value a byte at a time starting from the highest
+  let mut lines = vec![format!("xor\t{0}, {0}", register)];
+  for byte in (0..size).rev() {
+    lines.push(format!("shl\t$8, {}", register));
+    lines.push(format!("movzbq\t{}({}), %r11", offset + byte, base));
+    lines.push(format!("or\t%r11, {}", register));
+  }
+  lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+  use std::rc::Rc;
+
+  use super::*;
+  use crate::{ast::model::StructKind, semantic::StructLayout, utility::Span};
+
+  /// `types` with the structure or union `tag` of the given members added.
+  fn declare(types: &mut TypeTable, kind: StructKind, tag: &str, members: &[Type]) -> Type {
+    let members = members
+      .iter()
+      .enumerate()
+      .map(|(idx, ty)| (Rc::new(format!("m{}", idx)), ty.clone()))
+      .collect();
+    let layout = StructLayout::new(kind, members, Span::default(), types).unwrap();
+    types.insert(tag, layout);
+    kind.with_tag(Rc::new(tag.to_string()))
+  }
+
+  // the expected classes are the ones gcc passes the same types in
+
+  #[test]
+  fn eightbytes_mixing_integers_and_floats() {
+    let mut types = TypeTable::new();
+    let int_double = declare(
+      &mut types,
+      StructKind::Struct,
+      "a",
+      &[Type::Int, Type::Double],
+    );
+    let float_int = declare(
+      &mut types,
+      StructKind::Struct,
+      "b",
+      &[Type::Float, Type::Int],
+    );
+    let floats = declare(
+      &mut types,
+      StructKind::Struct,
+      "c",
+      &[Type::Float, Type::Float],
+    );
+    let doubles = declare(
+      &mut types,
+      StructKind::Struct,
+      "d",
+      &[Type::Double, Type::Double],
+    );
+    let union = declare(
+      &mut types,
+      StructKind::Union,
+      "e",
+      &[Type::Double, Type::Long],
+    );
+
+    use Eightbyte::*;
+    let cases = [
+      (int_double, vec![Integer, Sse]),
+      (float_int, vec![Integer]),
+      (floats, vec![Sse]),
+      (doubles, vec![Sse, Sse]),
+      (union, vec![Integer]),
+    ];
+    for (ty, classes) in cases {
+      assert_eq!(classify(&ty, &types), Class::Registers(classes), "{}", ty);
+      assert!(!returns_in_memory(&ty, &types));
+    }
+  }
+
+  #[test]
+  fn structures_over_16_bytes_go_in_memory() {
+    let mut types = TypeTable::new();
+    let longs = declare(
+      &mut types,
+      StructKind::Struct,
+      "a",
+      &[Type::Long, Type::Long, Type::Long],
+    );
+    let doubles = Type::Array(Box::new(Type::Double), 3);
+    let doubles = declare(&mut types, StructKind::Struct, "b", &[doubles]);
+    let padded = declare(
+      &mut types,
+      StructKind::Struct,
+      "c",
+      &[Type::Char, Type::Double, Type::Char],
+    );
+    for ty in [longs, doubles, padded] {
+      assert_eq!(classify(&ty, &types), Class::Memory, "{}", ty);
+      assert!(returns_in_memory(&ty, &types));
+    }
+  }
+
+  #[test]
+  fn arguments_left_without_registers_go_on_the_stack() {
+    let mut types = TypeTable::new();
+    let pair = declare(
+      &mut types,
+      StructKind::Struct,
+      "a",
+      &[Type::Long, Type::Long],
+    );
+    // a hidden return pointer takes %rdi, leaving room for two pairs
+    let params = vec![pair.clone(), pair.clone(), pair, Type::Int];
+    let (placements, stack_size) = place_arguments(&params, true, &types);
+    assert!(matches!(&placements[0], Placement::Registers(r) if r == &["%rsi", "%rdx"]));
+    assert!(matches!(&placements[1], Placement::Registers(r) if r == &["%rcx", "%r8"]));
+    assert!(matches!(placements[2], Placement::Stack(0)));
+    assert!(matches!(&placements[3], Placement::Registers(r) if r == &["%r9"]));
+    assert_eq!(stack_size, 16);
+  }
+}
@@ -0,0 +1,124 @@
+//! Instructions for `float` and `double` values.
+//!
+//! Floating values are kept in `%rax` as the bits of their representation
+//! like any other value, zero extended for `float`, and only moved into the
+//! SSE registers for the instructions operating on them.
+
+use super::width;
+use crate::{ast::Type, lex::BinaryOperator};
+
+/// Suffix of the SSE instructions operating on values of type `ty`.
+fn suffix(ty: &Type) -> &'static str {
+  match ty {
+    Type::Float => "ss",
+    _ => "sd",
+  }
+}
+
+/// Instruction moving the value of type `ty` in the SSE `register` to `%rax`.
+pub(super) fn from_sse(ty: &Type, register: &str) -> String {
+  match ty {
+    Type::Float => format!("movd\t{}, %eax", register),
+    _ => format!("movq\t{}, %rax", register),
+  }
+}
+
+/// Instructions for `op` on floating operands of type `ty`, the left one in
+/// `%rcx` and the right one in `%rax`. Comparisons involving a NaN are false
+/// except for `!=`, checked with the parity flag `ucomis*` sets for them.
+pub(super) fn binary(op: BinaryOperator, ty: &Type) -> String {
+  let s = suffix(ty);
+  let compare =
+    |lhs: &str, rhs: &str, set: &str| format!("ucomi{s}\t{rhs}, {lhs}\n{set}\nmovzb\t%al, %rax");
+  let operation = match op {
+    BinaryOperator::Addition => format!("add{s}\t%xmm1, %xmm0\n{}", from_sse(ty, "%xmm0")),
+    BinaryOperator::Minus => format!("sub{s}\t%xmm1, %xmm0\n{}", from_sse(ty, "%xmm0")),
+    BinaryOperator::Multiplication => format!("mul{s}\t%xmm1, %xmm0\n{}", from_sse(ty, "%xmm0")),
+    BinaryOperator::Division => format!("div{s}\t%xmm1, %xmm0\n{}", from_sse(ty, "%xmm0")),
+    BinaryOperator::Equal => compare("%xmm0", "%xmm1", "sete\t%al\nsetnp\t%cl\nand\t%cl, %al"),
+    BinaryOperator::NotEqual => compare("%xmm0", "%xmm1", "setne\t%al\nsetp\t%cl\nor\t%cl, %al"),
+    // an unordered comparison sets the carry flag, so only `a` and `ae`
+    // are false for NaN and less than swaps the operands
+    BinaryOperator::GreaterThan => compare("%xmm0", "%xmm1", "seta\t%al"),
+    BinaryOperator::GreaterThanOrEqual => compare("%xmm0", "%xmm1", "setae\t%al"),
+    BinaryOperator::LessThan => compare("%xmm1", "%xmm0", "seta\t%al"),
+    BinaryOperator::LessThanOrEqual => compare("%xmm1", "%xmm0", "setae\t%al"),
+    BinaryOperator::Remainder
+    | BinaryOperator::BitwiseAnd
+    | BinaryOperator::BitwiseOr
+    | BinaryOperator::BitwiseXor
+    | BinaryOperator::ShiftLeft
+    | BinaryOperator::ShiftRight => unreachable!("integer operators are rejected for floats"),
+    BinaryOperator::And | BinaryOperator::Or => unreachable!("short circuit handled by the caller"),
+  };
+  format!("movq\t%rcx, %xmm0\nmovq\t%rax, %xmm1\n{}", operation)
+}
+
+/// Instruction negating the value of type `ty` in `%rax` by flipping its
+/// sign bit.
+pub(super) fn negate(ty: &Type) -> &'static str {
+  match ty {
+    Type::Float => "btc\t$31, %rax",
+    _ => "btc\t$63, %rax",
+  }
+}
+
+/// Instructions replacing the value of type `ty` in `%rax` with 1 when it
+/// compares unequal to 0 and 0 otherwise, `None` for non floating types
+/// whose bits are only zero for 0. Both `0.0` and `-0.0` are false while
+/// NaN is true.
+pub(super) fn truth(ty: &Type) -> Option<String> {
+  if !ty.is_floating() {
+    return None;
+  }
+  Some(format!(
+    "movq\t%rax, %xmm0\nxorps\t%xmm1, %xmm1\nucomi{}\t%xmm1, %xmm0\nsetne\t%al\nsetp\t%cl\nor\t%cl, %al\nmovzb\t%al, %rax",
+    suffix(ty)
+  ))
+}
+
+/// Instructions converting the value in `%rax` from type `from` to `to` when
+/// either is floating, `None` otherwise. `clause` and `end` are labels for
+/// the conversions from and to `unsigned long`, which the SSE instructions
+/// only support for signed integers.
+pub(super) fn convert(from: &Type, to: &Type, clause: &str, end: &str) -> Option<String> {
+  let code = match (from.is_floating(), to.is_floating()) {
+    (false, false) => return None,
+    (true, true) if from == to => return None,
+    (true, true) => format!(
+      "movq\t%rax, %xmm0\ncvt{}2{}\t%xmm0, %xmm0\n{}",
+      suffix(from),
+      suffix(to),
+      from_sse(to, "%xmm0")
+    ),
+    // halve values with the top bit set, keeping the lowest bit to round
+    // the same way, and double the result
+    (false, true) if *from == Type::ULong => format!(
+      "test\t%rax, %rax\njs\t{clause}\ncvtsi2{s}q\t%rax, %xmm0\njmp\t{end}\n{clause}:\nmov\t%rax, %rcx\nshr\t%rcx\nand\t$1, %eax\nor\t%rax, %rcx\ncvtsi2{s}q\t%rcx, %xmm0\nadd{s}\t%xmm0, %xmm0\n{end}:\n{}",
+      from_sse(to, "%xmm0"),
+      s = suffix(to)
+    ),
+    // narrower integers are extended to 64 bits, which fit a signed long
+    (false, true) => format!("cvtsi2{}q\t%rax, %xmm0\n{}", suffix(to), from_sse(to, "%xmm0")),
+    // values from 2^63 up are converted after subtracting 2^63
+    (true, false) if *to == Type::ULong => {
+      let limit = match from {
+        Type::Float => "mov\t$0x5f000000, %ecx",
+        _ => "movabs\t$0x43e0000000000000, %rcx",
+      };
+      format!(
+        "movq\t%rax, %xmm0\n{limit}\nmovq\t%rcx, %xmm1\nucomi{s}\t%xmm1, %xmm0\njae\t{clause}\ncvtt{s}2siq\t%xmm0, %rax\njmp\t{end}\n{clause}:\nsub{s}\t%xmm1, %xmm0\ncvtt{s}2siq\t%xmm0, %rax\nbtc\t$63, %rax\n{end}:",
+        s = suffix(from)
+      )
+    }
+    // truncate towards zero, narrower integers wrap around like a cast
+    (true, false) => {
+      let convert = format!("movq\t%rax, %xmm0\ncvtt{}2siq\t%xmm0, %rax", suffix(from));
+      match width::extend(to) {
+        Some(extend) => format!("{}\n{}", convert, extend),
+        None => convert,
+      }
+    }
+  };
+  Some(code)
+}
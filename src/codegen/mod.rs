@@ -1,31 +1,235 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, rc::Rc};
+
+use tracing::trace;
 
 use crate::{
-  ast::model::*,
+  ast::{model::*, Type},
   lex::{BinaryOperator, UnaryOperator},
-  utility::SyntaxError,
+  semantic::{round_up, InitialValue, StaticInit, SymbolKind, SymbolTable, TypeTable},
+  utility::{Span, SyntaxError},
 };
 
 static FUNCTION_PROLOGUE_START: &str = "push\t%rbp\n\tmov\t%rsp, %rbp";
-static FUNCTION_PROLOGUE_END: &str = "mov\t%rbp, %rsp\n\tpop\t%rbp\n\tret\n";
+static FUNCTION_PROLOGUE_END: &str = "mov\t%rbp, %rsp\npop\t%rbp\nret";
+
+/// System V x86-64 registers carrying the first six integer arguments.
+static ARGUMENT_REGISTERS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+mod abi;
+mod float;
+mod width;
+
+use abi::Placement;
+
+/// Where the value of a variable is stored.
+#[derive(Clone)]
+enum Location {
+  /// Offset from `%rbp` of a local variable or parameter.
+  Stack(i64),
+  /// Label of a variable with static storage, addressed relative to `%rip`.
+  Data(String),
+}
+
+impl Location {
+  /// Memory operand addressing the variable.
+  fn address(&self) -> String {
+    match self {
+      Location::Stack(offset) => format!("{}(%rbp)", offset),
+      Location::Data(label) => format!("{}(%rip)", label),
+    }
+  }
+}
+
+/// Jump targets of the innermost loop for `break` and `continue`.
+struct LoopLabels {
+  continue_label: String,
+  break_label: String,
+  /// Value of [`CodeGenerator::stack_index`] at the start of the loop body,
+  /// jumps out of nested blocks release their variables down to it.
+  stack_index: i64,
+}
 
+#[derive(Default)]
 pub struct CodeGenerator {
-  symbol_table: HashMap<String, i64>,
+  /// Variables keyed by the unique names given to them by the semantic pass.
+  variables: HashMap<String, Location>,
+  /// Values of `stack_index` when the enclosing blocks were entered,
+  /// innermost last.
+  scopes: Vec<i64>,
   stack_index: i64,
   clause_count: u64,
+  loops: Vec<LoopLabels>,
+  /// Layouts of the structures used by the program.
+  types: TypeTable,
+  /// Value of `stack_index` when the full expression being generated started,
+  /// its temporaries are placed right below.
+  temporary_base: i64,
+  /// Bytes of temporaries the full expression being generated needs.
+  temporaries: i64,
+  /// Offset from `%rbp` of the address a structure returned in memory by the
+  /// function being generated is written to.
+  return_pointer: Option<i64>,
+  /// String literals emitted into `.rodata`, labelled by their index.
+  strings: Vec<Rc<Vec<u8>>>,
 }
 
 impl CodeGenerator {
   pub fn new() -> Self {
-    Self {
-      symbol_table: HashMap::new(),
-      stack_index: 0,
-      clause_count: 0,
+    Self::default()
+  }
+
+  pub fn generate(&mut self, root: &Program, symbols: &SymbolTable) -> Result<String, SyntaxError> {
+    let mut assembly = String::from("\t.text\n");
+    self.types = symbols.types().clone();
+    for (name, symbol) in symbols.iter() {
+      if let SymbolKind::Variable { .. } = symbol.kind {
+        self
+          .variables
+          .insert(name.to_string(), Location::Data(name.to_string()));
+      }
+    }
+    for func in root.functions() {
+      // prototypes only matter to the semantic checks
+      if let Some(body) = &func.body {
+        let global = symbols.get(&func.name).is_none_or(|symbol| symbol.global);
+        assembly.push_str(&self.generate_function(func, body, global)?);
+      }
     }
+
+    for (name, symbol) in symbols.iter() {
+      let (ty, values) = match &symbol.kind {
+        SymbolKind::Variable {
+          ty,
+          init: InitialValue::Initial(values),
+        } => (ty, values.clone()),
+        SymbolKind::Variable {
+          ty,
+          init: InitialValue::Tentative,
+        } => (ty, vec![StaticInit::Zero(self.types.size(ty))]),
+        _ => continue,
+      };
+      assembly.push_str(&self.generate_static_variable(name, ty, &values, symbol.global));
+    }
+
+    if !self.strings.is_empty() {
+      assembly.push_str("\n\t.section\t.rodata\n");
+      for (idx, val) in self.strings.iter().enumerate() {
+        assembly.push_str(&format!(".Lstring{}:\n\t.asciz \"{}\"\n", idx, escape(val)));
+      }
+    }
+
+    assembly.push_str("\n\t.section\t.note.GNU-stack,\"\",@progbits\n");
+    Ok(assembly)
   }
 
-  pub fn generate(&mut self, root: &Program) -> Result<String, SyntaxError> {
-    self.generate_function(&root.func)
+  /// Storage for a variable of type `ty`, zero initialised ones go in `.bss`.
+  fn generate_static_variable(
+    &mut self,
+    label: &str,
+    ty: &Type,
+    values: &[StaticInit],
+    global: bool,
+  ) -> String {
+    let visibility = if global {
+      format!("\t.globl {}\n", label)
+    } else {
+      String::new()
+    };
+    let (size, alignment) = (self.types.size(ty), self.alignment(ty));
+    if values.iter().all(StaticInit::is_zero) {
+      return format!("\n{visibility}\t.bss\n\t.align {alignment}\n{label}:\n\t.zero {size}\n");
+    }
+
+    let data = values
+      .iter()
+      .map(|value| match value {
+        StaticInit::Scalar(ty, value) => format!("\t{} {}\n", width::directive(ty), value),
+        StaticInit::String(val) => format!("\t.ascii \"{}\"\n", escape(val)),
+        StaticInit::StringPointer(val) => format!("\t.quad {}\n", self.string_label(val)),
+        StaticInit::Address(label, 0) => format!("\t.quad {}\n", label),
+        StaticInit::Address(label, offset) => format!("\t.quad {}{:+}\n", label, offset),
+        StaticInit::Zero(size) => format!("\t.zero {}\n", size),
+      })
+      .collect::<String>();
+    format!("\n{visibility}\t.data\n\t.align {alignment}\n{label}:\n{data}")
+  }
+
+  /// Label of a copy of the string literal `val` in `.rodata`.
+  fn string_label(&mut self, val: &Rc<Vec<u8>>) -> String {
+    self.strings.push(Rc::clone(val));
+    format!(".Lstring{}", self.strings.len() - 1)
+  }
+
+  /// Alignment of a variable of type `ty`, the ABI requires arrays of 16
+  /// bytes or more to be 16 byte aligned.
+  fn alignment(&self, ty: &Type) -> i64 {
+    if ty.is_array() && self.types.size(ty) >= 16 {
+      16
+    } else {
+      self.types.alignment(ty)
+    }
+  }
+
+  fn enter_scope(&mut self) {
+    self.scopes.push(self.stack_index);
+  }
+
+  /// Leave the innermost scope, returning the instruction releasing the stack
+  /// space of the variables declared in it.
+  fn exit_scope(&mut self) -> Option<String> {
+    let stack_index = self.scopes.pop()?;
+    let size = stack_index - self.stack_index;
+    self.stack_index = stack_index;
+    (size != 0).then(|| format!("add\t${}, %rsp", size))
+  }
+
+  fn declare_variable(&mut self, name: &str, location: Location) {
+    self.variables.insert(name.to_string(), location);
+  }
+
+  fn lookup_variable(&self, name: &str, span: Span) -> Result<&Location, SyntaxError> {
+    self.variables.get(name).ok_or_else(|| {
+      SyntaxError::new_codegen_error(format!("variable {} was not resolved", name)).with_span(span)
+    })
+  }
+
+  /// Generate a loop body with `continue_label` and `break_label` as the
+  /// targets of `continue` and `break` statements inside it.
+  fn generate_loop_body(
+    &mut self,
+    body: &Statement,
+    continue_label: &str,
+    break_label: &str,
+  ) -> Result<String, SyntaxError> {
+    self.loops.push(LoopLabels {
+      continue_label: continue_label.to_string(),
+      break_label: break_label.to_string(),
+      stack_index: self.stack_index,
+    });
+    let body = self.generate_statement(body);
+    self.loops.pop();
+    body
+  }
+
+  /// Jump to a label of the innermost loop, releasing block variables first.
+  fn generate_loop_jump(&self, st: &Statement) -> Result<String, SyntaxError> {
+    let Some(labels) = self.loops.last() else {
+      return Err(
+        SyntaxError::new_codegen_error("jump statement not within a loop".to_string())
+          .with_span(st.span),
+      );
+    };
+    let label = match st.kind {
+      StatementKind::Break => &labels.break_label,
+      _ => &labels.continue_label,
+    };
+
+    let size = labels.stack_index - self.stack_index;
+    if size != 0 {
+      Ok(format!("add\t${}, %rsp\njmp\t{}", size, label))
+    } else {
+      Ok(format!("jmp\t{}", label))
+    }
   }
 
   fn generate_clause(&mut self) -> String {
@@ -37,33 +241,71 @@ impl CodeGenerator {
     format!("_end{}", self.clause_count)
   }
 
-  fn generate_function(&mut self, func: &Function) -> Result<String, SyntaxError> {
+  fn generate_function(
+    &mut self,
+    func: &Function,
+    statements: &[Statement],
+    global: bool,
+  ) -> Result<String, SyntaxError> {
+    trace!("generating function {} defined at {}", func.name, func.span);
+    self.scopes.clear();
+    self.stack_index = 0;
+    self.return_pointer = None;
+    // parameters share the scope of the function body
+    self.enter_scope();
+
     let mut body = vec![];
-    let mut return_flag = false;
-    for x in &func.body {
-      let mut st = self.generate_statement(x)?;
-      st = st.replace("\n", "\n\t");
+    let hidden_return = abi::returns_in_memory(&func.return_type, &self.types);
+    if hidden_return {
+      body.push("push\t%rdi".to_string());
+      self.stack_index -= 8;
+      self.return_pointer = Some(self.stack_index);
+    }
 
-      match x {
-        Statement::Return(_) => {
-          st = format!("{}\n\t{}", st, FUNCTION_PROLOGUE_END);
-          return_flag = true;
+    // variables are loaded by the width of their type, so the undefined
+    // upper bytes of narrow arguments are never read
+    let params: Vec<Type> = func.params.iter().map(|param| param.ty.clone()).collect();
+    let (placements, _) = abi::place_arguments(&params, hidden_return, &self.types);
+    for (param, placement) in func.params.iter().zip(placements) {
+      let location = match placement {
+        Placement::Registers(registers) => {
+          // the first eightbyte ends up at the lowest address
+          for register in registers.iter().rev() {
+            body.push(abi::push_register(register));
+            self.stack_index -= 8;
+          }
+          Location::Stack(self.stack_index)
         }
-        _ => {}
+        // stack arguments sit above the return address and saved %rbp
+        Placement::Stack(offset) => Location::Stack(16 + offset),
+      };
+      if let Some(name) = &param.name {
+        self.declare_variable(name, location);
       }
-      body.push(st);
     }
 
-    let end = if return_flag {
-      format!("")
-    } else {
-      format!("mov\t$0, %rax\n\t{}", FUNCTION_PROLOGUE_END)
+    for x in statements {
+      let st = self.generate_statement(x)?;
+      body.push(st.replace('\n', "\n\t"));
+    }
+
+    // falling off the end of a function returns 0
+    let end = match statements.last() {
+      Some(Statement {
+        kind: StatementKind::Return(_),
+        ..
+      }) => String::new(),
+      _ => format!("mov\t$0, %rax\n{}\n", FUNCTION_PROLOGUE_END).replace('\n', "\n\t"),
     };
 
+    let visibility = if global {
+      format!("\t.globl {}\n", func.name)
+    } else {
+      String::new()
+    };
     Ok(format!(
       "
-\t.globl {name}
-{name}:
+{visibility}{name}:
 \t{start}
 \t{body_text}
 \t{end}
@@ -75,88 +317,745 @@ impl CodeGenerator {
   }
 
   fn generate_statement(&mut self, st: &Statement) -> Result<String, SyntaxError> {
-    match st {
-      Statement::Return(val) => {
-        let exp = self.generate_expression(val)?;
+    match &st.kind {
+      StatementKind::Return(val) => {
+        let return_value = self.return_value(val.ty());
+        let exp = self.generate_full_expression(val, &return_value)?;
+        Ok(format!("{}\n{}\n", exp, FUNCTION_PROLOGUE_END))
+      }
+      StatementKind::Declare(VariableDeclaration {
+        name,
+        ty,
+        init,
+        storage,
+        ..
+      }) => {
+        // variables with static storage live in the data sections
+        if storage.is_some() {
+          return Ok(String::new());
+        }
+        if !ty.is_scalar() {
+          return self.generate_local_object(name, ty, init.as_ref());
+        }
 
-        Ok(format!("{}", exp))
+        // a variable is in scope inside its own initialiser
+        self.declare_variable(name, Location::Stack(self.stack_index - 8));
+        let assembly_exp = match init {
+          Some(Initializer::Single(exp_some)) => self.generate_full_expression(exp_some, "")?,
+          Some(Initializer::Compound(_, span)) => {
+            return Err(
+              SyntaxError::new_codegen_error(format!("scalar {} initialised with a list", name))
+                .with_span(*span),
+            )
+          }
+          None => self.generate_expression(&Expression::new(
+            ExpressionKind::Const(0, Type::Int),
+            st.span,
+          ))?,
+        };
+        self.stack_index -= 8;
+        Ok(format!("{}\npush\t%rax", assembly_exp))
+      }
+      StatementKind::Exp(val) => self.generate_full_expression(val, ""),
+      // structures are laid out by the semantic pass
+      StatementKind::DeclareStruct(_) => Ok(String::new()),
+      StatementKind::Compound(statements) => {
+        self.enter_scope();
+        let mut lines = vec![];
+        for st in statements {
+          lines.push(self.generate_statement(st)?);
+        }
+        lines.extend(self.exit_scope());
+        Ok(lines.join("\n"))
+      }
+      StatementKind::While { condition, body } => {
+        let start_label = self.generate_clause();
+        let end_label = self.generate_end();
+        let condition = self.generate_full_condition(condition)?;
+        let body = self.generate_loop_body(body, &start_label, &end_label)?;
+        Ok(format!(
+          "{start_label}:\n{}\ncmp\t$0, %rax\nje\t{end_label}\n{}\njmp\t{start_label}\n{end_label}:",
+          condition, body
+        ))
+      }
+      StatementKind::DoWhile { body, condition } => {
+        let start_label = self.generate_clause();
+        let end_label = self.generate_end();
+        let continue_label = self.generate_clause();
+        let body = self.generate_loop_body(body, &continue_label, &end_label)?;
+        let condition = self.generate_full_condition(condition)?;
+        Ok(format!(
+          "{start_label}:\n{}\n{continue_label}:\n{}\ncmp\t$0, %rax\njne\t{start_label}\n{end_label}:",
+          body, condition
+        ))
       }
-      Statement::Declare { name, exp } => {
-        if self.symbol_table.contains_key(name.as_ref()) {
-          Err(SyntaxError::new_codegen_error(
-            format!("re-declaration of variable {}", name).to_string(),
-          ))
-        } else {
-          let mut assembly_exp = self.generate_expression(&Expression::Const(0))?;
-          if let Some(exp_some) = exp {
-            assembly_exp = self.generate_expression(exp_some)?;
-          };
-          self.stack_index -= 8;
-          self.symbol_table.insert(name.to_string(), self.stack_index);
-          Ok(format!("{}\npush\t%rax", assembly_exp))
+      StatementKind::For {
+        init,
+        condition,
+        post,
+        body,
+      } => {
+        let start_label = self.generate_clause();
+        let end_label = self.generate_end();
+        let continue_label = self.generate_clause();
+
+        // a declaration in the init clause is scoped to the loop
+        self.enter_scope();
+        let mut lines = vec![];
+        if let Some(init) = init {
+          lines.push(self.generate_statement(init)?);
+        }
+        lines.push(format!("{}:", start_label));
+        if let Some(condition) = condition {
+          lines.push(self.generate_full_condition(condition)?);
+          lines.push(format!("cmp\t$0, %rax\nje\t{}", end_label));
+        }
+        lines.push(self.generate_loop_body(body, &continue_label, &end_label)?);
+        lines.push(format!("{}:", continue_label));
+        if let Some(post) = post {
+          lines.push(self.generate_full_expression(post, "")?);
         }
+        lines.push(format!("jmp\t{}\n{}:", start_label, end_label));
+        lines.extend(self.exit_scope());
+        Ok(lines.join("\n"))
+      }
+      StatementKind::Break | StatementKind::Continue => self.generate_loop_jump(st),
+      StatementKind::Null => Ok(String::new()),
+      StatementKind::If {
+        condition,
+        then,
+        otherwise,
+      } => {
+        let else_label = self.generate_clause();
+        let end_label = self.generate_end();
+        let condition = self.generate_full_condition(condition)?;
+        let then = self.generate_statement(then)?;
+        match otherwise {
+          Some(otherwise) => {
+            let otherwise = self.generate_statement(otherwise)?;
+            Ok(format!(
+              "{}\ncmp\t$0, %rax\nje\t{else_label}\n{}\njmp\t{end_label}\n{else_label}:\n{}\n{end_label}:",
+              condition, then, otherwise
+            ))
+          }
+          None => Ok(format!(
+            "{}\ncmp\t$0, %rax\nje\t{end_label}\n{}\n{end_label}:",
+            condition, then
+          )),
+        }
+      }
+    }
+  }
+
+  /// Reserve stack space for a local array, structure or union, storing the
+  /// values of its initialiser and zeroing the parts without one.
+  fn generate_local_object(
+    &mut self,
+    name: &str,
+    ty: &Type,
+    init: Option<&Initializer>,
+  ) -> Result<String, SyntaxError> {
+    // keep %rsp a multiple of 8 below the object
+    let (size, alignment) = (self.types.size(ty), self.alignment(ty).max(8));
+    let offset = (self.stack_index - size).div_euclid(alignment) * alignment;
+    let mut lines = vec![format!("sub\t${}, %rsp", self.stack_index - offset)];
+    self.stack_index = offset;
+    self.declare_variable(name, Location::Stack(offset));
+
+    match init {
+      // copying a whole structure leaves nothing to zero
+      Some(init @ Initializer::Single(_)) if ty.is_aggregate() => {
+        self.generate_initializer(init, ty, offset, &mut lines)?
+      }
+      Some(init) => {
+        for idx in (0..size).step_by(8) {
+          lines.push(format!("movq\t$0, {}(%rbp)", offset + idx));
+        }
+        self.generate_initializer(init, ty, offset, &mut lines)?;
+      }
+      None => {}
+    }
+    Ok(lines.join("\n"))
+  }
+
+  /// Store the values of `init` into an object of type `ty` at `offset` from
+  /// `%rbp`.
+  fn generate_initializer(
+    &mut self,
+    init: &Initializer,
+    ty: &Type,
+    offset: i64,
+    lines: &mut Vec<String>,
+  ) -> Result<(), SyntaxError> {
+    match (init, ty) {
+      (Initializer::Single(exp), _) if ty.is_aggregate() => {
+        let copy = format!(
+          "lea\t{}(%rbp), %rcx\n{}",
+          offset,
+          width::copy(self.types.size(ty), "%rax", "%rcx")
+        );
+        lines.push(self.generate_full_expression(exp, &copy)?);
+      }
+      (
+        Initializer::Single(Expression {
+          kind: ExpressionKind::String(val),
+          ..
+        }),
+        Type::Array(_, len),
+      ) => {
+        // the terminating null character is dropped when it does not fit
+        let size = (val.len() + 1).min(*len) as i64;
+        lines.push(format!(
+          "lea\t{}(%rip), %rax\nlea\t{}(%rbp), %rcx\n{}",
+          self.string_label(val),
+          offset,
+          width::copy(size, "%rax", "%rcx")
+        ));
+      }
+      (Initializer::Single(exp), _) => {
+        lines.push(self.generate_full_expression(exp, "")?);
+        lines.push(width::store(ty, &format!("{}(%rbp)", offset)));
+      }
+      (Initializer::Compound(initializers, _), Type::Array(element, _)) => {
+        for (idx, init) in initializers.iter().enumerate() {
+          let offset = offset + idx as i64 * self.types.size(element);
+          self.generate_initializer(init, element, offset, lines)?;
+        }
+      }
+      (Initializer::Compound(initializers, _), _) if ty.is_aggregate() => {
+        let members = self
+          .types
+          .layout(ty)
+          .map(|layout| layout.members.clone())
+          .unwrap_or_default();
+        for (init, member) in initializers.iter().zip(members.iter()) {
+          self.generate_initializer(init, &member.ty, offset + member.offset, lines)?;
+        }
+      }
+      (Initializer::Compound(_, span), _) => {
+        return Err(
+          SyntaxError::new_codegen_error(format!("{} initialised with a list", ty))
+            .with_span(*span),
+        )
+      }
+    }
+    Ok(())
+  }
+
+  /// Generate `exp` as a full expression followed by `consume`, which uses
+  /// its value in `%rax`. Stack space for the temporaries holding structures
+  /// returned by calls in it is reserved until after `consume`.
+  fn generate_full_expression(
+    &mut self,
+    exp: &Expression,
+    consume: &str,
+  ) -> Result<String, SyntaxError> {
+    self.temporary_base = self.stack_index;
+    self.temporaries = 0;
+    let mut code = self.generate_expression(exp)?;
+    if !consume.is_empty() {
+      code = format!("{}\n{}", code, consume);
+    }
+    // a multiple of 16 keeps the alignment of calls in it unchanged
+    let size = round_up(self.temporaries, 16);
+    if size == 0 {
+      return Ok(code);
+    }
+    Ok(format!("sub\t${size}, %rsp\n{code}\nadd\t${size}, %rsp"))
+  }
+
+  /// Generate the controlling expression of a statement as a full
+  /// expression, leaving a value in `%rax` which is 0 exactly when it
+  /// compares equal to 0.
+  fn generate_full_condition(&mut self, exp: &Expression) -> Result<String, SyntaxError> {
+    self.generate_full_expression(exp, &float::truth(exp.ty()).unwrap_or_default())
+  }
+
+  /// Generate `exp` as an operand compared against 0, see
+  /// [`Self::generate_full_condition`].
+  fn generate_condition(&mut self, exp: &Expression) -> Result<String, SyntaxError> {
+    let code = self.generate_expression(exp)?;
+    match float::truth(exp.ty()) {
+      Some(truth) => Ok(format!("{}\n{}", code, truth)),
+      None => Ok(code),
+    }
+  }
+
+  /// Reserve `size` bytes for a temporary of the current full expression,
+  /// returning its offset from `%rbp`.
+  fn allocate_temporary(&mut self, size: i64) -> i64 {
+    self.temporaries += round_up(size, 8);
+    self.temporary_base - self.temporaries
+  }
+
+  /// Instructions passing the value of type `ty` in `%rax` back to the
+  /// caller, only floating values and structures need more than leaving it
+  /// there.
+  fn return_value(&self, ty: &Type) -> String {
+    if ty.is_floating() {
+      return "movq\t%rax, %xmm0".to_string();
+    }
+    if !ty.is_aggregate() {
+      return String::new();
+    }
+    let size = self.types.size(ty);
+    match (abi::return_registers(ty, &self.types), self.return_pointer) {
+      (None, Some(pointer)) => format!(
+        "mov\t{}(%rbp), %rcx\n{}\nmov\t%rcx, %rax",
+        pointer,
+        width::copy(size, "%rax", "%rcx")
+      ),
+      (registers, _) => {
+        let mut lines = vec!["mov\t%rax, %rcx".to_string()];
+        for (idx, register) in registers.unwrap_or_default().iter().enumerate() {
+          let offset = idx as i64 * 8;
+          lines.push(abi::load_eightbyte("%rcx", offset, size - offset, register));
+        }
+        lines.join("\n")
       }
-      Statement::Exp(val) => self.generate_expression(val),
     }
   }
 
   fn generate_expression(&mut self, exp: &Expression) -> Result<String, SyntaxError> {
-    match exp {
-      Expression::Const(val) => Ok(format!("mov\t${}, %rax", val.to_string())),
-      Expression::Unary { op, exp } => {
-        let inner_exp = self.generate_expression(exp)?;
+    match &exp.kind {
+      // immediates of other instructions are limited to 32 bits
+      ExpressionKind::Const(val, _) if i32::try_from(*val).is_ok() => {
+        Ok(format!("mov\t${}, %rax", val))
+      }
+      ExpressionKind::Const(val, _) => Ok(format!("movabs\t${}, %rax", val)),
+      ExpressionKind::ConstFloat(val, Type::Float) => {
+        Ok(format!("mov\t${}, %eax", (*val as f32).to_bits()))
+      }
+      ExpressionKind::ConstFloat(val, _) => Ok(format!("movabs\t${}, %rax", val.to_bits())),
+      ExpressionKind::Unary {
+        op: UnaryOperator::AddressOf,
+        exp: inner,
+      } => self.generate_address(inner),
+      ExpressionKind::Unary {
+        op: UnaryOperator::Negation,
+        exp: inner,
+      } if inner.ty().is_floating() => {
+        let inner_exp = self.generate_expression(inner)?;
+        Ok(format!("{}\n{}", inner_exp, float::negate(inner.ty())))
+      }
+      ExpressionKind::Unary { op, exp: inner } => {
+        let inner_exp = match op {
+          UnaryOperator::LogicalNegation => self.generate_condition(inner)?,
+          _ => self.generate_expression(inner)?,
+        };
         let ext_exp = match op {
-          UnaryOperator::Negation => format!("neg\t%rax"),
-          UnaryOperator::BitwiseComplement => format!("not\t%rax"),
-          UnaryOperator::LogicalNegation => {
-            format!("cmp\t$0, %rax\nmov\t$0, %rax\nsete\t%al")
+          UnaryOperator::Negation => "neg\t%rax",
+          UnaryOperator::BitwiseComplement => "not\t%rax",
+          UnaryOperator::LogicalNegation => "cmp\t$0, %rax\nmov\t$0, %rax\nsete\t%al",
+          UnaryOperator::Dereference => {
+            return Ok(format!(
+              "{}\n{}",
+              inner_exp,
+              width::load(exp.ty(), "(%rax)")
+            ))
           }
+          UnaryOperator::AddressOf => unreachable!("address of handled above"),
         };
-        Ok(format!("{}\n{}", inner_exp, ext_exp))
+        Ok(Self::extended(
+          format!("{}\n{}", inner_exp, ext_exp),
+          exp.ty(),
+        ))
       }
-      Expression::Binary { exp1, op, exp2 } => {
+      ExpressionKind::Cast { target, exp } => {
+        let inner_exp = self.generate_expression(exp)?;
+        Ok(self.converted(inner_exp, exp.ty(), target))
+      }
+      ExpressionKind::Binary { exp1, op, exp2 } => {
+        // both operands were converted to the same type, except for pointer
+        // arithmetic where the integer operand is a long
+        let (operand_type, exp2_type) = (exp1.ty().clone(), exp2.ty().clone());
+        if let BinaryOperator::And | BinaryOperator::Or = op {
+          let exp1 = self.generate_condition(exp1)?;
+          let exp2 = self.generate_condition(exp2)?;
+          if let BinaryOperator::And = op {
+            return Ok(format!("{}\ncmp\t$0, %rax\njne\t{_clause2}\njmp\t{_end}\n{_clause2}:\n{}\ncmp\t$0, %rax\nsetne\t%al\nmovzb\t%al, %rax\n{_end}:", exp1, exp2, _clause2 = self.generate_clause(), _end = self.generate_end()));
+          }
+          return Ok(format!("{}\ncmp\t$0, %rax\nje\t{_clause2}\nmov\t$1, %rax\njmp\t{_end}\n{_clause2}:\n{}\ncmp\t$0, %rax\nsetne\t%al\nmovzb\t%al, %rax\n{_end}:", exp1, exp2, _clause2 = self.generate_clause(), _end = self.generate_end()));
+        }
         let exp1 = self.generate_expression(exp1)?;
+
+        // exp1 stays pushed while exp2 is evaluated
+        self.stack_index -= 8;
         let exp2 = self.generate_expression(exp2)?;
-        let inner_exp = format!("{}\npush\t%rax\n{}\npop\t%rcx", exp1, exp2);
-        let ext_exp = match op {
-          BinaryOperator::Addition => format!("add\t%rcx, %rax"),
-          BinaryOperator::Multiplication => format!("imul\t%rcx, %rax"),
-          BinaryOperator::Minus => format!("sub\t%rax, %rcx\nmov\t%rcx, %rax"),
-          BinaryOperator::Division => {
-            format!("mov\t%rax, %rbx\nmov\t%rcx, %rax\ncqo\nidiv\t%rbx")
-          }
-          BinaryOperator::Equal => format!("cmp\t%rax, %rcx\nsete\t%al"),
-          BinaryOperator::NotEqual => {
-            format!("cmp\t%rax, %rcx\ncmp\t$0, %rax\nsetne\t%al")
-          }
-          BinaryOperator::LessThan => format!("cmp\t%rax, %rcx\nsetl\t%al"),
-          BinaryOperator::LessThanOrEqual => format!("cmp\t%rax, %rcx\nsetle\t%al"),
-          BinaryOperator::GreaterThan => format!("cmp\t%rax, %rcx\nsetg\t%al"),
-          BinaryOperator::GreaterThanOrEqual => format!("cmp\t%rax, %rcx\nsetge\t%al"),
-          BinaryOperator::And => {
-            return Ok(format!("{}\ncmp\t$0, %rax\njne\t{_clause2}\njmp\t{_end}\n{_clause2}:\n{}\ncmp\t$0, %rax\ncmp $0, %rax\nsetne\t%al\n{_end}:", exp1, exp2, _clause2 = self.generate_clause(), _end = self.generate_end()));
-          }
-          BinaryOperator::Or => {
-            return Ok(format!("{}\ncmp\t$0, %rax\nje\t{_clause2}\nmov\t$1, %rax\njmp\t{_end}\n{_clause2}:\n{}\ncmp\t$0, %rax\ncmp $0, %rax\nsetne\t%al\n{_end}:", exp1, exp2, _clause2 = self.generate_clause(), _end = self.generate_end()));
-          }
+        self.stack_index += 8;
+        let ext_exp = self.binary_operation(*op, &operand_type, &exp2_type);
+        Ok(Self::extended(
+          format!("{}\npush\t%rax\n{}\npop\t%rcx\n{}", exp1, exp2, ext_exp),
+          exp.ty(),
+        ))
+      }
+      ExpressionKind::CompoundAssign {
+        op,
+        target,
+        exp: inner,
+        postfix,
+        operation_type,
+      } => {
+        let operation_type = operation_type
+          .as_ref()
+          .expect("operation types are set by the semantic pass");
+        // the address and then the right operand stay pushed while the
+        // value is computed, along with the old value for a postfix operator
+        let address = self.generate_address(target)?;
+        self.stack_index -= 8;
+        let operand = self.generate_expression(inner)?;
+        self.stack_index -= 8;
+        let mut code = format!(
+          "{}\npush\t%rax\n{}\npush\t%rax\nmov\t8(%rsp), %rax\n{}",
+          address,
+          operand,
+          width::load(exp.ty(), "(%rax)")
+        );
+        let mut offset = 0;
+        if *postfix {
+          code.push_str("\npush\t%rax");
+          offset = 8;
+        }
+        let code = self.converted(code, exp.ty(), operation_type);
+        let operation = self.binary_operation(*op, operation_type, inner.ty());
+        let code = Self::extended(
+          format!(
+            "{}\nmov\t%rax, %rcx\nmov\t{}(%rsp), %rax\n{}",
+            code, offset, operation
+          ),
+          operation_type,
+        );
+        let mut code = self.converted(code, operation_type, exp.ty());
+        code.push_str(&format!(
+          "\nmov\t{}(%rsp), %rcx\n{}",
+          offset + 8,
+          width::store(exp.ty(), "(%rcx)")
+        ));
+        if *postfix {
+          code.push_str("\npop\t%rax");
+        }
+        self.stack_index += 16;
+        Ok(format!("{}\nadd\t$16, %rsp", code))
+      }
+      ExpressionKind::Comma { exp1, exp2 } => {
+        let exp1 = self.generate_expression(exp1)?;
+        let exp2 = self.generate_expression(exp2)?;
+        Ok(format!("{}\n{}", exp1, exp2))
+      }
+      ExpressionKind::Assign { target, exp: inner } => {
+        // the address stays pushed while the value is evaluated
+        let address = self.generate_address(target)?;
+        self.stack_index -= 8;
+        let assign_exp = self.generate_expression(inner)?;
+        self.stack_index += 8;
+        // the value of a structure assignment is the address of the target
+        let store = match exp.ty().is_aggregate() {
+          true => format!(
+            "{}\nmov\t%rcx, %rax",
+            width::copy(self.types.size(exp.ty()), "%rax", "%rcx")
+          ),
+          false => width::store(exp.ty(), "(%rcx)"),
         };
-        Ok(format!("{}\n{}", inner_exp, ext_exp))
+        Ok(format!(
+          "{}\npush\t%rax\n{}\npop\t%rcx\n{}",
+          address, assign_exp, store
+        ))
+      }
+      ExpressionKind::Var { name } => {
+        let address = self.lookup_variable(name, exp.span)?.address();
+        Ok(width::load(exp.ty(), &address))
       }
-      Expression::Assign { name, exp } => match self.symbol_table.get(name.as_ref()) {
-        None => Err(SyntaxError::new_codegen_error(
-          format!("variable not declared {}", name).to_string(),
-        )),
-        Some(&offset) => {
-          let assign_exp = self.generate_expression(exp)?;
-          Ok(format!("{}\nmov\t%rax, {}(%rbp)", assign_exp, offset))
+      // arrays decay to pointers, so only `&` and `sizeof` see a string
+      ExpressionKind::String(_) => self.generate_address(exp),
+      ExpressionKind::Subscript { .. } | ExpressionKind::Member { .. } => {
+        let address = self.generate_address(exp)?;
+        Ok(format!("{}\n{}", address, width::load(exp.ty(), "(%rax)")))
+      }
+      ExpressionKind::SizeOf(inner) => Ok(format!("mov\t${}, %rax", self.types.size(inner.ty()))),
+      ExpressionKind::SizeOfType(ty) => Ok(format!("mov\t${}, %rax", self.types.size(ty))),
+      ExpressionKind::FunctionCall { name, args } => self.generate_call(name, args, exp.ty()),
+      ExpressionKind::Conditional {
+        condition,
+        then,
+        otherwise,
+      } => {
+        let else_label = self.generate_clause();
+        let end_label = self.generate_end();
+        let condition = self.generate_condition(condition)?;
+        let then = self.generate_expression(then)?;
+        let otherwise = self.generate_expression(otherwise)?;
+        Ok(format!(
+          "{}\ncmp\t$0, %rax\nje\t{else_label}\n{}\njmp\t{end_label}\n{else_label}:\n{}\n{end_label}:",
+          condition, then, otherwise
+        ))
+      }
+    }
+  }
+
+  /// Compute the address of the object designated by the lvalue `exp` into
+  /// `%rax`.
+  fn generate_address(&mut self, exp: &Expression) -> Result<String, SyntaxError> {
+    match &exp.kind {
+      ExpressionKind::Var { name } => {
+        let address = self.lookup_variable(name, exp.span)?.address();
+        Ok(format!("lea\t{}, %rax", address))
+      }
+      ExpressionKind::String(val) => Ok(format!("lea\t{}(%rip), %rax", self.string_label(val))),
+      ExpressionKind::Unary {
+        op: UnaryOperator::Dereference,
+        exp: pointer,
+      } => self.generate_expression(pointer),
+      ExpressionKind::Subscript {
+        exp: pointer,
+        index,
+      } => {
+        let pointer_exp = self.generate_expression(pointer)?;
+        self.stack_index -= 8;
+        let index_exp = self.generate_expression(index)?;
+        self.stack_index += 8;
+        let add = self
+          .pointer_arithmetic(BinaryOperator::Addition, pointer.ty(), index.ty())
+          .expect("one operand of a subscript is a pointer");
+        Ok(format!(
+          "{}\npush\t%rax\n{}\npop\t%rcx\n{}",
+          pointer_exp, index_exp, add
+        ))
+      }
+      ExpressionKind::Member {
+        exp: object,
+        member,
+      } => {
+        // the value of a structure is its address
+        let object_exp = self.generate_expression(object)?;
+        let offset = self
+          .types
+          .member(object.ty(), member)
+          .map(|member| member.offset)
+          .ok_or_else(|| {
+            SyntaxError::new_codegen_error(format!("{} has no member {}", object.ty(), member))
+              .with_span(exp.span)
+          })?;
+        match offset {
+          0 => Ok(object_exp),
+          offset => Ok(format!("{}\nadd\t${}, %rax", object_exp, offset)),
         }
+      }
+      _ => Err(
+        SyntaxError::new_codegen_error("expression is not an lvalue".to_string())
+          .with_span(exp.span),
+      ),
+    }
+  }
+
+  /// Instructions for `op` on a left operand of type `lhs` in `%rcx` and a
+  /// right one of type `rhs` in `%rax`, which have the same type except for
+  /// pointer arithmetic and shifts.
+  fn binary_operation(&self, op: BinaryOperator, lhs: &Type, rhs: &Type) -> String {
+    if let Some(operation) = self.pointer_arithmetic(op, lhs, rhs) {
+      return operation;
+    }
+    if lhs.is_floating() {
+      return float::binary(op, lhs);
+    }
+    let signed = lhs.is_signed();
+    let operation = match op {
+      BinaryOperator::Addition => "add\t%rcx, %rax",
+      BinaryOperator::Multiplication => "imul\t%rcx, %rax",
+      BinaryOperator::Minus => "sub\t%rax, %rcx\nmov\t%rcx, %rax",
+      BinaryOperator::Division => match (lhs.size(), signed) {
+        (8, true) => "xchg\t%rax, %rcx\ncqo\nidiv\t%rcx",
+        (8, false) => "xchg\t%rax, %rcx\nxor\t%edx, %edx\ndiv\t%rcx",
+        (_, true) => "xchg\t%rax, %rcx\ncltd\nidivl\t%ecx",
+        (_, false) => "xchg\t%rax, %rcx\nxor\t%edx, %edx\ndivl\t%ecx",
       },
-      Expression::Var { name } => match self.symbol_table.get(name.as_ref()) {
-        None => Err(SyntaxError::new_codegen_error(
-          format!("variable not declared {}", name).to_string(),
-        )),
-        Some(&offset) => Ok(format!("mov\t{}(%rbp), %rax", offset)),
+      BinaryOperator::Equal => "cmp\t%rax, %rcx\nsete\t%al\nmovzb\t%al, %rax",
+      BinaryOperator::NotEqual => "cmp\t%rax, %rcx\nsetne\t%al\nmovzb\t%al, %rax",
+      BinaryOperator::LessThan if signed => "cmp\t%rax, %rcx\nsetl\t%al\nmovzb\t%al, %rax",
+      BinaryOperator::LessThan => "cmp\t%rax, %rcx\nsetb\t%al\nmovzb\t%al, %rax",
+      BinaryOperator::LessThanOrEqual if signed => "cmp\t%rax, %rcx\nsetle\t%al\nmovzb\t%al, %rax",
+      BinaryOperator::LessThanOrEqual => "cmp\t%rax, %rcx\nsetbe\t%al\nmovzb\t%al, %rax",
+      BinaryOperator::GreaterThan if signed => "cmp\t%rax, %rcx\nsetg\t%al\nmovzb\t%al, %rax",
+      BinaryOperator::GreaterThan => "cmp\t%rax, %rcx\nseta\t%al\nmovzb\t%al, %rax",
+      BinaryOperator::GreaterThanOrEqual if signed => {
+        "cmp\t%rax, %rcx\nsetge\t%al\nmovzb\t%al, %rax"
+      }
+      BinaryOperator::GreaterThanOrEqual => "cmp\t%rax, %rcx\nsetae\t%al\nmovzb\t%al, %rax",
+      BinaryOperator::Remainder => match (lhs.size(), signed) {
+        (8, true) => "xchg\t%rax, %rcx\ncqo\nidiv\t%rcx\nmov\t%rdx, %rax",
+        (8, false) => "xchg\t%rax, %rcx\nxor\t%edx, %edx\ndiv\t%rcx\nmov\t%rdx, %rax",
+        (_, true) => "xchg\t%rax, %rcx\ncltd\nidivl\t%ecx\nmov\t%edx, %eax",
+        (_, false) => "xchg\t%rax, %rcx\nxor\t%edx, %edx\ndivl\t%ecx\nmov\t%edx, %eax",
       },
+      BinaryOperator::BitwiseAnd => "and\t%rcx, %rax",
+      BinaryOperator::BitwiseOr => "or\t%rcx, %rax",
+      BinaryOperator::BitwiseXor => "xor\t%rcx, %rax",
+      // the count goes in `%cl`, values are extended to 64 bits so shifting
+      // the whole register keeps the sign of narrower ones
+      BinaryOperator::ShiftLeft => "xchg\t%rax, %rcx\nshl\t%cl, %rax",
+      BinaryOperator::ShiftRight if signed => "xchg\t%rax, %rcx\nsar\t%cl, %rax",
+      BinaryOperator::ShiftRight => "xchg\t%rax, %rcx\nshr\t%cl, %rax",
+      BinaryOperator::And | BinaryOperator::Or => {
+        unreachable!("short circuit handled by the caller")
+      }
+    };
+    operation.to_string()
+  }
+
+  /// `code` followed by instructions converting the value of type `from` it
+  /// leaves in `%rax` to `to`.
+  fn converted(&mut self, code: String, from: &Type, to: &Type) -> String {
+    let (clause, end) = (self.generate_clause(), self.generate_end());
+    match float::convert(from, to, &clause, &end) {
+      Some(convert) => format!("{}\n{}", code, convert),
+      None => Self::extended(code, to),
+    }
+  }
+
+  /// Instructions for `op` on operands of types `lhs` in `%rcx` and `rhs` in
+  /// `%rax` when it is pointer arithmetic, scaling the integer operand by the
+  /// size of the type pointed to.
+  fn pointer_arithmetic(&self, op: BinaryOperator, lhs: &Type, rhs: &Type) -> Option<String> {
+    let size = |pointee: &Type| self.types.size(pointee);
+    match (op, lhs.pointee(), rhs.pointee()) {
+      (BinaryOperator::Addition, Some(pointee), None) => {
+        Some(format!("imul\t${}, %rax\nadd\t%rcx, %rax", size(pointee)))
+      }
+      (BinaryOperator::Addition, None, Some(pointee)) => {
+        Some(format!("imul\t${}, %rcx\nadd\t%rcx, %rax", size(pointee)))
+      }
+      (BinaryOperator::Minus, Some(pointee), None) => Some(format!(
+        "imul\t${}, %rax\nsub\t%rax, %rcx\nmov\t%rcx, %rax",
+        size(pointee)
+      )),
+      (BinaryOperator::Minus, Some(pointee), Some(_)) => Some(format!(
+        "sub\t%rax, %rcx\nmov\t%rcx, %rax\ncqo\nmov\t${}, %rcx\nidiv\t%rcx",
+        size(pointee)
+      )),
+      _ => None,
+    }
+  }
+
+  /// Call `name` following the System V x86-64 calling convention: arguments
+  /// are placed in registers and on the stack by their class, pushed right
+  /// to left, and `%rsp` is 16-byte aligned at the `call` instruction. The
+  /// value of a call returning a structure is the address of a temporary
+  /// holding it.
+  fn generate_call(
+    &mut self,
+    name: &str,
+    args: &[Expression],
+    return_type: &Type,
+  ) -> Result<String, SyntaxError> {
+    let hidden_return = abi::returns_in_memory(return_type, &self.types);
+    let types: Vec<Type> = args.iter().map(|arg| arg.ty().clone()).collect();
+    let (placements, stack_size) = abi::place_arguments(&types, hidden_return, &self.types);
+    let result = return_type
+      .is_aggregate()
+      .then(|| self.allocate_temporary(self.types.size(return_type)));
+    let padding = (self.stack_index - stack_size).rem_euclid(16);
+
+    let mut lines = vec![];
+    if padding != 0 {
+      lines.push(format!("sub\t${}, %rsp", padding));
+      self.stack_index -= padding;
+    }
+
+    // stack arguments go first as the register ones are popped off again,
+    // evaluating right to left leaves the first argument on top
+    for (arg, placement) in args.iter().zip(&placements).rev() {
+      if let Placement::Stack(_) = placement {
+        lines.push(self.push_argument(arg)?);
+      }
+    }
+    for (arg, placement) in args.iter().zip(&placements).rev() {
+      if let Placement::Registers(_) = placement {
+        lines.push(self.push_argument(arg)?);
+      }
+    }
+    let mut sse_registers = 0;
+    for placement in &placements {
+      if let Placement::Registers(registers) = placement {
+        for register in registers {
+          lines.push(abi::pop_register(register));
+          sse_registers += abi::is_sse(register) as usize;
+          self.stack_index += 8;
+        }
+      }
+    }
+    if let (true, Some(offset)) = (hidden_return, result) {
+      lines.push(format!("lea\t{}(%rbp), %rdi", offset));
+    }
+
+    // %al holds the number of vector registers used by variadic callees
+    lines.push(format!("mov\t${}, %rax", sse_registers));
+    lines.push(format!("call\t{}", name));
+
+    let cleanup = stack_size + padding;
+    if cleanup != 0 {
+      lines.push(format!("add\t${}, %rsp", cleanup));
+      self.stack_index += cleanup;
     }
+
+    let Some(offset) = result else {
+      if return_type.is_floating() {
+        lines.push(float::from_sse(return_type, "%xmm0"));
+      }
+      return Ok(Self::extended(lines.join("\n"), return_type));
+    };
+    if !hidden_return {
+      let registers = abi::return_registers(return_type, &self.types).unwrap_or_default();
+      for (idx, register) in registers.iter().enumerate() {
+        let mov = if abi::is_sse(register) { "movq" } else { "mov" };
+        lines.push(format!(
+          "{}\t{}, {}(%rbp)",
+          mov,
+          register,
+          offset + idx as i64 * 8
+        ));
+      }
+    }
+    lines.push(format!("lea\t{}(%rbp), %rax", offset));
+    Ok(lines.join("\n"))
+  }
+
+  /// Evaluate an argument and push it, structures are copied onto the stack
+  /// taking a multiple of 8 bytes with the first eightbyte on top.
+  fn push_argument(&mut self, arg: &Expression) -> Result<String, SyntaxError> {
+    let exp = self.generate_expression(arg)?;
+    if !arg.ty().is_aggregate() {
+      self.stack_index -= 8;
+      return Ok(format!("{}\npush\t%rax", exp));
+    }
+    let size = self.types.size(arg.ty());
+    let reserved = round_up(size, 8);
+    self.stack_index -= reserved;
+    Ok(format!(
+      "{}\nsub\t${}, %rsp\nmov\t%rsp, %rcx\n{}",
+      exp,
+      reserved,
+      width::copy(size, "%rax", "%rcx")
+    ))
   }
+
+  /// `code` followed by the extension of its result in `%rax` to 64 bits,
+  /// results narrower than that only define the low bytes of the register.
+  fn extended(code: String, ty: &Type) -> String {
+    match width::extend(ty) {
+      Some(extend) => format!("{}\n{}", code, extend),
+      None => code,
+    }
+  }
+}
+
+/// `val` as the contents of a string in assembler syntax, with everything
+/// but printable ASCII written as octal escapes.
+fn escape(val: &[u8]) -> String {
+  val
+    .iter()
+    .map(|&byte| match byte {
+      b'"' | b'\\' => format!("\\{}", byte as char),
+      b' '..=b'~' => (byte as char).to_string(),
+      _ => format!("\\{:03o}", byte),
+    })
+    .collect()
 }
@@ -0,0 +1,85 @@
+//! Instructions depending on the width and signedness of a [`Type`].
+//!
+//! Values are kept in `%rax` sign or zero extended to 64 bits according to
+//! their type, so only loads, stores and conversions look at the width.
+//! Floating values are kept as their bits, zero extended for `float`.
+
+use crate::ast::Type;
+
+/// Instruction extending the low bytes of `%rax` holding a value of type `ty`
+/// to the full register, `None` for 64-bit and floating types.
+pub(super) fn extend(ty: &Type) -> Option<&'static str> {
+  match ty {
+    Type::Char => Some("movsbq\t%al, %rax"),
+    Type::UChar => Some("movzbq\t%al, %rax"),
+    Type::Short => Some("movswq\t%ax, %rax"),
+    Type::UShort => Some("movzwq\t%ax, %rax"),
+    Type::Int => Some("movslq\t%eax, %rax"),
+    // writing a 32-bit register clears the upper half
+    Type::UInt => Some("mov\t%eax, %eax"),
+    Type::Long
+    | Type::ULong
+    | Type::Float
+    | Type::Double
+    | Type::Pointer(_)
+    | Type::Array(..)
+    | Type::Struct(_)
+    | Type::Union(_) => None,
+  }
+}
+
+/// Instruction loading a value of type `ty` stored at `address` into `%rax`,
+/// the value of a structure or union is its address.
+pub(super) fn load(ty: &Type, address: &str) -> String {
+  match ty {
+    Type::Char => format!("movsbq\t{}, %rax", address),
+    Type::UChar => format!("movzbq\t{}, %rax", address),
+    Type::Short => format!("movswq\t{}, %rax", address),
+    Type::UShort => format!("movzwq\t{}, %rax", address),
+    Type::Int => format!("movslq\t{}, %rax", address),
+    Type::UInt | Type::Float => format!("mov\t{}, %eax", address),
+    Type::Long | Type::ULong | Type::Double | Type::Pointer(_) => format!("mov\t{}, %rax", address),
+    Type::Struct(_) | Type::Union(_) => format!("lea\t{}, %rax", address),
+    Type::Array(..) => unreachable!("arrays decay to pointers instead of being loaded"),
+  }
+}
+
+/// Instruction storing the low bytes of `%rax` as a value of type `ty` at `address`.
+pub(super) fn store(ty: &Type, address: &str) -> String {
+  let register = match ty.size() {
+    1 => "%al",
+    2 => "%ax",
+    4 => "%eax",
+    _ => "%rax",
+  };
+  format!("mov\t{}, {}", register, address)
+}
+
+/// Assembler directive emitting a value of type `ty` into a data section.
+pub(super) fn directive(ty: &Type) -> &'static str {
+  match ty.size() {
+    1 => ".byte",
+    2 => ".short",
+    4 => ".long",
+    _ => ".quad",
+  }
+}
+
+/// Instructions copying `size` bytes from the address in `from` to the
+/// address in `to` through `%r11`, the widest moves first.
+pub(super) fn copy(size: i64, from: &str, to: &str) -> String {
+  let mut lines = vec![];
+  let mut offset = 0;
+  while offset < size {
+    let (chunk, register) = match size - offset {
+      8.. => (8, "%r11"),
+      4..=7 => (4, "%r11d"),
+      2 | 3 => (2, "%r11w"),
+      _ => (1, "%r11b"),
+    };
+    lines.push(format!("mov\t{}({}), {}", offset, from, register));
+    lines.push(format!("mov\t{}, {}({})", register, offset, to));
+    offset += chunk;
+  }
+  lines.join("\n")
+}
@@ -1,12 +1,16 @@
 use std::{fmt::Display, rc::Rc};
 
-use crate::utility::SyntaxError;
+use crate::utility::{Span, SyntaxError};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum UnaryOperator {
   Negation,
   BitwiseComplement,
   LogicalNegation,
+  /// `&`, the address of an lvalue.
+  AddressOf,
+  /// `*`, the object a pointer points to.
+  Dereference,
 }
 
 impl TryFrom<Token> for UnaryOperator {
@@ -17,6 +21,8 @@ impl TryFrom<Token> for UnaryOperator {
       Token::Negation => Ok(Self::Negation),
       Token::BitwiseComplement => Ok(Self::BitwiseComplement),
       Token::LogicalNegation => Ok(Self::LogicalNegation),
+      Token::Ampersand => Ok(Self::AddressOf),
+      Token::Multiplication => Ok(Self::Dereference),
       _ => Err(SyntaxError::new_lex_error(
         "Can only convert unary operators".to_string(),
       )),
@@ -30,6 +36,14 @@ pub enum BinaryOperator {
   Minus,
   Multiplication,
   Division,
+  /// `%`, the remainder of a division.
+  Remainder,
+  BitwiseAnd,
+  BitwiseOr,
+  BitwiseXor,
+  ShiftLeft,
+  /// `>>`, arithmetic for signed and logical for unsigned operands.
+  ShiftRight,
   And,
   Or,
   Equal,
@@ -49,6 +63,12 @@ impl TryFrom<Token> for BinaryOperator {
       Token::Negation => Ok(Self::Minus),
       Token::Multiplication => Ok(Self::Multiplication),
       Token::Division => Ok(Self::Division),
+      Token::Remainder => Ok(Self::Remainder),
+      Token::Ampersand => Ok(Self::BitwiseAnd),
+      Token::Pipe => Ok(Self::BitwiseOr),
+      Token::Caret => Ok(Self::BitwiseXor),
+      Token::ShiftLeft => Ok(Self::ShiftLeft),
+      Token::ShiftRight => Ok(Self::ShiftRight),
       Token::And => Ok(Self::And),
       Token::Or => Ok(Self::Or),
       Token::Equal => Ok(Self::Equal),
@@ -64,23 +84,86 @@ impl TryFrom<Token> for BinaryOperator {
   }
 }
 
+/// Type of an integer literal, the first of the types its suffix and base
+/// allow which can represent its value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntegerKind {
+  Int,
+  UInt,
+  Long,
+  ULong,
+}
+
+impl IntegerKind {
+  /// Largest value of this type.
+  pub fn max(self) -> u64 {
+    match self {
+      IntegerKind::Int => i32::MAX as u64,
+      IntegerKind::UInt => u32::MAX as u64,
+      IntegerKind::Long => i64::MAX as u64,
+      IntegerKind::ULong => u64::MAX,
+    }
+  }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
   OpenBrace,
   CloseBrace,
   OpenParenthesis,
   CloseParenthesis,
+  OpenBracket,
+  CloseBracket,
   SemiColon,
+  Comma,
+  Char,
+  Short,
   Int,
+  Long,
+  Float,
+  Double,
+  Signed,
+  Unsigned,
+  Struct,
+  Union,
+  Void,
+  Static,
+  Extern,
   Return,
+  If,
+  Else,
+  While,
+  Do,
+  For,
+  Break,
+  Continue,
+  Sizeof,
   Identifier(Rc<String>),
-  Integer(i32),
+  /// Integer literal, an `unsigned long` above `i64::MAX` held as its bits.
+  Integer(i64, IntegerKind),
+  /// Floating literal without a suffix.
+  DoubleLiteral(f64),
+  /// Floating literal with an `f` suffix, already rounded to single precision.
+  FloatLiteral(f64),
+  /// Character literal, holding the value of its `char` converted to `int`.
+  CharLiteral(i64),
+  /// String literal with its escape sequences replaced, without the
+  /// terminating null character.
+  StringLiteral(Rc<Vec<u8>>),
   Negation,
   BitwiseComplement,
   LogicalNegation,
   Addition,
   Multiplication,
   Division,
+  Remainder,
+  Ampersand,
+  Pipe,
+  Caret,
+  ShiftLeft,
+  ShiftRight,
+  Increment,
+  Decrement,
   And,
   Or,
   Equal,
@@ -90,23 +173,98 @@ pub enum Token {
   GreaterThan,
   GreaterThanOrEqual,
   Assignment,
+  /// `op=`, e.g. `+=` or `<<=`.
+  CompoundAssignment(BinaryOperator),
+  QuestionMark,
+  Colon,
+  Dot,
+  Arrow,
+  Ellipsis,
+  /// `#`, which starts a preprocessing directive when first on its line.
+  Hash,
+  /// `##`, pasting tokens together in a macro replacement list.
+  HashHash,
+}
+
+/// A [`Token`] along with the [`Span`] of source text it was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+  pub token: Token,
+  pub span: Span,
+  /// Whether only whitespace and comments precede the token on its line.
+  pub first_on_line: bool,
+  /// Whether whitespace or a comment comes right before the token, which a
+  /// macro turning it into a string keeps as a space.
+  pub whitespace_before: bool,
 }
 
 impl Token {
   pub fn keywords(value: &str) -> Option<Self> {
     match value {
+      "char" => Some(Self::Char),
+      "short" => Some(Self::Short),
       "int" => Some(Self::Int),
+      "long" => Some(Self::Long),
+      "float" => Some(Self::Float),
+      "double" => Some(Self::Double),
+      "signed" => Some(Self::Signed),
+      "unsigned" => Some(Self::Unsigned),
+      "struct" => Some(Self::Struct),
+      "union" => Some(Self::Union),
+      "void" => Some(Self::Void),
+      "static" => Some(Self::Static),
+      "extern" => Some(Self::Extern),
       "return" => Some(Self::Return),
+      "if" => Some(Self::If),
+      "else" => Some(Self::Else),
+      "while" => Some(Self::While),
+      "do" => Some(Self::Do),
+      "for" => Some(Self::For),
+      "break" => Some(Self::Break),
+      "continue" => Some(Self::Continue),
+      "sizeof" => Some(Self::Sizeof),
       _ => None,
     }
   }
+
+  /// Whether the token names or modifies a type, e.g. `unsigned`, `int` or
+  /// the `struct` of `struct point`.
+  pub fn is_type_specifier(&self) -> bool {
+    matches!(
+      self,
+      Self::Char
+        | Self::Short
+        | Self::Int
+        | Self::Long
+        | Self::Float
+        | Self::Double
+        | Self::Signed
+        | Self::Unsigned
+        | Self::Struct
+        | Self::Union
+    )
+  }
+
+  /// Whether the token may start a declaration.
+  pub fn is_specifier(&self) -> bool {
+    self.is_type_specifier() || matches!(self, Self::Static | Self::Extern)
+  }
 }
 
 impl Display for Token {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match self {
-      Token::Integer(val) => f.write_fmt(format_args!("Token: Integer{{{}}}", val)),
+      Token::Integer(val, IntegerKind::ULong) => {
+        f.write_fmt(format_args!("Token: Integer{{{} ULong}}", *val as u64))
+      }
+      Token::Integer(val, kind) => {
+        f.write_fmt(format_args!("Token: Integer{{{} {:?}}}", val, kind))
+      }
       Token::Identifier(val) => f.write_fmt(format_args!("Token: Identifier{{{}}}", val)),
+      Token::StringLiteral(val) => f.write_fmt(format_args!(
+        "Token: StringLiteral{{{:?}}}",
+        String::from_utf8_lossy(val)
+      )),
       val => f.write_fmt(format_args!("Token: {:?}", val)),
     }
   }
@@ -1,118 +1,499 @@
+//! Single pass lexer turning source text into [`SpannedToken`] values.
+//!
+//! [`Lexer`] walks the bytes of the source once, picking the kind of token
+//! from its first byte and extending it as far as it goes, the longest
+//! match wins. Only identifiers and literals allocate.
+
 use std::rc::Rc;
 
-use regex::Regex;
 use tracing::trace;
 
-use crate::utility::SyntaxError;
-
-use super::model::Token;
+use crate::utility::{FileId, Span, SyntaxError};
 
-pub fn parse_literal_token(value: &str) -> (Option<Token>, usize) {
-  let literal_regex = Regex::new("^\\w+").unwrap();
-  let number_regex = Regex::new("^\\d+").unwrap();
+use super::model::{BinaryOperator, IntegerKind, SpannedToken, Token};
 
-  match literal_regex.find(value) {
-    Some(literal_match) => {
-      let token = if let Some(keyword) = Token::keywords(literal_match.as_str()) {
-        keyword
-      } else if let Some(number_match) = number_regex.find(literal_match.as_str()) {
-        Token::Integer(number_match.as_str().parse().expect("integer parse"))
-      } else {
-        Token::Identifier(Rc::new(literal_match.as_str().to_owned()))
-      };
-
-      (Some(token), literal_match.end())
+/// Value of the escape sequence at the start of `value`, which follows a
+/// backslash, along with the number of bytes it takes.
+fn parse_escape(value: &[u8]) -> Result<(u8, usize), SyntaxError> {
+  let simple = match value.first() {
+    Some(b'n') => b'\n',
+    Some(b't') => b'\t',
+    Some(b'r') => b'\r',
+    Some(b'a') => 0x07,
+    Some(b'b') => 0x08,
+    Some(b'f') => 0x0c,
+    Some(b'v') => 0x0b,
+    Some(&byte @ (b'\\' | b'\'' | b'"' | b'?')) => byte,
+    Some(b'0'..=b'7') => {
+      let digits = value
+        .iter()
+        .take(3)
+        .take_while(|byte| (b'0'..=b'7').contains(*byte))
+        .count();
+      let code = u32::from_str_radix(std::str::from_utf8(&value[..digits]).unwrap(), 8).unwrap();
+      let code = u8::try_from(code).map_err(|_| {
+        SyntaxError::new_lex_error("octal escape sequence out of range".to_string())
+      })?;
+      return Ok((code, digits));
     }
-    None => (None, 0),
-  }
+    Some(b'x') => {
+      let digits = value[1..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_hexdigit())
+        .count();
+      if digits == 0 {
+        return Err(SyntaxError::new_lex_error(
+          "\\x used with no following hex digits".to_string(),
+        ));
+      }
+      let code = u64::from_str_radix(std::str::from_utf8(&value[1..=digits]).unwrap(), 16)
+        .ok()
+        .and_then(|code| u8::try_from(code).ok())
+        .ok_or_else(|| {
+          SyntaxError::new_lex_error("hex escape sequence out of range".to_string())
+        })?;
+      return Ok((code, digits + 1));
+    }
+    Some(&other) => {
+      return Err(SyntaxError::new_lex_error(format!(
+        "unknown escape sequence \\{}",
+        other as char
+      )))
+    }
+    None => {
+      return Err(SyntaxError::new_lex_error(
+        "unterminated escape sequence".to_string(),
+      ))
+    }
+  };
+  Ok((simple, 1))
 }
 
-pub fn parse_compound_token(value: Option<&str>) -> (Option<Token>, usize) {
-  return match value {
-    Some(compound_value) => {
-      let compound_token = match compound_value {
-        "&&" => Token::And,
-        "||" => Token::Or,
-        "==" => Token::Equal,
-        "!=" => Token::NotEqual,
-        "<=" => Token::LessThanOrEqual,
-        ">=" => Token::GreaterThanOrEqual,
-        _ => return (None, 0),
-      };
+/// Parse the character or string literal at the start of `value`, which
+/// starts with its opening quote, returning the token and its length.
+pub fn parse_quoted_token(value: &str) -> Result<(Token, usize), SyntaxError> {
+  let bytes = value.as_bytes();
+  let quote = bytes[0];
+  let mut contents = vec![];
+  let mut idx = 1;
+  loop {
+    match bytes.get(idx) {
+      Some(&byte) if byte == quote => break,
+      Some(b'\\') => {
+        let (code, len) = parse_escape(&bytes[idx + 1..]).map_err(|err| err.with_code("E0103"))?;
+        contents.push(code);
+        idx += len + 1;
+      }
+      Some(b'\n') | None => {
+        let kind = if quote == b'"' { "string" } else { "character" };
+        return Err(
+          SyntaxError::new_lex_error(format!("missing terminating {} character", quote as char))
+            .with_code("E0102")
+            .with_help(format!("{} literals end on the line they start", kind)),
+        );
+      }
+      Some(&byte) => {
+        contents.push(byte);
+        idx += 1;
+      }
+    }
+  }
 
-      (Some(compound_token), 2)
+  let token = match (quote, &contents[..]) {
+    (b'"', _) => Token::StringLiteral(Rc::new(contents)),
+    // `char` is signed, so bytes from 0x80 up are negative
+    (_, &[byte]) => Token::CharLiteral(byte as i8 as i64),
+    (_, []) => {
+      return Err(
+        SyntaxError::new_lex_error("empty character literal".to_string()).with_code("E0103"),
+      )
+    }
+    _ => {
+      return Err(
+        SyntaxError::new_lex_error("multi-character character literal".to_string())
+          .with_code("E0103"),
+      )
     }
-    None => (None, 0),
   };
+  Ok((token, idx + 1))
 }
 
-pub fn try_parse_simple_token(value: char) -> Result<(Option<Token>, usize), SyntaxError> {
-  if value.is_whitespace() {
-    return Ok((None, 1));
-  }
-
-  let token = match value {
-    '{' => Token::OpenBrace,
-    '}' => Token::CloseBrace,
-    '(' => Token::OpenParenthesis,
-    ')' => Token::CloseParenthesis,
-    ';' => Token::SemiColon,
-    '-' => Token::Negation,
-    '~' => Token::BitwiseComplement,
-    '!' => Token::LogicalNegation,
-    '+' => Token::Addition,
-    '*' => Token::Multiplication,
-    '/' => Token::Division,
-    '<' => Token::LessThan,
-    '>' => Token::GreaterThan,
-    '=' => Token::Assignment,
-    val => {
-      return Err(SyntaxError::new_lex_error(format!(
-        "unidentifiable character {}",
-        val
-      )))
+/// Iterator over the tokens of a source text, skipping whitespace and
+/// comments.
+pub struct Lexer<'a> {
+  source: &'a str,
+  file: FileId,
+  /// Byte offset of the next character to look at.
+  position: usize,
+  /// 1-based line `position` is on.
+  line: usize,
+  /// Byte offset of the first character of `line`.
+  line_start: usize,
+  /// Whether only whitespace and comments were read since the last newline.
+  first_on_line: bool,
+}
+
+impl<'a> Lexer<'a> {
+  pub fn new(source: &'a str, file: FileId) -> Self {
+    Self {
+      source,
+      file,
+      position: 0,
+      line: 1,
+      line_start: 0,
+      first_on_line: true,
     }
-  };
+  }
 
-  return Ok((Some(token), 1));
-}
+  /// Read the next token, `None` once the source is exhausted.
+  pub fn next_token(&mut self) -> Result<Option<SpannedToken>, SyntaxError> {
+    let trivia = self.position;
+    self.skip_trivia()?;
+    let whitespace_before = self.position != trivia;
+    let Some(&byte) = self.bytes().get(self.position) else {
+      return Ok(None);
+    };
+    let first_on_line = std::mem::replace(&mut self.first_on_line, false);
 
-pub fn string_tokenizer(value: &str) -> Result<Vec<Token>, SyntaxError> {
-  let mut tokens = Vec::new();
-  let mut end_idx = 0;
-  let byte_array = value.as_bytes();
-  while end_idx < value.len() {
-    if let (Some(literal_value), increment) = parse_literal_token(&value[end_idx..]) {
-      trace!(
-        "literal match for token {} end index is now {}",
-        literal_value,
-        end_idx + increment
-      );
-      tokens.push(literal_value);
-      end_idx += increment;
-    } else if let (Some(compound_value), increment) =
-      parse_compound_token(value.get(end_idx..end_idx + 2))
+    let start = self.position;
+    let token = match byte {
+      b'a'..=b'z' | b'A'..=b'Z' | b'_' => self.word(),
+      b'0'..=b'9' => self.number()?,
+      b'.' if self.peek(1).is_ascii_digit() => self.number()?,
+      b'\'' | b'"' => {
+        let (token, len) = parse_quoted_token(&self.source[start..])
+          .map_err(|err| err.with_span(self.span_from(start, self.line_end())))?;
+        self.position += len;
+        token
+      }
+      _ => self.punctuator()?,
+    };
+    let span = self.span_from(start, self.position);
+    trace!("lexed {} at {}", token, span);
+    Ok(Some(SpannedToken {
+      token,
+      span,
+      first_on_line,
+      whitespace_before,
+    }))
+  }
+
+  fn bytes(&self) -> &'a [u8] {
+    self.source.as_bytes()
+  }
+
+  /// Byte `offset` places after the next one, 0 past the end of the source.
+  fn peek(&self, offset: usize) -> u8 {
+    self
+      .bytes()
+      .get(self.position + offset)
+      .copied()
+      .unwrap_or(0)
+  }
+
+  /// Span from the byte offset `start` on the current line up to `end`.
+  fn span_from(&self, start: usize, end: usize) -> Span {
+    Span::new(
+      self.file,
+      start,
+      end,
+      self.line,
+      start - self.line_start + 1,
+    )
+  }
+
+  /// Byte offset of the end of the current line.
+  fn line_end(&self) -> usize {
+    self.source[self.position..]
+      .find('\n')
+      .map_or(self.source.len(), |len| self.position + len)
+  }
+
+  /// Move past `len` bytes which may contain newlines.
+  fn advance(&mut self, len: usize) {
+    let skipped = &self.bytes()[self.position..self.position + len];
+    for (idx, _) in skipped
+      .iter()
+      .enumerate()
+      .filter(|(_, &byte)| byte == b'\n')
     {
-      trace!(
-        "compound match for token {} end index is now {}",
-        compound_value,
-        end_idx
-      );
-      tokens.push(compound_value);
-      end_idx += increment;
-    } else {
-      let (simple_token, increment) = try_parse_simple_token(byte_array[end_idx] as char)?;
-      trace!(
-        "simple match for token {:?} end index is now {}",
-        simple_token,
-        end_idx
-      );
-      if let Some(simple_value) = simple_token {
-        tokens.push(simple_value);
+      self.line += 1;
+      self.line_start = self.position + idx + 1;
+      self.first_on_line = true;
+    }
+    self.position += len;
+  }
+
+  /// Skip whitespace and line splices along with `//` comments running up to
+  /// the end of the line and `/* */` comments which may span lines.
+  fn skip_trivia(&mut self) -> Result<(), SyntaxError> {
+    loop {
+      match (self.peek(0), self.peek(1)) {
+        (b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c, _) => self.advance(1),
+        // a backslash ending a line joins it with the next one
+        (b'\\', b'\n') => self.splice(2),
+        (b'\\', b'\r') if self.peek(2) == b'\n' => self.splice(3),
+        (b'/', b'/') => self.position = self.line_end(),
+        (b'/', b'*') => match self.source[self.position + 2..].find("*/") {
+          Some(len) => self.advance(len + 4),
+          None => {
+            return Err(
+              SyntaxError::new_lex_error("unterminated comment".to_string())
+                .with_span(self.span_from(self.position, self.position + 2))
+                .with_code("E0104")
+                .with_help("close the comment with `*/`".to_string()),
+            )
+          }
+        },
+        _ => return Ok(()),
       }
-      end_idx += increment;
     }
   }
 
-  Ok(tokens)
+  /// Move past a backslash and the `len - 1` bytes of the newline after it,
+  /// which leave the next token on the same logical line.
+  fn splice(&mut self, len: usize) {
+    self.position += len;
+    self.line += 1;
+    self.line_start = self.position;
+  }
+
+  /// Move past the bytes matching `predicate`.
+  fn skip_while(&mut self, predicate: impl Fn(u8) -> bool) {
+    while self.position < self.source.len() && predicate(self.peek(0)) {
+      self.position += 1;
+    }
+  }
+
+  /// Read a keyword or an identifier.
+  fn word(&mut self) -> Token {
+    let start = self.position;
+    self.skip_while(|byte| byte.is_ascii_alphanumeric() || byte == b'_');
+    let word = &self.source[start..self.position];
+    Token::keywords(word).unwrap_or_else(|| Token::Identifier(Rc::new(word.to_owned())))
+  }
+
+  /// Read an integer or floating literal, which is floating when it has a
+  /// fraction or an exponent, `1.` and `.5` included.
+  fn number(&mut self) -> Result<Token, SyntaxError> {
+    let start = self.position;
+    // a prefix needs a digit after it, `0x` alone is `0` with a bad suffix
+    let radix = match (self.peek(0), self.peek(1), self.peek(2)) {
+      (b'0', b'x' | b'X', digit) if digit.is_ascii_hexdigit() => 16,
+      (b'0', b'b' | b'B', b'0' | b'1') => 2,
+      _ => 10,
+    };
+    if radix != 10 {
+      self.position += 2;
+      // digits out of range for binary are reported by `integer`
+      self.skip_while(|byte| byte.is_ascii_hexdigit());
+      return self.integer(start, start + 2, radix);
+    }
+
+    self.skip_while(|byte| byte.is_ascii_digit());
+    let mut floating = false;
+    if self.peek(0) == b'.' {
+      floating = true;
+      self.position += 1;
+      self.skip_while(|byte| byte.is_ascii_digit());
+    }
+    // an exponent needs digits, otherwise the `e` starts a suffix
+    let sign = matches!(self.peek(1), b'+' | b'-') as usize;
+    if matches!(self.peek(0), b'e' | b'E') && self.peek(1 + sign).is_ascii_digit() {
+      floating = true;
+      self.position += 1 + sign;
+      self.skip_while(|byte| byte.is_ascii_digit());
+    }
+    if !floating {
+      // a leading zero makes the literal octal, `0` itself included
+      let radix = if self.bytes()[start] == b'0' { 8 } else { 10 };
+      return self.integer(start, start, radix);
+    }
+
+    let digits = &self.source[start..self.position];
+    let token = if matches!(self.peek(0), b'f' | b'F') {
+      self.position += 1;
+      Token::FloatLiteral(digits.parse::<f32>().expect("floating parse") as f64)
+    } else {
+      Token::DoubleLiteral(digits.parse().expect("floating parse"))
+    };
+    let suffix = self.suffix();
+    if !suffix.is_empty() {
+      return Err(self.invalid_number(
+        start,
+        format!("invalid suffix {} on floating literal", suffix),
+      ));
+    }
+    Ok(token)
+  }
+
+  /// Read the suffix of a numeric literal, the letters, digits and dots
+  /// directly following it.
+  fn suffix(&mut self) -> &'a str {
+    let start = self.position;
+    self.skip_while(|byte| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'.');
+    &self.source[start..self.position]
+  }
+
+  /// Error for the malformed numeric literal starting at `start`.
+  fn invalid_number(&self, start: usize, message: String) -> SyntaxError {
+    SyntaxError::new_lex_error(message)
+      .with_span(self.span_from(start, self.position))
+      .with_code("E0105")
+  }
+
+  /// Finish the integer literal starting at `start` whose digits in base
+  /// `radix` start at `digits_start` and end at the current position, giving
+  /// it the first type allowed by its suffix and base which can hold it.
+  fn integer(
+    &mut self,
+    start: usize,
+    digits_start: usize,
+    radix: u32,
+  ) -> Result<Token, SyntaxError> {
+    let digits = &self.source[digits_start..self.position];
+    let suffix = self.suffix();
+    let Some((unsigned, long)) = integer_suffix(suffix) else {
+      return Err(self.invalid_number(
+        start,
+        format!("invalid suffix {} on integer literal", suffix),
+      ));
+    };
+    if let Some(digit) = digits.chars().find(|digit| !digit.is_digit(radix)) {
+      let base = match radix {
+        2 => "binary",
+        8 => "octal",
+        _ => "decimal",
+      };
+      return Err(self.invalid_number(
+        start,
+        format!("invalid digit {} in {} literal", digit, base),
+      ));
+    }
+
+    let too_large = |help: &str| {
+      SyntaxError::new_lex_error("integer literal is too large for its type".to_string())
+        .with_span(self.span_from(start, self.position))
+        .with_code("E0106")
+        .with_help(help.to_string())
+    };
+    let value = u64::from_str_radix(digits, radix)
+      .map_err(|_| too_large("the largest integer type is unsigned long, 64 bits wide"))?;
+    // decimal literals only become unsigned when asked to
+    let kinds: &[IntegerKind] = match (unsigned, long, radix == 10) {
+      (false, false, true) => &[IntegerKind::Int, IntegerKind::Long],
+      (false, false, false) => &[
+        IntegerKind::Int,
+        IntegerKind::UInt,
+        IntegerKind::Long,
+        IntegerKind::ULong,
+      ],
+      (false, true, true) => &[IntegerKind::Long],
+      (false, true, false) => &[IntegerKind::Long, IntegerKind::ULong],
+      (true, false, _) => &[IntegerKind::UInt, IntegerKind::ULong],
+      (true, true, _) => &[IntegerKind::ULong],
+    };
+    let kind = kinds
+      .iter()
+      .find(|kind| value <= kind.max())
+      .ok_or_else(|| too_large("add a `u` suffix to make it unsigned long"))?;
+    Ok(Token::Integer(value as i64, *kind))
+  }
+
+  /// Read an operator or punctuation character, the longest one matching.
+  fn punctuator(&mut self) -> Result<Token, SyntaxError> {
+    let compound = |op| Token::CompoundAssignment(op);
+    let (token, len) = match (self.peek(0), self.peek(1), self.peek(2)) {
+      (b'.', b'.', b'.') => (Token::Ellipsis, 3),
+      (b'<', b'<', b'=') => (compound(BinaryOperator::ShiftLeft), 3),
+      (b'>', b'>', b'=') => (compound(BinaryOperator::ShiftRight), 3),
+      (b'#', b'#', _) => (Token::HashHash, 2),
+      (b'<', b'<', _) => (Token::ShiftLeft, 2),
+      (b'>', b'>', _) => (Token::ShiftRight, 2),
+      (b'+', b'+', _) => (Token::Increment, 2),
+      (b'-', b'-', _) => (Token::Decrement, 2),
+      (b'-', b'>', _) => (Token::Arrow, 2),
+      (b'&', b'&', _) => (Token::And, 2),
+      (b'|', b'|', _) => (Token::Or, 2),
+      (b'=', b'=', _) => (Token::Equal, 2),
+      (b'!', b'=', _) => (Token::NotEqual, 2),
+      (b'<', b'=', _) => (Token::LessThanOrEqual, 2),
+      (b'>', b'=', _) => (Token::GreaterThanOrEqual, 2),
+      (b'+', b'=', _) => (compound(BinaryOperator::Addition), 2),
+      (b'-', b'=', _) => (compound(BinaryOperator::Minus), 2),
+      (b'*', b'=', _) => (compound(BinaryOperator::Multiplication), 2),
+      (b'/', b'=', _) => (compound(BinaryOperator::Division), 2),
+      (b'%', b'=', _) => (compound(BinaryOperator::Remainder), 2),
+      (b'&', b'=', _) => (compound(BinaryOperator::BitwiseAnd), 2),
+      (b'|', b'=', _) => (compound(BinaryOperator::BitwiseOr), 2),
+      (b'^', b'=', _) => (compound(BinaryOperator::BitwiseXor), 2),
+      (b'{', ..) => (Token::OpenBrace, 1),
+      (b'}', ..) => (Token::CloseBrace, 1),
+      (b'(', ..) => (Token::OpenParenthesis, 1),
+      (b')', ..) => (Token::CloseParenthesis, 1),
+      (b'[', ..) => (Token::OpenBracket, 1),
+      (b']', ..) => (Token::CloseBracket, 1),
+      (b';', ..) => (Token::SemiColon, 1),
+      (b',', ..) => (Token::Comma, 1),
+      (b'-', ..) => (Token::Negation, 1),
+      (b'~', ..) => (Token::BitwiseComplement, 1),
+      (b'!', ..) => (Token::LogicalNegation, 1),
+      (b'+', ..) => (Token::Addition, 1),
+      (b'*', ..) => (Token::Multiplication, 1),
+      (b'/', ..) => (Token::Division, 1),
+      (b'%', ..) => (Token::Remainder, 1),
+      (b'&', ..) => (Token::Ampersand, 1),
+      (b'|', ..) => (Token::Pipe, 1),
+      (b'^', ..) => (Token::Caret, 1),
+      (b'<', ..) => (Token::LessThan, 1),
+      (b'>', ..) => (Token::GreaterThan, 1),
+      (b'=', ..) => (Token::Assignment, 1),
+      (b'?', ..) => (Token::QuestionMark, 1),
+      (b'#', ..) => (Token::Hash, 1),
+      (b':', ..) => (Token::Colon, 1),
+      (b'.', ..) => (Token::Dot, 1),
+      _ => {
+        // report the whole character, which may take several bytes
+        let character = self.source[self.position..]
+          .chars()
+          .next()
+          .unwrap_or_default();
+        return Err(
+          SyntaxError::new_lex_error(format!("unidentifiable character {}", character))
+            .with_span(self.span_from(self.position, self.position + character.len_utf8()))
+            .with_code("E0101"),
+        );
+      }
+    };
+    self.position += len;
+    Ok(token)
+  }
+}
+
+/// Whether the integer suffix `suffix` makes a literal unsigned and long,
+/// `None` when it is not one of `u`, `l` and `ll` in either case, a `u`
+/// combined with the others before or after them.
+fn integer_suffix(suffix: &str) -> Option<(bool, bool)> {
+  let rest = suffix
+    .strip_prefix(['u', 'U'])
+    .or_else(|| suffix.strip_suffix(['u', 'U']));
+  let long = match rest.unwrap_or(suffix) {
+    "" => false,
+    "l" | "L" | "ll" | "LL" => true,
+    _ => return None,
+  };
+  Some((rest.is_some(), long))
+}
+
+impl Iterator for Lexer<'_> {
+  type Item = Result<SpannedToken, SyntaxError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.next_token().transpose()
+  }
+}
+
+/// Tokenize the whole source text of `file`.
+pub fn string_tokenizer(value: &str, file: FileId) -> Result<Vec<SpannedToken>, SyntaxError> {
+  Lexer::new(value, file).collect()
 }
@@ -0,0 +1,133 @@
+//! A C compiler producing x86-64 assembly in AT&T syntax.
+//!
+//! [`compile`] runs the whole pipeline over a single source file, the stage
+//! functions [`tokenize`], [`parse`], [`analyze`] and [`generate`] expose each
+//! step for tools that only need part of it.
+//!
+//! ```no_run
+//! use compiler::{compile, CompileOptions};
+//!
+//! let output = compile("int main() { return 2; }", &CompileOptions::new("main.c"));
+//! print!("{}", output.unwrap().assembly);
+//! ```
+
+use std::path::PathBuf;
+
+use tracing::debug;
+
+pub mod ast;
+pub mod codegen;
+pub mod lex;
+pub mod preprocess;
+pub mod semantic;
+pub mod utility;
+
+use ast::{model::Program, Scanner};
+use codegen::CodeGenerator;
+use lex::SpannedToken;
+use preprocess::{MacroOption, Preprocessor};
+use semantic::{Analyzer, SymbolTable};
+use utility::{Diagnostics, FileId, SourceMap, DEFAULT_ERROR_LIMIT};
+
+pub struct CompileOptions {
+  /// Name of the source file used when reporting errors.
+  pub file_name: String,
+  /// Number of errors reported by a stage before it gives up.
+  pub error_limit: usize,
+  /// Directories searched for included headers before the system ones.
+  pub include_paths: Vec<PathBuf>,
+  /// `-D` and `-U` options, carried out in order before the first line.
+  pub macros: Vec<MacroOption>,
+}
+
+impl CompileOptions {
+  pub fn new(file_name: impl Into<String>) -> Self {
+    Self {
+      file_name: file_name.into(),
+      error_limit: DEFAULT_ERROR_LIMIT,
+      include_paths: vec![],
+      macros: vec![],
+    }
+  }
+
+  pub fn with_error_limit(mut self, error_limit: usize) -> Self {
+    self.error_limit = error_limit;
+    self
+  }
+
+  pub fn with_include_paths(mut self, include_paths: Vec<PathBuf>) -> Self {
+    self.include_paths = include_paths;
+    self
+  }
+
+  pub fn with_macros(mut self, macros: Vec<MacroOption>) -> Self {
+    self.macros = macros;
+    self
+  }
+}
+
+/// Result of compiling a source file.
+pub struct Output {
+  /// Assembly ready to be handed to an assembler such as `gcc -c`.
+  pub assembly: String,
+}
+
+/// Compile `source` into assembly.
+///
+/// Spans of the returned errors point into `source`, registered as the first
+/// file of a [`SourceMap`], or into the headers it includes registered after
+/// it, so use [`compile_file`] to render errors raised inside headers.
+pub fn compile(source: &str, options: &CompileOptions) -> Result<Output, Diagnostics> {
+  let mut sources = SourceMap::new();
+  let file = sources.add_file(options.file_name.clone(), source.to_string());
+  compile_file(&mut sources, file, options)
+}
+
+/// Compile a file already registered in `sources`, adding the headers it
+/// includes to them.
+pub fn compile_file(
+  sources: &mut SourceMap,
+  file: FileId,
+  options: &CompileOptions,
+) -> Result<Output, Diagnostics> {
+  let tokens = tokenize(sources, file, options)?;
+  let mut program = parse(&tokens, options)?;
+  let symbols = analyze(&mut program, options)?;
+  let assembly = generate(&program, &symbols)?;
+  Ok(Output { assembly })
+}
+
+/// Split `file` into tokens, carry out its preprocessing directives and
+/// expand its macros, registering the headers it includes in `sources`.
+pub fn tokenize(
+  sources: &mut SourceMap,
+  file: FileId,
+  options: &CompileOptions,
+) -> Result<Vec<SpannedToken>, Diagnostics> {
+  debug!("lexing {}", sources.name(file));
+  let preprocessor = Preprocessor::new(sources)
+    .with_include_paths(options.include_paths.clone())
+    .with_macros(options.macros.clone());
+  Ok(preprocessor.run(file)?)
+}
+
+/// Build the syntax tree of a translation unit.
+pub fn parse(tokens: &[SpannedToken], options: &CompileOptions) -> Result<Program, Diagnostics> {
+  let mut scanner = Scanner::new(tokens);
+  ast::parse_program(&mut scanner, options.error_limit)
+}
+
+/// Run the semantic checks, renaming the local variables of `program` to
+/// unique names and returning the symbols with static storage it declares.
+pub fn analyze(
+  program: &mut Program,
+  options: &CompileOptions,
+) -> Result<SymbolTable, Diagnostics> {
+  Analyzer::new(options.error_limit).check(program)
+}
+
+/// Generate assembly for a program which passed [`analyze`].
+pub fn generate(program: &Program, symbols: &SymbolTable) -> Result<String, Diagnostics> {
+  let mut codegenerator = CodeGenerator::new();
+  Ok(codegenerator.generate(program, symbols)?)
+}
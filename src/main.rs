@@ -1,63 +1,359 @@
 use std::{
-  env,
-  fs::File,
-  io::{BufRead, BufReader, Write},
-  path::Path,
-  process::Command,
+  env, fs,
+  path::{Path, PathBuf},
+  process::{self, Command, ExitCode},
 };
 
-mod lex;
+use compiler::{
+  analyze, generate, parse,
+  preprocess::MacroOption,
+  tokenize,
+  utility::{DiagnosticRenderer, Diagnostics, FileId, SourceMap, DEFAULT_ERROR_LIMIT},
+  CompileOptions,
+};
+use tracing::{info, Level};
+
+static USAGE: &str = "\
+usage: compiler [options] <file>...
+
+options:
+  -o <file>   write the output to <file>
+  -I <dir>    search <dir> for included headers
+  -D <def>    define a macro, <def> being <name>=<value> or <name> for 1
+  -U <name>   undefine the macro <name>
+  -S          stop after generating assembly, writing <file>.s
+  -c          stop after assembling, writing <file>.o
+  --lex       stop after lexing and print the tokens
+  --parse     stop after parsing and print the syntax tree
+  --codegen   stop after code generation and print the assembly
+  -fmax-errors=<n>
+              stop reporting errors of a stage after <n> of them, 20 by default
+              and 0 for no limit
+  -v          log compiler progress, repeat for more detail
+  -h, --help  print this message
+
+files not ending in .c are passed on to the linker. without -o a single
+file is linked into an executable named after it, several into a.out, as is
+a single file without an extension.
+";
+
+/// Exit code for invalid command line arguments.
+const USAGE_ERROR: u8 = 2;
+
+/// Last step the driver runs for every input file, in pipeline order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Stop {
+  Lex,
+  Parse,
+  Codegen,
+  Assembly,
+  Object,
+  Executable,
+}
+
+struct Options {
+  inputs: Vec<PathBuf>,
+  output: Option<PathBuf>,
+  include_paths: Vec<PathBuf>,
+  /// `-D` and `-U` options in the order they were given.
+  macros: Vec<MacroOption>,
+  stop: Stop,
+  /// Number of errors a stage reports before giving up.
+  error_limit: usize,
+  verbosity: usize,
+  help: bool,
+}
+
+impl Options {
+  fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+    let mut options = Options {
+      inputs: vec![],
+      output: None,
+      include_paths: vec![],
+      macros: vec![],
+      stop: Stop::Executable,
+      error_limit: DEFAULT_ERROR_LIMIT,
+      verbosity: 0,
+      help: false,
+    };
+
+    while let Some(arg) = args.next() {
+      let stop = match arg.as_str() {
+        "-h" | "--help" => {
+          options.help = true;
+          continue;
+        }
+        "-o" => {
+          let output = args.next().ok_or("missing file name after -o")?;
+          options.output = Some(PathBuf::from(output));
+          continue;
+        }
+        "-I" => {
+          let path = args.next().ok_or("missing directory after -I")?;
+          options.include_paths.push(PathBuf::from(path));
+          continue;
+        }
+        "-D" => {
+          let definition = args.next().ok_or("missing macro name after -D")?;
+          options.macros.push(MacroOption::define(&definition));
+          continue;
+        }
+        "-U" => {
+          let name = args.next().ok_or("missing macro name after -U")?;
+          options.macros.push(MacroOption::Undefine(name));
+          continue;
+        }
+        "-S" => Stop::Assembly,
+        "-c" => Stop::Object,
+        "--lex" => Stop::Lex,
+        "--parse" => Stop::Parse,
+        "--codegen" => Stop::Codegen,
+        flag if flag.starts_with("-o") => {
+          options.output = Some(PathBuf::from(&flag[2..]));
+          continue;
+        }
+        flag if flag.starts_with("-I") => {
+          options.include_paths.push(PathBuf::from(&flag[2..]));
+          continue;
+        }
+        flag if flag.starts_with("-D") => {
+          options.macros.push(MacroOption::define(&flag[2..]));
+          continue;
+        }
+        flag if flag.starts_with("-U") => {
+          options
+            .macros
+            .push(MacroOption::Undefine(flag[2..].to_string()));
+          continue;
+        }
+        flag if flag.starts_with("-fmax-errors=") => {
+          let limit = &flag["-fmax-errors=".len()..];
+          // like gcc 0 lifts the limit
+          options.error_limit = match limit.parse() {
+            Ok(0) => usize::MAX,
+            Ok(limit) => limit,
+            Err(_) => return Err(format!("invalid error limit {}", limit)),
+          };
+          continue;
+        }
+        flag if flag.len() > 1 && flag[1..].chars().all(|c| c == 'v') => {
+          options.verbosity += flag.len() - 1;
+          continue;
+        }
+        flag if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+        _ => {
+          options.inputs.push(PathBuf::from(arg));
+          continue;
+        }
+      };
+      // like cc the earliest stage asked for wins
+      options.stop = options.stop.min(stop);
+    }
 
-mod ast;
-use ast::Scanner;
-use itertools::{multipeek, Itertools};
+    if options.help {
+      return Ok(options);
+    }
+    if options.inputs.is_empty() {
+      return Err("no input files".to_string());
+    }
+    let sources = options.sources().count();
+    if options.stop != Stop::Executable && sources != options.inputs.len() {
+      return Err("only .c files can be compiled without linking".to_string());
+    }
+    if options.output.is_some() && options.stop != Stop::Executable && sources > 1 {
+      return Err("cannot use -o with -S or -c and several input files".to_string());
+    }
+    Ok(options)
+  }
 
-mod codegen;
-use codegen::CodeGenerator;
-use tracing::{debug, info};
-use utility::SyntaxError;
+  /// Inputs which have to be compiled, anything else goes to the linker.
+  fn sources(&self) -> impl Iterator<Item = &PathBuf> {
+    self.inputs.iter().filter(|path| is_source(path))
+  }
 
-mod utility;
+  fn log_level(&self) -> Level {
+    match self.verbosity {
+      0 => Level::WARN,
+      1 => Level::INFO,
+      2 => Level::DEBUG,
+      _ => Level::TRACE,
+    }
+  }
 
-fn lex(path: &str) -> Result<Vec<lex::Token>, SyntaxError> {
-  let f = File::open(path).expect("unable to open file");
-  let f = BufReader::new(f);
-  let mut token_vector = Vec::new();
-  for line in f.lines() {
-    let line = line.unwrap();
-    debug!("lexing line {}", line);
-    let mut tokens = lex::string_tokenizer(&line)?;
-    token_vector.append(&mut tokens);
+  /// Where the result of compiling `input` is written when stopping early.
+  fn output_for(&self, input: &Path, extension: &str) -> PathBuf {
+    match &self.output {
+      Some(output) => output.clone(),
+      None => input.with_extension(extension),
+    }
   }
 
-  Ok(token_vector)
+  fn executable(&self) -> PathBuf {
+    match (&self.output, self.inputs.as_slice()) {
+      (Some(output), _) => output.clone(),
+      // linking must not overwrite an input without an extension
+      (None, [input]) if input.extension().is_some() => input.with_extension(""),
+      (None, _) => PathBuf::from("a.out"),
+    }
+  }
 }
 
-fn main() -> Result<(), SyntaxError> {
-  tracing_subscriber::fmt::fmt().init();
+fn is_source(path: &Path) -> bool {
+  path.extension().is_some_and(|extension| extension == "c")
+}
 
-  let default_filename = "./data/stage_5/valid/exp_return_val.c".to_string();
-  info!("default file path {}", default_filename);
+/// Run the compiler over `file` up to `stop`, returning the generated assembly
+/// unless an earlier stage printed its result instead.
+fn compile(
+  sources: &mut SourceMap,
+  file: FileId,
+  options: &Options,
+) -> Result<Option<String>, Diagnostics> {
+  let stop = options.stop;
+  let options = CompileOptions::new(sources.name(file))
+    .with_error_limit(options.error_limit)
+    .with_include_paths(options.include_paths.clone())
+    .with_macros(options.macros.clone());
+  let tokens = tokenize(sources, file, &options)?;
+  if stop == Stop::Lex {
+    for token in &tokens {
+      println!("{}", token.token);
+    }
+    return Ok(None);
+  }
 
-  let path_value = env::args().get(1..2).next().unwrap_or(default_filename);
-  let path = Path::new(&path_value);
-  info!("running compiler for file {}", path_value);
+  let mut program = parse(&tokens, &options)?;
+  if stop == Stop::Parse {
+    print!("{}", program);
+    return Ok(None);
+  }
 
-  let tokens = lex(&path_value)?;
-  let mut scanner = Scanner::new(multipeek(tokens.iter()));
-  let program = ast::parse_program(&mut scanner)?;
-  let mut codegenerator = CodeGenerator::new();
-  let assembly = codegenerator.generate(&program)?;
-  let filename = path.file_stem().unwrap().to_str().unwrap();
-  let mut file = File::create(path.with_file_name(format!("{}.s", filename))).unwrap();
-  file.write_all(assembly.as_bytes()).unwrap();
+  let symbols = analyze(&mut program, &options)?;
+  let assembly = generate(&program, &symbols)?;
+  if stop == Stop::Codegen {
+    print!("{}", assembly);
+    return Ok(None);
+  }
 
-  Command::new("gcc")
-    .arg(path.with_file_name(format!("{}.s", filename)))
-    .arg("-o")
-    .arg(path.with_file_name(format!("{}", filename)))
-    .output()
-    .expect("assembly to elf failed");
+  Ok(Some(assembly))
+}
 
+/// Run gcc with `args`, turning a failure into the exit code to report.
+fn gcc(args: &[&Path]) -> Result<(), ExitCode> {
+  info!("running gcc {:?}", args);
+  match Command::new("gcc").args(args).status() {
+    Ok(status) if status.success() => Ok(()),
+    Ok(status) => Err(ExitCode::from(
+      status.code().unwrap_or(1).clamp(1, 255) as u8
+    )),
+    Err(err) => {
+      eprintln!("error: unable to run gcc: {}", err);
+      Err(ExitCode::FAILURE)
+    }
+  }
+}
+
+fn write(path: &Path, contents: &str) -> Result<(), ExitCode> {
+  fs::write(path, contents).map_err(|err| {
+    eprintln!("error: unable to write {}: {}", path.display(), err);
+    ExitCode::FAILURE
+  })
+}
+
+/// Compile every input and hand the results to gcc, recording the files to
+/// remove once the driver is done in `temporaries`.
+fn run(options: &Options, temporaries: &mut Vec<PathBuf>) -> Result<(), ExitCode> {
+  let mut sources = SourceMap::new();
+  let mut linker_inputs = vec![];
+  let mut failed = false;
+
+  for (idx, input) in options.inputs.iter().enumerate() {
+    if !is_source(input) {
+      linker_inputs.push(input.clone());
+      continue;
+    }
+
+    info!("running compiler for file {}", input.display());
+    let source = match fs::read_to_string(input) {
+      Ok(source) => source,
+      Err(err) => {
+        eprintln!("error: unable to read {}: {}", input.display(), err);
+        failed = true;
+        continue;
+      }
+    };
+    let file = sources.add_file(input.display().to_string(), source);
+
+    let assembly = match compile(&mut sources, file, options) {
+      Ok(Some(assembly)) => assembly,
+      Ok(None) => continue,
+      Err(err) => {
+        let mut renderer = DiagnosticRenderer::new(&sources);
+        if env::var_os("NO_COLOR").is_some() {
+          renderer = renderer.with_colour(false);
+        }
+        eprint!("{}", renderer.render_all(&err));
+        failed = true;
+        continue;
+      }
+    };
+
+    if options.stop == Stop::Assembly {
+      write(&options.output_for(input, "s"), &assembly)?;
+      continue;
+    }
+
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let assembly_path = env::temp_dir().join(format!("{}-{}-{}.s", stem, process::id(), idx));
+    temporaries.push(assembly_path.clone());
+    write(&assembly_path, &assembly)?;
+
+    if options.stop == Stop::Object {
+      let object = options.output_for(input, "o");
+      gcc(&[Path::new("-c"), &assembly_path, Path::new("-o"), &object])?;
+    } else {
+      linker_inputs.push(assembly_path);
+    }
+  }
+
+  if failed {
+    return Err(ExitCode::FAILURE);
+  }
+  if options.stop == Stop::Executable {
+    let executable = options.executable();
+    let mut args: Vec<&Path> = linker_inputs.iter().map(PathBuf::as_path).collect();
+    args.extend([Path::new("-o"), &executable]);
+    gcc(&args)?;
+  }
   Ok(())
 }
+
+fn main() -> ExitCode {
+  let options = match Options::parse(env::args().skip(1)) {
+    Ok(options) => options,
+    Err(err) => {
+      eprint!("error: {}\n\n{}", err, USAGE);
+      return ExitCode::from(USAGE_ERROR);
+    }
+  };
+  if options.help {
+    print!("{}", USAGE);
+    return ExitCode::SUCCESS;
+  }
+
+  tracing_subscriber::fmt::fmt()
+    .with_max_level(options.log_level())
+    .with_writer(std::io::stderr)
+    .init();
+
+  let mut temporaries = vec![];
+  let result = run(&options, &mut temporaries);
+  for path in temporaries {
+    let _ = fs::remove_file(path);
+  }
+
+  match result {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(code) => code,
+  }
+}
@@ -0,0 +1,546 @@
+//! Definition and expansion of macros.
+//!
+//! Expansion follows Dave Prosser's algorithm: every token remembers the
+//! macros it came out of in its hide set, and an identifier naming one of
+//! them is painted blue, never to be expanded again.
+
+use std::rc::Rc;
+
+use crate::{
+  lex::{self, SpannedToken, Token},
+  utility::{ExpansionId, Span, SyntaxError},
+};
+
+use super::{macro_name, Preprocessor};
+
+/// Name of the files holding the text of pasted and stringified tokens.
+const SCRATCH_SPACE: &str = "<scratch space>";
+
+/// Parameter standing for the variable arguments of a variadic macro.
+const VARIADIC_PARAMETER: &str = "__VA_ARGS__";
+
+/// A `-D` or `-U` command line option, carried out before the first line of
+/// the file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MacroOption {
+  /// `-D NAME=VALUE`, a bare `-D NAME` defines `NAME` as `1`.
+  Define(String, String),
+  /// `-U NAME`.
+  Undefine(String),
+}
+
+impl MacroOption {
+  /// Option for the argument of `-D`.
+  pub fn define(argument: &str) -> Self {
+    match argument.split_once('=') {
+      Some((name, value)) => MacroOption::Define(name.to_string(), value.to_string()),
+      None => MacroOption::Define(argument.to_string(), "1".to_string()),
+    }
+  }
+
+  /// Directive with the same effect as the option.
+  pub(super) fn directive(&self) -> String {
+    match self {
+      MacroOption::Define(name, value) => format!("#define {} {}\n", name, value),
+      MacroOption::Undefine(name) => format!("#undef {}\n", name),
+    }
+  }
+}
+
+pub(super) struct Macro {
+  /// Parameters of a function-like macro, ending with `__VA_ARGS__` when it
+  /// is variadic, `None` for an object-like macro.
+  parameters: Option<Vec<Rc<String>>>,
+  variadic: bool,
+  replacement: Vec<SpannedToken>,
+}
+
+impl Macro {
+  /// Index of the parameter named by `token`.
+  fn parameter(&self, token: &SpannedToken) -> Option<usize> {
+    let Token::Identifier(name) = &token.token else {
+      return None;
+    };
+    self
+      .parameters
+      .as_ref()?
+      .iter()
+      .position(|parameter| parameter == name)
+  }
+
+  /// Whether `other` has the same parameters and replacement list, the only
+  /// way a macro may be defined again.
+  fn same_as(&self, other: &Macro) -> bool {
+    self.parameters == other.parameters
+      && self.replacement.len() == other.replacement.len()
+      && self
+        .replacement
+        .iter()
+        .zip(&other.replacement)
+        .all(|(token, other)| token.token == other.token)
+  }
+}
+
+/// A token along with the hide set of macros it came out of.
+#[derive(Debug, Clone)]
+pub(super) struct ExpandedToken {
+  pub token: SpannedToken,
+  hideset: Vec<Rc<String>>,
+}
+
+impl From<SpannedToken> for ExpandedToken {
+  fn from(token: SpannedToken) -> Self {
+    Self {
+      token,
+      hideset: vec![],
+    }
+  }
+}
+
+impl Preprocessor<'_> {
+  /// Carry out the `#define` at `directive` made of `arguments`.
+  pub(super) fn define(
+    &mut self,
+    directive: Span,
+    arguments: &[SpannedToken],
+  ) -> Result<(), SyntaxError> {
+    let name = macro_name(directive, arguments)?;
+    let definition = definition(&arguments[0], &arguments[1..])?;
+    if let Some(previous) = self.macros.get(&name) {
+      if !previous.same_as(&definition) {
+        return Err(
+          SyntaxError::new_preprocess_error(format!("macro {} redefined", name))
+            .with_span(arguments[0].span)
+            .with_code("E0505")
+            .with_help(format!(
+              "remove the previous definition with `#undef {}`",
+              name
+            )),
+        );
+      }
+    }
+    self.macros.insert(name, Rc::new(definition));
+    Ok(())
+  }
+
+  /// Carry out the `#undef` at `directive` made of `arguments`.
+  pub(super) fn undefine(
+    &mut self,
+    directive: Span,
+    arguments: &[SpannedToken],
+  ) -> Result<(), SyntaxError> {
+    let name = macro_name(directive, arguments)?;
+    self.macros.remove(&name);
+    Ok(())
+  }
+
+  /// Expand the macros invoked in `tokens`, rescanning every replacement
+  /// along with the tokens following it.
+  pub(super) fn expand(
+    &mut self,
+    tokens: Vec<ExpandedToken>,
+  ) -> Result<Vec<ExpandedToken>, SyntaxError> {
+    // tokens still to scan, the next one last
+    let mut input: Vec<_> = tokens.into_iter().rev().collect();
+    let mut output = vec![];
+
+    while let Some(token) = input.pop() {
+      let Some((name, definition)) = self.invoked(&token, input.last()) else {
+        output.push(token);
+        continue;
+      };
+      let whitespace_before = token.token.whitespace_before;
+
+      let span = token.token.span;
+
+      let (arguments, mut hideset, invocation) = match definition.parameters {
+        None => (vec![], token.hideset, span),
+        Some(_) => {
+          input.pop();
+          let (arguments, close) = self.arguments(&mut input, &name, &definition, &token)?;
+          // the macro stays usable for tokens after the invocation
+          let mut hideset = token.hideset;
+          hideset.retain(|name| close.hideset.contains(name));
+          let invocation = match close.token.span {
+            close if close.file == span.file => span.to(close),
+            _ => span,
+          };
+          (arguments, hideset, invocation)
+        }
+      };
+      let expansion = self.sources.add_expansion(name.to_string(), invocation);
+      hideset.push(name);
+
+      let mut replacement = self.substitute(&definition, &arguments, &hideset, expansion)?;
+      // the replacement is spaced from what comes before like the invocation
+      if let Some(first) = replacement.first_mut() {
+        first.token.whitespace_before = whitespace_before;
+      }
+      input.extend(replacement.into_iter().rev());
+    }
+    Ok(output)
+  }
+
+  /// Name and definition of the macro `token` invokes, where `next` is the
+  /// token after it.
+  fn invoked(
+    &self,
+    token: &ExpandedToken,
+    next: Option<&ExpandedToken>,
+  ) -> Option<(Rc<String>, Rc<Macro>)> {
+    let Token::Identifier(name) = &token.token.token else {
+      return None;
+    };
+    if token.hideset.contains(name) {
+      return None;
+    }
+    let definition = self.macros.get(name)?;
+    // the name of a function-like macro alone is left as it is
+    let called = next.is_some_and(|next| next.token.token == Token::OpenParenthesis);
+    if definition.parameters.is_some() && !called {
+      return None;
+    }
+    Some((name.clone(), definition.clone()))
+  }
+
+  /// Arguments of the invocation of `name` at `token` whose `(` was just
+  /// read from `input`, along with the `)` closing them.
+  fn arguments(
+    &self,
+    input: &mut Vec<ExpandedToken>,
+    name: &str,
+    definition: &Macro,
+    token: &ExpandedToken,
+  ) -> Result<(Vec<Vec<ExpandedToken>>, ExpandedToken), SyntaxError> {
+    let parameters = definition.parameters.as_deref().unwrap_or_default();
+    let named = parameters.len() - definition.variadic as usize;
+    let mut arguments = vec![vec![]];
+    let mut depth = 0;
+
+    let close = loop {
+      let Some(argument) = input.pop() else {
+        return Err(
+          SyntaxError::new_preprocess_error(format!(
+            "unterminated argument list invoking macro {}",
+            name
+          ))
+          .with_span(token.token.span)
+          .with_code("E0506")
+          .with_help("close the argument list with `)`".to_string()),
+        );
+      };
+      match argument.token.token {
+        Token::CloseParenthesis if depth == 0 => break argument,
+        Token::CloseParenthesis => depth -= 1,
+        Token::OpenParenthesis => depth += 1,
+        // the variable arguments keep their commas
+        Token::Comma if depth == 0 && !(definition.variadic && arguments.len() > named) => {
+          arguments.push(vec![]);
+          continue;
+        }
+        _ => {}
+      }
+      arguments.last_mut().expect("an argument").push(argument);
+    };
+
+    // `()` passes a single empty argument, which counts as none
+    if parameters.is_empty() && arguments.len() == 1 && arguments[0].is_empty() {
+      arguments.clear();
+    }
+    // the variable arguments may be left out entirely
+    if definition.variadic && arguments.len() == named {
+      arguments.push(vec![]);
+    }
+    if arguments.len() != parameters.len() {
+      return Err(
+        SyntaxError::new_preprocess_error(format!(
+          "macro {} takes {}{} argument(s) but {} were supplied",
+          name,
+          if definition.variadic { "at least " } else { "" },
+          named,
+          arguments.len()
+        ))
+        .with_span(token.token.span)
+        .with_code("E0506"),
+      );
+    }
+    Ok((arguments, close))
+  }
+
+  /// Replacement list of `definition` with its parameters replaced by
+  /// `arguments`, every token adding `hideset` to its own. Tokens not coming
+  /// from an argument are placed in `expansion`.
+  fn substitute(
+    &mut self,
+    definition: &Macro,
+    arguments: &[Vec<ExpandedToken>],
+    hideset: &[Rc<String>],
+    expansion: ExpansionId,
+  ) -> Result<Vec<ExpandedToken>, SyntaxError> {
+    let expanded_token = |token: &SpannedToken| -> ExpandedToken {
+      SpannedToken {
+        span: token.span.in_expansion(expansion),
+        ..token.clone()
+      }
+      .into()
+    };
+    let replacement = &definition.replacement;
+    // arguments are expanded once and only when not an operand of `#` or `##`
+    let mut expanded: Vec<Option<Vec<ExpandedToken>>> = vec![None; arguments.len()];
+    // `None` is a placemarker for an empty argument about to be pasted
+    let mut output: Vec<Option<ExpandedToken>> = vec![];
+
+    let mut idx = 0;
+    while let Some(token) = replacement.get(idx) {
+      let next = replacement.get(idx + 1);
+      match (&token.token, definition.parameter(token)) {
+        (Token::Hash, _) if definition.parameters.is_some() => {
+          let parameter = next
+            .and_then(|next| definition.parameter(next))
+            .expect("checked when defined");
+          let span = token.span.in_expansion(expansion);
+          output.push(Some(self.stringify(&arguments[parameter], span)?));
+          idx += 2;
+        }
+        (Token::HashHash, _) => {
+          let next = next.expect("checked when defined");
+          let mut operand = match definition.parameter(next) {
+            Some(parameter) => arguments[parameter].clone(),
+            None => vec![expanded_token(next)],
+          }
+          .into_iter();
+          let pasted = match (output.pop().flatten(), operand.next()) {
+            (Some(lhs), Some(rhs)) => {
+              Some(self.paste(&lhs, &rhs, token.span.in_expansion(expansion))?)
+            }
+            (lhs, rhs) => lhs.or(rhs),
+          };
+          output.push(pasted);
+          output.extend(operand.map(Some));
+          idx += 2;
+        }
+        // the left operand of `##` is pasted as written
+        (_, Some(parameter)) if next.is_some_and(|next| next.token == Token::HashHash) => {
+          if arguments[parameter].is_empty() {
+            output.push(None);
+          }
+          output.extend(arguments[parameter].iter().cloned().map(Some));
+          idx += 1;
+        }
+        (_, Some(parameter)) => {
+          if expanded[parameter].is_none() {
+            expanded[parameter] = Some(self.expand(arguments[parameter].clone())?);
+          }
+          output.extend(expanded[parameter].iter().flatten().cloned().map(Some));
+          idx += 1;
+        }
+        (_, None) => {
+          output.push(Some(expanded_token(token)));
+          idx += 1;
+        }
+      }
+    }
+
+    Ok(
+      output
+        .into_iter()
+        .flatten()
+        .map(|mut token| {
+          for name in hideset {
+            if !token.hideset.contains(name) {
+              token.hideset.push(name.clone());
+            }
+          }
+          token
+        })
+        .collect(),
+    )
+  }
+
+  /// String literal spelling out `tokens`, with a space between two of them
+  /// wherever there was whitespace.
+  fn stringify(
+    &mut self,
+    tokens: &[ExpandedToken],
+    span: Span,
+  ) -> Result<ExpandedToken, SyntaxError> {
+    let mut text = String::from("\"");
+    for (idx, token) in tokens.iter().enumerate() {
+      let spelling = self.spelling(&token.token);
+      if idx > 0 && token.token.whitespace_before {
+        text.push(' ');
+      }
+      match token.token.token {
+        // quotes and backslashes of literals are escaped to keep their meaning
+        Token::StringLiteral(_) | Token::CharLiteral(_) => {
+          for character in spelling.chars() {
+            if matches!(character, '"' | '\\') {
+              text.push('\\');
+            }
+            text.push(character);
+          }
+        }
+        _ => text.push_str(spelling),
+      }
+    }
+    text.push('"');
+
+    self.scratch(text, span.expansion).ok_or_else(|| {
+      SyntaxError::new_preprocess_error("stringifying formed an invalid string literal".to_string())
+        .with_span(span)
+        .with_code("E0507")
+    })
+  }
+
+  /// Token spelled like `lhs` followed by `rhs`, pasted by the `##` at `span`.
+  fn paste(
+    &mut self,
+    lhs: &ExpandedToken,
+    rhs: &ExpandedToken,
+    span: Span,
+  ) -> Result<ExpandedToken, SyntaxError> {
+    let text = format!("{}{}", self.spelling(&lhs.token), self.spelling(&rhs.token));
+    self.scratch(text.clone(), span.expansion).ok_or_else(|| {
+      SyntaxError::new_preprocess_error(format!(
+        "pasting formed `{}`, an invalid preprocessing token",
+        text
+      ))
+      .with_span(span)
+      .with_code("E0507")
+    })
+  }
+
+  /// The single token `text` is lexed into, kept in a scratch file so its
+  /// spelling can still be looked up, and placed in `expansion`.
+  fn scratch(&mut self, text: String, expansion: Option<ExpansionId>) -> Option<ExpandedToken> {
+    let file = self.sources.add_file(SCRATCH_SPACE.to_string(), text);
+    match lex::string_tokenizer(self.sources.source(file), file).as_deref() {
+      Ok([token]) => Some(
+        SpannedToken {
+          span: Span {
+            expansion,
+            ..token.span
+          },
+          first_on_line: false,
+          ..token.clone()
+        }
+        .into(),
+      ),
+      _ => None,
+    }
+  }
+}
+
+/// Macro defined by `tokens`, the rest of the `#define` after `name`.
+fn definition(name: &SpannedToken, tokens: &[SpannedToken]) -> Result<Macro, SyntaxError> {
+  // only a parenthesis right after the name starts a parameter list
+  let function_like = tokens.first().is_some_and(|open| {
+    open.token == Token::OpenParenthesis
+      && open.span.file == name.span.file
+      && open.span.start == name.span.end
+  });
+  let definition = if function_like {
+    let (parameters, variadic, len) = parameters(tokens)?;
+    Macro {
+      parameters: Some(parameters),
+      variadic,
+      replacement: tokens[len..].to_vec(),
+    }
+  } else {
+    Macro {
+      parameters: None,
+      variadic: false,
+      replacement: tokens.to_vec(),
+    }
+  };
+
+  let replacement = &definition.replacement;
+  let ends = [replacement.first(), replacement.last()];
+  if let Some(paste) = ends
+    .into_iter()
+    .flatten()
+    .find(|token| token.token == Token::HashHash)
+  {
+    return Err(
+      SyntaxError::new_preprocess_error(
+        "`##` cannot appear at either end of a macro replacement list".to_string(),
+      )
+      .with_span(paste.span)
+      .with_code("E0501"),
+    );
+  }
+  if definition.parameters.is_some() {
+    let stringified = replacement.iter().enumerate().find(|(idx, token)| {
+      token.token == Token::Hash
+        && replacement
+          .get(idx + 1)
+          .and_then(|next| definition.parameter(next))
+          .is_none()
+    });
+    if let Some((_, hash)) = stringified {
+      return Err(
+        SyntaxError::new_preprocess_error("`#` is not followed by a macro parameter".to_string())
+          .with_span(hash.span)
+          .with_code("E0501"),
+      );
+    }
+  }
+  Ok(definition)
+}
+
+/// Parameters of the function-like macro whose parameter list starts
+/// `tokens`, whether it is variadic and the number of tokens the list takes.
+fn parameters(tokens: &[SpannedToken]) -> Result<(Vec<Rc<String>>, bool, usize), SyntaxError> {
+  let mut parameters = vec![];
+  if tokens
+    .get(1)
+    .is_some_and(|token| token.token == Token::CloseParenthesis)
+  {
+    return Ok((parameters, false, 2));
+  }
+
+  let end = tokens[tokens.len() - 1].span.after();
+  let mut idx = 1;
+  loop {
+    let token = tokens.get(idx);
+    let variadic = match token.map(|token| &token.token) {
+      Some(Token::Identifier(name)) if parameters.contains(name) => {
+        return Err(
+          SyntaxError::new_preprocess_error(format!("duplicate macro parameter {}", name))
+            .with_span(token.map_or(end, |token| token.span))
+            .with_code("E0501"),
+        )
+      }
+      Some(Token::Identifier(name)) => {
+        parameters.push(name.clone());
+        false
+      }
+      Some(Token::Ellipsis) => {
+        parameters.push(Rc::new(VARIADIC_PARAMETER.to_string()));
+        true
+      }
+      _ => {
+        return Err(
+          SyntaxError::new_preprocess_error("expected a macro parameter name".to_string())
+            .with_span(token.map_or(end, |token| token.span))
+            .with_code("E0501"),
+        )
+      }
+    };
+
+    let separator = tokens.get(idx + 1);
+    match separator.map(|token| &token.token) {
+      Some(Token::CloseParenthesis) => return Ok((parameters, variadic, idx + 2)),
+      Some(Token::Comma) if !variadic => idx += 2,
+      _ => {
+        return Err(
+          SyntaxError::new_preprocess_error(
+            "expected `,` or `)` in macro parameter list".to_string(),
+          )
+          .with_span(separator.map_or(end, |token| token.span))
+          .with_code("E0501"),
+        )
+      }
+    }
+  }
+}
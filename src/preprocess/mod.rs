@@ -0,0 +1,498 @@
+//! Preprocessor carrying out the directives of a translation unit on its
+//! tokens and pulling in the files named by `#include`.
+//!
+//! Besides `#include` it understands `#define` and `#undef` of the macros
+//! expanded in the text lines, `#pragma once` and the `#ifdef`, `#ifndef`,
+//! `#else` and `#endif` conditionals which make up include guards.
+
+use std::{
+  collections::{HashMap, HashSet},
+  fs,
+  path::{Path, PathBuf},
+  rc::Rc,
+};
+
+use tracing::debug;
+
+use crate::{
+  lex::{self, SpannedToken, Token},
+  utility::{FileId, SourceMap, Span, SyntaxError},
+};
+
+mod macros;
+pub use macros::MacroOption;
+use macros::{ExpandedToken, Macro};
+
+/// Directories searched for headers after the ones given with `-I`.
+pub const SYSTEM_INCLUDE_PATHS: &[&str] = &[
+  "/usr/local/include",
+  "/usr/include/x86_64-linux-gnu",
+  "/usr/include",
+];
+
+/// Name of the file holding the directives given on the command line.
+const COMMAND_LINE: &str = "<command line>";
+
+/// How deeply `#include` directives may nest, the same limit as gcc.
+pub const INCLUDE_DEPTH_LIMIT: usize = 200;
+
+/// A conditional group whose `#endif` was not read yet.
+struct Conditional {
+  /// Location of the directive opening the group.
+  span: Span,
+  /// Whether the lines of the current branch are kept.
+  active: bool,
+  /// Whether the lines around the group are kept, no branch is otherwise.
+  parent_active: bool,
+  seen_else: bool,
+}
+
+pub struct Preprocessor<'a> {
+  sources: &'a mut SourceMap,
+  include_paths: Vec<PathBuf>,
+  system_include_paths: Vec<PathBuf>,
+  /// `-D` and `-U` options carried out before the file.
+  macro_options: Vec<MacroOption>,
+  macros: HashMap<Rc<String>, Rc<Macro>>,
+  /// Macro guarding each file wrapped in `#ifndef` and `#endif`.
+  guards: HashMap<PathBuf, Rc<String>>,
+  /// Files marked with `#pragma once`.
+  once: HashSet<PathBuf>,
+  /// Canonical path and id of the files being read, the innermost last.
+  stack: Vec<(PathBuf, FileId)>,
+  output: Vec<SpannedToken>,
+}
+
+impl<'a> Preprocessor<'a> {
+  pub fn new(sources: &'a mut SourceMap) -> Self {
+    Self {
+      sources,
+      include_paths: vec![],
+      system_include_paths: SYSTEM_INCLUDE_PATHS.iter().map(PathBuf::from).collect(),
+      macro_options: vec![],
+      macros: HashMap::new(),
+      guards: HashMap::new(),
+      once: HashSet::new(),
+      stack: vec![],
+      output: vec![],
+    }
+  }
+
+  /// Search `include_paths` for headers before the system directories.
+  pub fn with_include_paths(mut self, include_paths: Vec<PathBuf>) -> Self {
+    self.include_paths = include_paths;
+    self
+  }
+
+  /// Carry out `macro_options` in order before the file.
+  pub fn with_macros(mut self, macro_options: Vec<MacroOption>) -> Self {
+    self.macro_options = macro_options;
+    self
+  }
+
+  /// Tokens of `file` once its directives were carried out and its macros
+  /// expanded.
+  pub fn run(mut self, file: FileId) -> Result<Vec<SpannedToken>, SyntaxError> {
+    if !self.macro_options.is_empty() {
+      let directives = self
+        .macro_options
+        .iter()
+        .map(MacroOption::directive)
+        .collect();
+      let command_line = self.sources.add_file(COMMAND_LINE.to_string(), directives);
+      self.file(command_line, PathBuf::from(COMMAND_LINE))?;
+    }
+    let path = canonical(Path::new(self.sources.name(file)));
+    self.file(file, path)?;
+    Ok(self.output)
+  }
+
+  fn file(&mut self, file: FileId, path: PathBuf) -> Result<(), SyntaxError> {
+    debug!("preprocessing {}", self.sources.name(file));
+    let tokens = lex::string_tokenizer(self.sources.source(file), file)?;
+    if let Some(guard) = self.include_guard(&tokens) {
+      debug!("{} is guarded by {}", self.sources.name(file), guard);
+      self.guards.insert(path.clone(), guard);
+    }
+
+    self.stack.push((path, file));
+    let mut conditionals = vec![];
+    // tokens of the text lines read since the last directive
+    let mut text = vec![];
+    let mut idx = 0;
+    while let Some(token) = tokens.get(idx) {
+      if is_directive(token) {
+        self.flush(&mut text)?;
+        let end = directive_end(&tokens, idx);
+        self.directive(token, &tokens[idx + 1..end], &mut conditionals)?;
+        idx = end;
+        continue;
+      }
+      if conditionals.last().is_none_or(|group| group.active) {
+        text.push(token.clone());
+      }
+      idx += 1;
+    }
+    self.flush(&mut text)?;
+    self.stack.pop();
+
+    match conditionals.last() {
+      Some(group) => Err(
+        SyntaxError::new_preprocess_error("unterminated conditional directive".to_string())
+          .with_span(group.span)
+          .with_code("E0504")
+          .with_help("close the group with `#endif`".to_string()),
+      ),
+      None => Ok(()),
+    }
+  }
+
+  /// Expand the macros of the text lines in `text` into the output.
+  fn flush(&mut self, text: &mut Vec<SpannedToken>) -> Result<(), SyntaxError> {
+    let tokens = text.drain(..).map(ExpandedToken::from).collect();
+    let expanded = self.expand(tokens)?;
+    self
+      .output
+      .extend(expanded.into_iter().map(|token| token.token));
+    Ok(())
+  }
+
+  /// Carry out the directive started by `hash` whose remaining tokens on the
+  /// line are `tokens`.
+  fn directive(
+    &mut self,
+    hash: &SpannedToken,
+    tokens: &[SpannedToken],
+    conditionals: &mut Vec<Conditional>,
+  ) -> Result<(), SyntaxError> {
+    // a lone `#` is the null directive
+    let Some((name, arguments)) = tokens.split_first() else {
+      return Ok(());
+    };
+    let span = hash.span.to(name.span);
+    let active = conditionals.last().is_none_or(|group| group.active);
+    let directive = self.spelling(name).to_string();
+
+    match directive.as_str() {
+      "ifdef" | "ifndef" => {
+        let defined = !active || self.macros.contains_key(&macro_name(span, arguments)?);
+        conditionals.push(Conditional {
+          span,
+          active: active && defined == (directive == "ifdef"),
+          parent_active: active,
+          seen_else: false,
+        });
+      }
+      "if" if !active => conditionals.push(Conditional {
+        span,
+        active: false,
+        parent_active: false,
+        seen_else: false,
+      }),
+      "elif" => match conditionals.last() {
+        Some(group) if group.seen_else => {
+          return Err(
+            SyntaxError::new_preprocess_error("#elif after #else".to_string())
+              .with_span(span)
+              .with_code("E0504"),
+          )
+        }
+        Some(group) if !group.parent_active => {}
+        Some(_) => return Err(unsupported(span, "#elif")),
+        None => return Err(unmatched(span, "#elif")),
+      },
+      "if" => return Err(unsupported(span, "#if")),
+      "else" => match conditionals.last_mut() {
+        Some(group) if group.seen_else => {
+          return Err(
+            SyntaxError::new_preprocess_error("#else after #else".to_string())
+              .with_span(span)
+              .with_code("E0504"),
+          )
+        }
+        Some(group) => {
+          group.seen_else = true;
+          group.active = group.parent_active && !group.active;
+        }
+        None => return Err(unmatched(span, "#else")),
+      },
+      "endif" => {
+        conditionals
+          .pop()
+          .ok_or_else(|| unmatched(span, "#endif"))?;
+      }
+      // skipped groups only have their conditionals tracked
+      _ if !active => {}
+      "include" => self.include(span, arguments)?,
+      "define" => self.define(span, arguments)?,
+      "undef" => self.undefine(span, arguments)?,
+      "pragma" => {
+        // unknown pragmas are ignored as the standard asks
+        if arguments
+          .first()
+          .is_some_and(|arg| self.spelling(arg) == "once")
+        {
+          let (path, _) = self.stack.last().expect("directive outside of a file");
+          self.once.insert(path.clone());
+        }
+      }
+      _ => {
+        return Err(
+          SyntaxError::new_preprocess_error(format!(
+            "invalid preprocessing directive #{}",
+            directive
+          ))
+          .with_span(span)
+          .with_code("E0501"),
+        )
+      }
+    }
+    Ok(())
+  }
+
+  /// Carry out the `#include` at `directive` naming the header in `arguments`.
+  fn include(&mut self, directive: Span, arguments: &[SpannedToken]) -> Result<(), SyntaxError> {
+    let (name, span, quoted) = self.header_name(directive, arguments)?;
+    let Some(path) = self.find_header(&name, quoted, directive.file) else {
+      return Err(
+        SyntaxError::new_preprocess_error(format!("`{}` file not found", name))
+          .with_span(span)
+          .with_code("E0502")
+          .with_help("add the directory holding it to the search paths with `-I`".to_string()),
+      );
+    };
+
+    let canonical = canonical(&path);
+    if self.once.contains(&canonical)
+      || self
+        .guards
+        .get(&canonical)
+        .is_some_and(|guard| self.macros.contains_key(guard))
+    {
+      debug!("skipping {} which was already included", path.display());
+      return Ok(());
+    }
+    if self.stack.len() >= INCLUDE_DEPTH_LIMIT {
+      return Err(self.nested_too_deeply(span, &canonical, &path));
+    }
+
+    let source = fs::read_to_string(&path).map_err(|err| {
+      SyntaxError::new_preprocess_error(format!("unable to read {}: {}", path.display(), err))
+        .with_span(span)
+        .with_code("E0502")
+    })?;
+    let file = self
+      .sources
+      .add_included_file(path.display().to_string(), source, span);
+    self.file(file, canonical)
+  }
+
+  /// Name of the header an `#include` asks for, its location and whether it
+  /// was quoted rather than bracketed. The name is read from the source as
+  /// the tokens it was lexed into lost its spelling.
+  fn header_name(
+    &self,
+    directive: Span,
+    arguments: &[SpannedToken],
+  ) -> Result<(String, Span, bool), SyntaxError> {
+    let source = self.sources.source(directive.file);
+    let (name, span, quoted, rest) = match arguments {
+      [literal @ SpannedToken {
+        token: Token::StringLiteral(_),
+        ..
+      }, rest @ ..] => {
+        let span = literal.span;
+        (&source[span.start + 1..span.end - 1], span, true, rest)
+      }
+      [open @ SpannedToken {
+        token: Token::LessThan,
+        ..
+      }, rest @ ..] => {
+        let Some(close) = rest.iter().position(|arg| arg.token == Token::GreaterThan) else {
+          return Err(
+            SyntaxError::new_preprocess_error("missing terminating > character".to_string())
+              .with_span(open.span.to(arguments[arguments.len() - 1].span))
+              .with_code("E0501"),
+          );
+        };
+        let span = open.span.to(rest[close].span);
+        let name = &source[open.span.end..rest[close].span.start];
+        (name, span, false, &rest[close + 1..])
+      }
+      _ => {
+        return Err(
+          SyntaxError::new_preprocess_error(
+            "#include expects \"FILENAME\" or <FILENAME>".to_string(),
+          )
+          .with_span(arguments.first().map_or(directive.after(), |arg| arg.span))
+          .with_code("E0501"),
+        )
+      }
+    };
+
+    if let Some(extra) = rest.first() {
+      return Err(
+        SyntaxError::new_preprocess_error("extra tokens at end of #include directive".to_string())
+          .with_span(extra.span.to(rest[rest.len() - 1].span))
+          .with_code("E0501"),
+      );
+    }
+    Ok((name.to_string(), span, quoted))
+  }
+
+  /// Path of the header `name`, looked up first in the directory of
+  /// `includer` when it was quoted, then in the `-I` and system directories.
+  fn find_header(&self, name: &str, quoted: bool, includer: FileId) -> Option<PathBuf> {
+    let current = quoted.then(|| {
+      Path::new(self.sources.name(includer))
+        .parent()
+        .unwrap_or(Path::new(""))
+        .to_path_buf()
+    });
+    current
+      .into_iter()
+      .chain(self.include_paths.iter().cloned())
+      .chain(self.system_include_paths.iter().cloned())
+      .map(|dir| dir.join(name))
+      .find(|path| path.is_file())
+  }
+
+  /// Error for an `#include` of `path` at `span` going past the depth limit,
+  /// pointing at the start of the cycle when the file includes itself.
+  fn nested_too_deeply(&self, span: Span, canonical: &Path, path: &Path) -> SyntaxError {
+    let error = SyntaxError::new_preprocess_error("#include nested too deeply".to_string())
+      .with_code("E0503")
+      .with_note(format!(
+        "at most {} files can be nested",
+        INCLUDE_DEPTH_LIMIT
+      ));
+    let Some(start) = self.stack.iter().position(|(open, _)| open == canonical) else {
+      return error.with_span(span);
+    };
+
+    // report the first include of the cycle, which has a short include stack
+    let first = self
+      .stack
+      .get(start + 1)
+      .and_then(|(_, file)| self.sources.included_from(*file))
+      .unwrap_or(span);
+    let last = self
+      .stack
+      .iter()
+      .rposition(|(open, _)| open == canonical)
+      .unwrap_or(start);
+    let cycle: Vec<_> = self.stack[last..]
+      .iter()
+      .map(|(_, file)| self.sources.name(*file).to_string())
+      .chain([path.display().to_string()])
+      .collect();
+    error
+      .with_span(first)
+      .with_note(format!("include cycle: {}", cycle.join(" -> ")))
+      .with_help("guard the header with `#pragma once` or an include guard".to_string())
+  }
+
+  /// Macro guarding a file whose tokens all sit between `#ifndef NAME` and the
+  /// matching `#endif`, such a file has no effect once `NAME` is defined.
+  fn include_guard(&self, tokens: &[SpannedToken]) -> Option<Rc<String>> {
+    let guard = match tokens {
+      [hash, _, SpannedToken {
+        token: Token::Identifier(name),
+        ..
+      }, ..]
+        if is_directive(hash) && self.directive_name(tokens, 0) == Some("ifndef") =>
+      {
+        name.clone()
+      }
+      _ => return None,
+    };
+
+    let mut depth = 0;
+    let mut idx = 0;
+    while idx < tokens.len() {
+      if !is_directive(&tokens[idx]) {
+        idx += 1;
+        continue;
+      }
+      match self.directive_name(tokens, idx) {
+        Some("if" | "ifdef" | "ifndef") => depth += 1,
+        Some("else" | "elif") if depth == 1 => return None,
+        Some("endif") if depth == 1 => {
+          return (directive_end(tokens, idx) == tokens.len()).then_some(guard)
+        }
+        Some("endif") => depth -= 1,
+        _ => {}
+      }
+      idx = directive_end(tokens, idx);
+    }
+    None
+  }
+
+  /// Name of the directive started by the `#` at `idx`.
+  fn directive_name(&self, tokens: &[SpannedToken], idx: usize) -> Option<&str> {
+    tokens
+      .get(idx + 1)
+      .filter(|name| !name.first_on_line)
+      .map(|name| self.spelling(name))
+  }
+
+  /// Text `token` was read from.
+  fn spelling(&self, token: &SpannedToken) -> &str {
+    &self.sources.source(token.span.file)[token.span.start..token.span.end]
+  }
+}
+
+/// Whether `token` starts a directive, being a `#` first on its line.
+fn is_directive(token: &SpannedToken) -> bool {
+  token.token == Token::Hash && token.first_on_line
+}
+
+/// Index past the last token of the directive started at `idx`.
+fn directive_end(tokens: &[SpannedToken], idx: usize) -> usize {
+  tokens[idx + 1..]
+    .iter()
+    .position(|token| token.first_on_line)
+    .map_or(tokens.len(), |len| idx + 1 + len)
+}
+
+/// Name of the macro a `#define`, `#ifdef` or `#ifndef` at `directive` is about.
+fn macro_name(directive: Span, arguments: &[SpannedToken]) -> Result<Rc<String>, SyntaxError> {
+  match arguments.first() {
+    Some(SpannedToken {
+      token: Token::Identifier(name),
+      ..
+    }) => Ok(name.clone()),
+    Some(token) => Err(
+      SyntaxError::new_preprocess_error("macro names must be identifiers".to_string())
+        .with_span(token.span)
+        .with_code("E0501"),
+    ),
+    None => Err(
+      SyntaxError::new_preprocess_error("macro name missing".to_string())
+        .with_span(directive)
+        .with_code("E0501"),
+    ),
+  }
+}
+
+/// Error for a `#else` or `#endif` without a group to close.
+fn unmatched(span: Span, directive: &str) -> SyntaxError {
+  SyntaxError::new_preprocess_error(format!("{} without #if", directive))
+    .with_span(span)
+    .with_code("E0504")
+}
+
+/// Error for a conditional directive evaluating an expression, which is not
+/// supported.
+fn unsupported(span: Span, directive: &str) -> SyntaxError {
+  SyntaxError::new_preprocess_error(format!("{} is not supported", directive))
+    .with_span(span)
+    .with_code("E0501")
+    .with_help("test whether a macro is defined with `#ifdef` or `#ifndef`".to_string())
+}
+
+/// Path `path` resolves to, so a file reached through different paths is
+/// recognised, or `path` itself when it does not exist.
+fn canonical(path: &Path) -> PathBuf {
+  fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
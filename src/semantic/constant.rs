@@ -0,0 +1,177 @@
+use crate::{
+  ast::{model::*, Type},
+  lex::{BinaryOperator, UnaryOperator},
+};
+
+use super::TypeTable;
+
+/// Value of a constant expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Constant {
+  Int(i64),
+  Float(f64),
+}
+
+impl Constant {
+  pub fn is_zero(self) -> bool {
+    match self {
+      Constant::Int(val) => val == 0,
+      Constant::Float(val) => val == 0.0,
+    }
+  }
+
+  fn to_float(self) -> f64 {
+    match self {
+      Constant::Int(val) => val as f64,
+      Constant::Float(val) => val,
+    }
+  }
+
+  /// This value converted to the scalar type `ty` like a cast does.
+  pub fn convert(self, ty: &Type) -> Constant {
+    match (self, ty) {
+      (val, Type::Double) => Constant::Float(val.to_float()),
+      (val, Type::Float) => Constant::Float(val.to_float() as f32 as f64),
+      // out of range values are undefined, saturate like Rust does
+      (Constant::Float(val), Type::ULong) => Constant::Int(val as u64 as i64),
+      (Constant::Float(val), ty) => Constant::Int(ty.convert(val as i64)),
+      (Constant::Int(val), ty) => Constant::Int(ty.convert(val)),
+    }
+  }
+
+  /// Bytes representing this value of type `ty` in memory, read as an integer.
+  pub fn bits(self, ty: &Type) -> i64 {
+    match (self, ty) {
+      (Constant::Float(val), Type::Float) => (val as f32).to_bits() as i64,
+      (Constant::Float(val), _) => val.to_bits() as i64,
+      (Constant::Int(val), _) => val,
+    }
+  }
+}
+
+/// Value of `exp` when it can be computed at compile time, as required of the
+/// initialisers of variables with static storage duration.
+pub fn evaluate_constant(exp: &Expression, types: &TypeTable) -> Option<Constant> {
+  match &exp.kind {
+    ExpressionKind::Const(val, _) => Some(Constant::Int(*val)),
+    ExpressionKind::ConstFloat(val, _) => Some(Constant::Float(*val)),
+    ExpressionKind::Unary { op, exp } => {
+      let val = evaluate_constant(exp, types)?;
+      Some(match (op, val) {
+        (UnaryOperator::Negation, Constant::Int(val)) => Constant::Int(val.wrapping_neg()),
+        (UnaryOperator::Negation, Constant::Float(val)) => Constant::Float(-val),
+        (UnaryOperator::BitwiseComplement, Constant::Int(val)) => Constant::Int(!val),
+        (UnaryOperator::LogicalNegation, val) => Constant::Int(val.is_zero() as i64),
+        _ => return None,
+      })
+    }
+    ExpressionKind::Binary { exp1, op, exp2 } => {
+      let lhs = evaluate_constant(exp1, types)?;
+      let rhs = evaluate_constant(exp2, types)?;
+      match (op, lhs, rhs) {
+        (BinaryOperator::And, ..) => Some(Constant::Int((!lhs.is_zero() && !rhs.is_zero()) as i64)),
+        (BinaryOperator::Or, ..) => Some(Constant::Int((!lhs.is_zero() || !rhs.is_zero()) as i64)),
+        // unsigned values shift in zeroes from the left
+        (BinaryOperator::ShiftRight, Constant::Int(lhs), Constant::Int(rhs))
+          if operand_type(exp1).is_some_and(|ty| !ty.is_signed()) =>
+        {
+          let shifted = (lhs as u64).checked_shr(u32::try_from(rhs).ok()?)?;
+          Some(Constant::Int(shifted as i64))
+        }
+        (_, Constant::Int(lhs), Constant::Int(rhs)) => integer_binary(*op, lhs, rhs),
+        // either operand being floating makes the other one floating too
+        _ => floating_binary(*op, lhs.to_float(), rhs.to_float()),
+      }
+    }
+    ExpressionKind::Conditional {
+      condition,
+      then,
+      otherwise,
+    } => {
+      let condition = evaluate_constant(condition, types)?;
+      let then = evaluate_constant(then, types)?;
+      let otherwise = evaluate_constant(otherwise, types)?;
+      Some(if !condition.is_zero() {
+        then
+      } else {
+        otherwise
+      })
+    }
+    ExpressionKind::Cast { target, exp } => Some(evaluate_constant(exp, types)?.convert(target)),
+    ExpressionKind::SizeOfType(ty) => types.is_complete(ty).then(|| Constant::Int(types.size(ty))),
+    // the type of an expression is only known once it was checked
+    ExpressionKind::SizeOf(exp) => exp
+      .ty
+      .as_ref()
+      .filter(|ty| types.is_complete(ty))
+      .map(|ty| Constant::Int(types.size(ty))),
+    ExpressionKind::Comma { .. }
+    | ExpressionKind::Assign { .. }
+    | ExpressionKind::CompoundAssign { .. }
+    | ExpressionKind::Var { .. }
+    | ExpressionKind::String(_)
+    | ExpressionKind::FunctionCall { .. }
+    | ExpressionKind::Subscript { .. }
+    | ExpressionKind::Member { .. } => None,
+  }
+}
+
+/// Type of `exp`, which is also known before the semantic pass for literals
+/// and casts.
+fn operand_type(exp: &Expression) -> Option<&Type> {
+  match &exp.kind {
+    _ if exp.ty.is_some() => exp.ty.as_ref(),
+    ExpressionKind::Const(_, ty) | ExpressionKind::Cast { target: ty, .. } => Some(ty),
+    _ => None,
+  }
+}
+
+fn integer_binary(op: BinaryOperator, lhs: i64, rhs: i64) -> Option<Constant> {
+  Some(Constant::Int(match op {
+    BinaryOperator::Addition => lhs.wrapping_add(rhs),
+    BinaryOperator::Minus => lhs.wrapping_sub(rhs),
+    BinaryOperator::Multiplication => lhs.wrapping_mul(rhs),
+    BinaryOperator::Division => lhs.checked_div(rhs)?,
+    BinaryOperator::Remainder => lhs.checked_rem(rhs)?,
+    BinaryOperator::BitwiseAnd => lhs & rhs,
+    BinaryOperator::BitwiseOr => lhs | rhs,
+    BinaryOperator::BitwiseXor => lhs ^ rhs,
+    BinaryOperator::ShiftLeft => lhs.checked_shl(u32::try_from(rhs).ok()?)?,
+    BinaryOperator::ShiftRight => lhs.checked_shr(u32::try_from(rhs).ok()?)?,
+    BinaryOperator::Equal => (lhs == rhs) as i64,
+    BinaryOperator::NotEqual => (lhs != rhs) as i64,
+    BinaryOperator::LessThan => (lhs < rhs) as i64,
+    BinaryOperator::LessThanOrEqual => (lhs <= rhs) as i64,
+    BinaryOperator::GreaterThan => (lhs > rhs) as i64,
+    BinaryOperator::GreaterThanOrEqual => (lhs >= rhs) as i64,
+    BinaryOperator::And | BinaryOperator::Or => {
+      unreachable!("logical operators are evaluated first")
+    }
+  }))
+}
+
+fn floating_binary(op: BinaryOperator, lhs: f64, rhs: f64) -> Option<Constant> {
+  let comparison = |val: bool| Some(Constant::Int(val as i64));
+  Some(Constant::Float(match op {
+    BinaryOperator::Addition => lhs + rhs,
+    BinaryOperator::Minus => lhs - rhs,
+    BinaryOperator::Multiplication => lhs * rhs,
+    BinaryOperator::Division => lhs / rhs,
+    BinaryOperator::Equal => return comparison(lhs == rhs),
+    BinaryOperator::NotEqual => return comparison(lhs != rhs),
+    BinaryOperator::LessThan => return comparison(lhs < rhs),
+    BinaryOperator::LessThanOrEqual => return comparison(lhs <= rhs),
+    BinaryOperator::GreaterThan => return comparison(lhs > rhs),
+    BinaryOperator::GreaterThanOrEqual => return comparison(lhs >= rhs),
+    // only defined on integers
+    BinaryOperator::Remainder
+    | BinaryOperator::BitwiseAnd
+    | BinaryOperator::BitwiseOr
+    | BinaryOperator::BitwiseXor
+    | BinaryOperator::ShiftLeft
+    | BinaryOperator::ShiftRight => return None,
+    BinaryOperator::And | BinaryOperator::Or => {
+      unreachable!("logical operators are evaluated first")
+    }
+  }))
+}
@@ -0,0 +1,150 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+  ast::{model::StructKind, Type},
+  utility::Span,
+};
+
+/// Member of a structure or union along with where it is placed.
+#[derive(Debug, Clone)]
+pub struct Member {
+  pub name: Rc<String>,
+  pub ty: Type,
+  /// Offset of the member from the start of the object in bytes.
+  pub offset: i64,
+}
+
+/// Layout of a complete structure or union type.
+#[derive(Debug, Clone)]
+pub struct StructLayout {
+  pub kind: StructKind,
+  /// Members in declaration order.
+  pub members: Vec<Member>,
+  pub size: i64,
+  pub alignment: i64,
+  /// Definition of the type.
+  pub span: Span,
+}
+
+impl StructLayout {
+  /// Lay out `members` the way the System V ABI does, so the objects are
+  /// compatible with other compilers. Each member of a structure is placed at
+  /// the next offset aligned for its type while members of a union all start
+  /// at 0, and the size is rounded up to the strictest member alignment.
+  /// `None` when the size does not fit in an `i64`.
+  pub fn new(
+    kind: StructKind,
+    members: Vec<(Rc<String>, Type)>,
+    span: Span,
+    types: &TypeTable,
+  ) -> Option<Self> {
+    let mut layout = Self {
+      kind,
+      members: Vec::with_capacity(members.len()),
+      size: 0,
+      alignment: 1,
+      span,
+    };
+    for (name, ty) in members {
+      let (size, alignment) = (types.checked_size(&ty)?, types.alignment(&ty));
+      let offset = match kind {
+        StructKind::Struct => checked_round_up(layout.size, alignment)?,
+        StructKind::Union => 0,
+      };
+      layout.size = layout.size.max(offset.checked_add(size)?);
+      layout.alignment = layout.alignment.max(alignment);
+      layout.members.push(Member { name, ty, offset });
+    }
+    layout.size = checked_round_up(layout.size, layout.alignment)?;
+    Some(layout)
+  }
+}
+
+/// `value` rounded up to the next multiple of `alignment`.
+pub fn round_up(value: i64, alignment: i64) -> i64 {
+  (value + alignment - 1).div_euclid(alignment) * alignment
+}
+
+/// `value` rounded up to the next multiple of `alignment`, `None` when that
+/// does not fit in an `i64`.
+fn checked_round_up(value: i64, alignment: i64) -> Option<i64> {
+  Some(value.checked_add(alignment - 1)?.div_euclid(alignment) * alignment)
+}
+
+/// Layouts of the structures and unions of a program keyed by their unique
+/// tag, through which the size of any type is found.
+#[derive(Debug, Clone, Default)]
+pub struct TypeTable {
+  layouts: HashMap<String, StructLayout>,
+}
+
+impl TypeTable {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn insert(&mut self, tag: &str, layout: StructLayout) {
+    self.layouts.insert(tag.to_string(), layout);
+  }
+
+  /// Layout of a structure or union type, `None` for other types and while
+  /// the type is incomplete.
+  pub fn layout(&self, ty: &Type) -> Option<&StructLayout> {
+    match ty {
+      Type::Struct(tag) | Type::Union(tag) => self.layouts.get(tag.as_str()),
+      _ => None,
+    }
+  }
+
+  /// Whether the size of `ty` is known, which is only not the case for
+  /// structures declared without their members and arrays of them.
+  pub fn is_complete(&self, ty: &Type) -> bool {
+    match ty {
+      Type::Struct(_) | Type::Union(_) => self.layout(ty).is_some(),
+      Type::Array(element, _) => self.is_complete(element),
+      _ => true,
+    }
+  }
+
+  /// Size of a value of the complete type `ty` in bytes, `i64::MAX` for an
+  /// array the semantic pass reported as too large.
+  pub fn size(&self, ty: &Type) -> i64 {
+    self.checked_size(ty).unwrap_or(i64::MAX)
+  }
+
+  /// Size of a value of the complete type `ty` in bytes, `None` when it does
+  /// not fit in an `i64`.
+  pub fn checked_size(&self, ty: &Type) -> Option<i64> {
+    match ty {
+      Type::Struct(_) | Type::Union(_) => Some(self.complete(ty).size),
+      Type::Array(element, len) => self
+        .checked_size(element)?
+        .checked_mul(i64::try_from(*len).ok()?),
+      ty => Some(ty.size()),
+    }
+  }
+
+  /// Alignment of a value of the complete type `ty` in bytes.
+  pub fn alignment(&self, ty: &Type) -> i64 {
+    match ty {
+      Type::Struct(_) | Type::Union(_) => self.complete(ty).alignment,
+      Type::Array(element, _) => self.alignment(element),
+      ty => ty.size(),
+    }
+  }
+
+  /// Member `name` of the structure or union type `ty`.
+  pub fn member(&self, ty: &Type, name: &str) -> Option<&Member> {
+    self
+      .layout(ty)?
+      .members
+      .iter()
+      .find(|member| *member.name == name)
+  }
+
+  fn complete(&self, ty: &Type) -> &StructLayout {
+    self
+      .layout(ty)
+      .unwrap_or_else(|| panic!("{} is checked to be complete", ty))
+  }
+}
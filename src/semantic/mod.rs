@@ -0,0 +1,510 @@
+use std::rc::Rc;
+
+use crate::{
+  ast::{model::*, Type},
+  lex::UnaryOperator,
+  utility::{Diagnostics, Span, SyntaxError},
+};
+
+mod constant;
+mod layout;
+mod resolver;
+mod structs;
+mod symbols;
+mod typecheck;
+pub use constant::*;
+pub use layout::*;
+pub use symbols::*;
+
+/// Checks run over a parsed [`Program`] before it is handed to code generation,
+/// resolving every variable to a unique name on the way.
+pub struct Analyzer {
+  symbols: SymbolTable,
+  /// Identifiers and tags visible at the point being checked, file scope
+  /// first.
+  scopes: Vec<resolver::Scope>,
+  /// Counter making the names given to local variables unique.
+  next_id: usize,
+  /// Return type of the function being checked.
+  return_type: Type,
+  /// Number of loops enclosing the statement being checked.
+  loop_depth: usize,
+  diagnostics: Diagnostics,
+}
+
+impl Analyzer {
+  pub fn new(error_limit: usize) -> Self {
+    Self {
+      symbols: SymbolTable::new(),
+      scopes: vec![],
+      next_id: 0,
+      return_type: Type::Int,
+      loop_depth: 0,
+      diagnostics: Diagnostics::with_limit(error_limit),
+    }
+  }
+
+  /// Check `program` and rename its local variables to unique names,
+  /// returning the symbols with static storage it declares.
+  pub fn check(mut self, program: &mut Program) -> Result<SymbolTable, Diagnostics> {
+    // structures are laid out before anything sized with them
+    self.scopes.push(resolver::Scope::new());
+    for declaration in &mut program.declarations {
+      match declaration {
+        ExternalDeclaration::Function(func) => {
+          func.return_type = self.resolve_type(&func.return_type, func.span);
+          self.require_passable(&func.return_type, func.span);
+          for param in &mut func.params {
+            param.ty = self.resolve_type(&param.ty, param.span);
+            self.require_passable(&param.ty, param.span);
+          }
+          self.declare_function(func);
+        }
+        ExternalDeclaration::Variable(var) => {
+          var.ty = self.resolve_type(&var.ty, var.span);
+          self.infer_array_length(var);
+          self.declare_file_variable(var);
+        }
+        ExternalDeclaration::Struct(declaration) => self.declare_struct(declaration),
+      }
+    }
+
+    // names only become visible after their declaration
+    for declaration in &mut program.declarations {
+      match declaration {
+        ExternalDeclaration::Function(func) => {
+          self.declare_file_scope(&func.name, &func.return_type, func.span);
+          self.resolve_function(func);
+        }
+        ExternalDeclaration::Variable(var) => {
+          // a tentative definition may precede the members of its type
+          if var.init.is_none() && var.storage != Some(StorageClass::Extern) {
+            self.require_complete(&var.ty, var.span);
+          }
+          self.declare_file_scope(&var.name, &var.ty, var.span);
+        }
+        ExternalDeclaration::Struct(_) => {}
+      }
+    }
+
+    if self.diagnostics.is_empty() {
+      Ok(self.symbols)
+    } else {
+      Err(self.diagnostics)
+    }
+  }
+
+  fn redeclared_kind_error(name: &str, span: Span, previous: &Symbol) -> SyntaxError {
+    SyntaxError::new_semantic_error(format!("{} redeclared as a different kind of symbol", name))
+      .with_span(span)
+      .with_code("E0405")
+      .with_note(format!("previously declared at {}", previous.span))
+  }
+
+  fn linkage_error(name: &str, span: Span, previous: &Symbol) -> SyntaxError {
+    SyntaxError::new_semantic_error(format!(
+      "static declaration of {} follows non-static declaration",
+      name
+    ))
+    .with_span(span)
+    .with_code("E0407")
+    .with_note(format!("previously declared at {}", previous.span))
+  }
+
+  /// Record a function declaration, checking that it agrees with earlier
+  /// declarations and that the function is not defined twice.
+  fn declare_function(&mut self, func: &Function) {
+    let defined = func.body.is_some();
+    let global = func.storage != Some(StorageClass::Static);
+    let params: Vec<Type> = func.params.iter().map(|param| param.ty.clone()).collect();
+    let Some(previous) = self.symbols.get_mut(&func.name) else {
+      self.symbols.insert(
+        &func.name,
+        Symbol {
+          kind: SymbolKind::Function {
+            params,
+            variadic: func.variadic,
+            return_type: func.return_type.clone(),
+            defined,
+          },
+          global,
+          span: func.span,
+        },
+      );
+      return;
+    };
+
+    let SymbolKind::Function {
+      params: previous_params,
+      variadic: previously_variadic,
+      return_type: previous_return_type,
+      defined: previously_defined,
+    } = &previous.kind
+    else {
+      let error = Self::redeclared_kind_error(&func.name, func.span, previous);
+      self.diagnostics.push(error);
+      return;
+    };
+
+    if previous_params.len() != params.len() {
+      self.diagnostics.push(
+        SyntaxError::new_semantic_error(format!(
+          "conflicting declaration of function {}, it takes {} parameter(s) here but {} before",
+          func.name,
+          params.len(),
+          previous_params.len()
+        ))
+        .with_span(func.span)
+        .with_code("E0401")
+        .with_note(format!("previously declared at {}", previous.span)),
+      );
+    } else if *previous_params != params
+      || *previous_return_type != func.return_type
+      || *previously_variadic != func.variadic
+    {
+      self.diagnostics.push(
+        SyntaxError::new_semantic_error(format!("conflicting types for function {}", func.name))
+          .with_span(func.span)
+          .with_code("E0401")
+          .with_note(format!("previously declared at {}", previous.span)),
+      );
+    } else if defined && *previously_defined {
+      self.diagnostics.push(
+        SyntaxError::new_semantic_error(format!("redefinition of function {}", func.name))
+          .with_span(func.span)
+          .with_code("E0402")
+          .with_note(format!("previously defined at {}", previous.span)),
+      );
+    } else if previous.global && !global {
+      let error = Self::linkage_error(&func.name, func.span, previous);
+      self.diagnostics.push(error);
+    } else if defined {
+      previous.kind = SymbolKind::Function {
+        params,
+        variadic: func.variadic,
+        return_type: func.return_type.clone(),
+        defined: true,
+      };
+      previous.span = func.span;
+    }
+  }
+
+  /// Record a file scope variable, merging it with earlier declarations of
+  /// the same name, e.g. a tentative definition followed by an initialised one.
+  fn declare_file_variable(&mut self, var: &VariableDeclaration) {
+    let init = match &var.init {
+      Some(_) => match self.constant_initializer(var) {
+        Some(val) => InitialValue::Initial(val),
+        None => InitialValue::Tentative,
+      },
+      None if var.storage == Some(StorageClass::Extern) => InitialValue::NoInitializer,
+      None => InitialValue::Tentative,
+    };
+    let global = var.storage != Some(StorageClass::Static);
+
+    let Some(previous) = self.symbols.get_mut(&var.name) else {
+      self.symbols.insert(
+        &var.name,
+        Symbol {
+          kind: SymbolKind::Variable {
+            ty: var.ty.clone(),
+            init,
+          },
+          global,
+          span: var.span,
+        },
+      );
+      return;
+    };
+
+    let SymbolKind::Variable {
+      ty: previous_ty,
+      init: previous_init,
+    } = &previous.kind
+    else {
+      let error = Self::redeclared_kind_error(&var.name, var.span, previous);
+      self.diagnostics.push(error);
+      return;
+    };
+    if *previous_ty != var.ty {
+      self.diagnostics.push(
+        SyntaxError::new_semantic_error(format!("conflicting types for variable {}", var.name))
+          .with_span(var.span)
+          .with_code("E0412")
+          .with_note(format!(
+            "previously declared as {} at {}",
+            previous_ty, previous.span
+          )),
+      );
+      return;
+    }
+    let previous_init = previous_init.clone();
+
+    // `extern` takes the linkage of an earlier declaration
+    if var.storage != Some(StorageClass::Extern) && previous.global != global {
+      let error = if global {
+        SyntaxError::new_semantic_error(format!(
+          "non-static declaration of {} follows static declaration",
+          var.name
+        ))
+        .with_span(var.span)
+        .with_code("E0407")
+        .with_note(format!("previously declared at {}", previous.span))
+      } else {
+        Self::linkage_error(&var.name, var.span, previous)
+      };
+      self.diagnostics.push(error);
+      return;
+    }
+
+    let init = match (previous_init, init) {
+      (InitialValue::Initial(_), InitialValue::Initial(_)) => {
+        self.diagnostics.push(
+          SyntaxError::new_semantic_error(format!("redefinition of variable {}", var.name))
+            .with_span(var.span)
+            .with_code("E0408")
+            .with_note(format!("previously defined at {}", previous.span)),
+        );
+        return;
+      }
+      (InitialValue::Initial(val), _) => InitialValue::Initial(val),
+      (_, InitialValue::Initial(val)) => {
+        previous.span = var.span;
+        InitialValue::Initial(val)
+      }
+      (InitialValue::Tentative, _) | (_, InitialValue::Tentative) => InitialValue::Tentative,
+      _ => InitialValue::NoInitializer,
+    };
+    previous.kind = SymbolKind::Variable {
+      ty: var.ty.clone(),
+      init,
+    };
+  }
+
+  /// Give an array declared with an empty first dimension the number of
+  /// elements of its initialiser, or of a string literal and its terminating
+  /// null character.
+  fn infer_array_length(&mut self, var: &mut VariableDeclaration) {
+    let Type::Array(element, 0) = &var.ty else {
+      return;
+    };
+    let len = match &var.init {
+      Some(Initializer::Compound(initializers, _)) => initializers.len(),
+      Some(Initializer::Single(Expression {
+        kind: ExpressionKind::String(val),
+        ..
+      }))
+        if matches!(**element, Type::Char | Type::UChar) =>
+      {
+        val.len() + 1
+      }
+      // the shape of the initialiser is reported when it is checked
+      Some(Initializer::Single(_)) => return,
+      None => {
+        self.diagnostics.push(
+          SyntaxError::new_semantic_error(format!(
+            "array size missing in declaration of `{}`",
+            var.name
+          ))
+          .with_span(var.span)
+          .with_code("E0427"),
+        );
+        return;
+      }
+    };
+    var.ty = Type::Array(element.clone(), len);
+  }
+
+  /// Value a variable with static storage is initialised to, converted to its
+  /// type. `None` after reporting an initialiser which is not a constant, a
+  /// pointer initialised to anything but a null pointer or an address, or a
+  /// type too large.
+  fn constant_initializer(&mut self, var: &VariableDeclaration) -> Option<Vec<StaticInit>> {
+    let init = var.init.as_ref()?;
+    // a type too large was reported when it was resolved
+    self.symbols.types().checked_size(&var.ty)?;
+    let mut values = vec![];
+    self
+      .static_values(&var.name, &var.ty, init, &mut values)
+      .then_some(values)
+  }
+
+  /// Label, offset and pointer type of an address known before the program
+  /// runs: `&var`, `&array[index]` or an array decaying to a pointer, where
+  /// the variable has static storage and the index is a constant.
+  fn address_constant(&self, exp: &Expression) -> Option<(Rc<String>, i64, Type)> {
+    let (target, address_of) = match &exp.kind {
+      ExpressionKind::Unary {
+        op: UnaryOperator::AddressOf,
+        exp,
+      } => (&**exp, true),
+      _ => (exp, false),
+    };
+    match &target.kind {
+      ExpressionKind::Var { name } => {
+        let (label, ty) = self.static_variable(name)?;
+        match ty {
+          _ if address_of => Some((label, 0, Type::Pointer(Box::new(ty)))),
+          Type::Array(element, _) => Some((label, 0, Type::Pointer(element))),
+          _ => None,
+        }
+      }
+      ExpressionKind::Subscript { exp, index } if address_of => {
+        let ExpressionKind::Var { name } = &exp.kind else {
+          return None;
+        };
+        let (label, Type::Array(element, _)) = self.static_variable(name)? else {
+          return None;
+        };
+        let Constant::Int(index) = evaluate_constant(index, self.symbols.types())? else {
+          return None;
+        };
+        let offset = index.wrapping_mul(self.symbols.types().size(&element));
+        Some((label, offset, Type::Pointer(element)))
+      }
+      _ => None,
+    }
+  }
+
+  /// Label and type of the variable with static storage `name` refers to.
+  /// File scope initialisers are checked before any name is in scope, so
+  /// they look up the symbol table directly.
+  fn static_variable(&self, name: &Rc<String>) -> Option<(Rc<String>, Type)> {
+    let label = match self.lookup(name) {
+      Some(entry) => entry.unique,
+      None if self.scopes.len() == 1 => Rc::clone(name),
+      None => return None,
+    };
+    match &self.symbols.get(&label)?.kind {
+      SymbolKind::Variable { ty, .. } => Some((label, ty.clone())),
+      _ => None,
+    }
+  }
+
+  /// Append the value `init` gives an object of type `ty` to `values`,
+  /// returning false once an error was reported.
+  fn static_values(
+    &mut self,
+    name: &str,
+    ty: &Type,
+    init: &Initializer,
+    values: &mut Vec<StaticInit>,
+  ) -> bool {
+    if !self.require_complete(ty, init.span()) {
+      return false;
+    }
+    if let Err(err) = typecheck::check_initializer_shape(ty, init, self.symbols.types()) {
+      self.diagnostics.push(err);
+      return false;
+    }
+
+    let exp = match (ty, init) {
+      (Type::Array(element, len), Initializer::Compound(initializers, _)) => {
+        for init in initializers {
+          if !self.static_values(name, element, init, values) {
+            return false;
+          }
+        }
+        // elements without an initialiser are zero
+        let rest = (len - initializers.len()) as i64 * self.symbols.types().size(element);
+        if rest > 0 {
+          values.push(StaticInit::Zero(rest));
+        }
+        return true;
+      }
+      (_, Initializer::Compound(initializers, _)) if ty.is_aggregate() => {
+        let Some(layout) = self.symbols.types().layout(ty).cloned() else {
+          return false;
+        };
+        // padding between the members and after the last one is zero
+        let mut offset = 0;
+        for (init, member) in initializers.iter().zip(layout.members.iter()) {
+          if member.offset > offset {
+            values.push(StaticInit::Zero(member.offset - offset));
+          }
+          if !self.static_values(name, &member.ty, init, values) {
+            return false;
+          }
+          offset = member.offset + self.symbols.types().size(&member.ty);
+        }
+        if layout.size > offset {
+          values.push(StaticInit::Zero(layout.size - offset));
+        }
+        return true;
+      }
+      (Type::Array(_, len), Initializer::Single(exp)) => {
+        let ExpressionKind::String(val) = &exp.kind else {
+          unreachable!("initialiser shape was checked");
+        };
+        // the terminating null character is dropped when it does not fit
+        values.push(StaticInit::String(Rc::clone(val)));
+        if *len > val.len() {
+          values.push(StaticInit::Zero((len - val.len()) as i64));
+        }
+        return true;
+      }
+      (_, Initializer::Single(exp)) => exp,
+      (_, Initializer::Compound(..)) => unreachable!("initialiser shape was checked"),
+    };
+
+    if let ExpressionKind::String(val) = &exp.kind {
+      if let Type::Pointer(pointee) = ty {
+        if let Type::Char | Type::UChar = **pointee {
+          values.push(StaticInit::StringPointer(Rc::clone(val)));
+          return true;
+        }
+      }
+      self.diagnostics.push(
+        SyntaxError::new_semantic_error(format!(
+          "cannot convert char[{}] to {}",
+          val.len() + 1,
+          ty
+        ))
+        .with_span(exp.span)
+        .with_code("E0417"),
+      );
+      return false;
+    }
+
+    if let Some((label, offset, pointer)) = self.address_constant(exp) {
+      if pointer != *ty {
+        self.diagnostics.push(
+          SyntaxError::new_semantic_error(format!("cannot convert {} to {}", pointer, ty))
+            .with_span(exp.span)
+            .with_code("E0417"),
+        );
+        return false;
+      }
+      values.push(StaticInit::Address(label, offset));
+      return true;
+    }
+
+    // copying another structure is not a constant either
+    let constant = match ty.is_aggregate() {
+      true => None,
+      false => evaluate_constant(exp, self.symbols.types()),
+    };
+    let Some(val) = constant else {
+      self.diagnostics.push(
+        SyntaxError::new_semantic_error(format!("initialiser of {} is not a constant", name))
+          .with_span(exp.span)
+          .with_code("E0406")
+          .with_note(
+            "variables with static storage are initialised before the program runs".to_string(),
+          ),
+      );
+      return false;
+    };
+    if ty.is_pointer() && val != Constant::Int(0) {
+      self.diagnostics.push(
+        SyntaxError::new_semantic_error(format!("cannot convert an integer to {}", ty))
+          .with_span(exp.span)
+          .with_code("E0417")
+          .with_help("only a null pointer constant may initialise a static pointer".to_string()),
+      );
+      return false;
+    }
+    values.push(StaticInit::Scalar(ty.clone(), val.convert(ty).bits(ty)));
+    true
+  }
+}
@@ -0,0 +1,705 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+  ast::{model::*, Type},
+  lex::UnaryOperator,
+  utility::{Span, SyntaxError},
+};
+
+use super::{
+  round_up,
+  structs::TagEntry,
+  typecheck::{
+    binary_type, check_initializer_shape, conditional_type, convert_by_assignment, decay,
+    promote_argument, subscript_type, unary_type,
+  },
+  Analyzer, InitialValue, StaticInit, Symbol, SymbolKind,
+};
+
+/// Most bytes the parameters and automatic variables of a function may take
+/// on the stack, which leaves room below them for temporaries while every
+/// `%rbp` displacement still fits in the 32 bits of an instruction.
+pub(super) const MAX_FRAME_SIZE: i64 = 1 << 30;
+
+/// What an identifier visible in a scope refers to.
+#[derive(Clone)]
+pub(super) struct ScopeEntry {
+  /// Name uses of the identifier are rewritten to, unique within the
+  /// translation unit for locals and the linkage name otherwise.
+  pub(super) unique: Rc<String>,
+  /// Whether the identifier refers to an entry of the [`super::SymbolTable`].
+  has_linkage: bool,
+  /// Type of a variable, the return type of a function.
+  ty: Type,
+  span: Span,
+}
+
+/// Identifiers and tags declared in one block, tags of structures living in a
+/// namespace of their own.
+#[derive(Default)]
+pub(super) struct Scope {
+  identifiers: HashMap<String, ScopeEntry>,
+  pub(super) tags: HashMap<String, TagEntry>,
+  /// Bytes of the stack frame taken by the automatic variables visible in
+  /// the block, laid out in 8 byte slots as code generation does.
+  frame_size: i64,
+}
+
+impl Scope {
+  pub(super) fn new() -> Self {
+    Self::default()
+  }
+}
+
+impl Analyzer {
+  /// Open a block scope, whose automatic variables are placed below those
+  /// of the enclosing blocks.
+  fn enter_scope(&mut self) {
+    let frame_size = self.scopes.last().map_or(0, |scope| scope.frame_size);
+    self.scopes.push(Scope {
+      frame_size,
+      ..Scope::new()
+    });
+  }
+
+  /// Reserve room on the stack for a parameter or automatic variable of type
+  /// `ty`, reporting a frame which grows too large.
+  fn reserve_frame(&mut self, ty: &Type, span: Span) {
+    // an object too large for its size to be known was already reported
+    let Some(size) = self.symbols.types().checked_size(ty) else {
+      return;
+    };
+    let Some(scope) = self.scopes.last_mut() else {
+      return;
+    };
+    let size = round_up(size.min(MAX_FRAME_SIZE + 1), 8);
+    let reported = scope.frame_size > MAX_FRAME_SIZE;
+    scope.frame_size = scope.frame_size.saturating_add(size);
+    if scope.frame_size > MAX_FRAME_SIZE && !reported {
+      self.diagnostics.push(
+        SyntaxError::new_semantic_error("total size of local objects is too large".to_string())
+          .with_span(span)
+          .with_code("E0429")
+          .with_note(format!(
+            "the stack frame of a function holds at most {} bytes",
+            MAX_FRAME_SIZE
+          )),
+      );
+    }
+  }
+
+  /// Check that a parameter or return value of type `ty` is small enough to
+  /// be copied through the stack.
+  pub(super) fn require_passable(&mut self, ty: &Type, span: Span) {
+    let types = self.symbols.types();
+    // incomplete types and sizes which do not fit are reported elsewhere
+    if !types.is_complete(ty)
+      || types
+        .checked_size(ty)
+        .is_none_or(|size| size <= MAX_FRAME_SIZE)
+    {
+      return;
+    }
+    self.diagnostics.push(
+      SyntaxError::new_semantic_error(format!(
+        "{} is too large to be passed or returned by value",
+        ty
+      ))
+      .with_span(span)
+      .with_code("E0429"),
+    );
+  }
+
+  /// Make a function or variable declared at file scope visible to the
+  /// declarations following it.
+  pub(super) fn declare_file_scope(&mut self, name: &Rc<String>, ty: &Type, span: Span) {
+    if let Some(scope) = self.scopes.first_mut() {
+      scope.identifiers.insert(
+        name.to_string(),
+        ScopeEntry {
+          unique: Rc::clone(name),
+          has_linkage: true,
+          ty: ty.clone(),
+          span,
+        },
+      );
+    }
+  }
+
+  pub(super) fn unique_name(&mut self, name: &str) -> Rc<String> {
+    self.next_id += 1;
+    Rc::new(format!("{}.{}", name, self.next_id))
+  }
+
+  pub(super) fn lookup(&self, name: &str) -> Option<ScopeEntry> {
+    self
+      .scopes
+      .iter()
+      .rev()
+      .find_map(|scope| scope.identifiers.get(name))
+      .cloned()
+  }
+
+  /// Whether `name` is a symbol with linkage naming a function.
+  fn is_function(&self, name: &str) -> bool {
+    self
+      .symbols
+      .get(name)
+      .is_some_and(|symbol| matches!(symbol.kind, SymbolKind::Function { .. }))
+  }
+
+  /// Declare `name` in the innermost scope, two declarations of a name in one
+  /// scope are only allowed when both refer to the same symbol with linkage.
+  fn declare_local(&mut self, name: &str, entry: ScopeEntry) {
+    let Some(scope) = self.scopes.last_mut() else {
+      return;
+    };
+    if let Some(previous) = scope.identifiers.get(name) {
+      if !(previous.has_linkage && entry.has_linkage) {
+        self.diagnostics.push(
+          SyntaxError::new_semantic_error(format!("re-declaration of variable {}", name))
+            .with_span(entry.span)
+            .with_code("E0426")
+            .with_note(format!(
+              "`{}` is already declared in this scope at {}",
+              name, previous.span
+            )),
+        );
+      }
+    }
+    scope.identifiers.insert(name.to_string(), entry);
+  }
+
+  pub(super) fn resolve_function(&mut self, func: &mut Function) {
+    let Some(body) = &mut func.body else {
+      return;
+    };
+
+    self.require_complete(&func.return_type, func.span);
+    self.return_type = func.return_type.clone();
+    // parameters share the scope of the function body
+    self.enter_scope();
+    for param in &mut func.params {
+      if self.require_complete(&param.ty, param.span) {
+        self.reserve_frame(&param.ty, param.span);
+      }
+      if let Some(name) = &mut param.name {
+        let unique = self.unique_name(name);
+        let entry = ScopeEntry {
+          unique: Rc::clone(&unique),
+          has_linkage: false,
+          ty: param.ty.clone(),
+          span: param.span,
+        };
+        self.declare_local(name, entry);
+        *name = unique;
+      }
+    }
+    for st in body {
+      self.resolve_statement(st);
+    }
+    self.scopes.pop();
+  }
+
+  /// Resolve a declaration inside a function body, giving locals and `static`
+  /// variables their unique names.
+  fn resolve_local_declaration(&mut self, var: &mut VariableDeclaration) {
+    var.ty = self.resolve_type(&var.ty, var.span);
+    self.infer_array_length(var);
+    if var.storage != Some(StorageClass::Extern) && !self.require_complete(&var.ty, var.span) {
+      // declare the name anyway to avoid follow up errors
+      let entry = ScopeEntry {
+        unique: Rc::clone(&var.name),
+        has_linkage: false,
+        ty: var.ty.clone(),
+        span: var.span,
+      };
+      self.declare_local(&var.name, entry);
+      return;
+    }
+    match var.storage {
+      None => {
+        let unique = self.unique_name(&var.name);
+        let entry = ScopeEntry {
+          unique: Rc::clone(&unique),
+          has_linkage: false,
+          ty: var.ty.clone(),
+          span: var.span,
+        };
+        // a variable is in scope inside its own initialiser
+        self.declare_local(&var.name, entry);
+        self.reserve_frame(&var.ty, var.span);
+        var.name = unique;
+        if let Some(init) = &mut var.init {
+          self.resolve_initializer(init, &var.ty);
+        }
+      }
+      Some(StorageClass::Static) => {
+        let values = self
+          .constant_initializer(var)
+          .unwrap_or_else(|| vec![StaticInit::Zero(self.symbols.types().size(&var.ty))]);
+        let init = InitialValue::Initial(values);
+        let unique = self.unique_name(&var.name);
+        let entry = ScopeEntry {
+          unique: Rc::clone(&unique),
+          has_linkage: false,
+          ty: var.ty.clone(),
+          span: var.span,
+        };
+        self.declare_local(&var.name, entry);
+        self.symbols.insert(
+          &unique,
+          Symbol {
+            kind: SymbolKind::Variable {
+              ty: var.ty.clone(),
+              init,
+            },
+            global: false,
+            span: var.span,
+          },
+        );
+        var.name = unique;
+      }
+      Some(StorageClass::Extern) => {
+        if let Some(init) = &var.init {
+          self.diagnostics.push(
+            SyntaxError::new_semantic_error(format!(
+              "block scope extern declaration of {} has an initialiser",
+              var.name
+            ))
+            .with_span(init.span())
+            .with_code("E0409")
+            .with_help(format!("initialise {} where it is defined", var.name)),
+          );
+        }
+        match self.symbols.get(&var.name) {
+          Some(previous) if matches!(previous.kind, SymbolKind::Function { .. }) => {
+            let error = Self::redeclared_kind_error(&var.name, var.span, previous);
+            self.diagnostics.push(error);
+          }
+          Some(Symbol {
+            kind: SymbolKind::Variable { ty, .. },
+            span,
+            ..
+          }) if *ty != var.ty => {
+            let error = SyntaxError::new_semantic_error(format!(
+              "conflicting types for variable {}",
+              var.name
+            ))
+            .with_span(var.span)
+            .with_code("E0412")
+            .with_note(format!("previously declared as {} at {}", ty, span));
+            self.diagnostics.push(error);
+          }
+          Some(_) => {}
+          None => self.symbols.insert(
+            &var.name,
+            Symbol {
+              kind: SymbolKind::Variable {
+                ty: var.ty.clone(),
+                init: InitialValue::NoInitializer,
+              },
+              global: true,
+              span: var.span,
+            },
+          ),
+        }
+        let entry = ScopeEntry {
+          unique: Rc::clone(&var.name),
+          has_linkage: true,
+          ty: var.ty.clone(),
+          span: var.span,
+        };
+        self.declare_local(&var.name, entry);
+      }
+    }
+  }
+
+  /// Resolve the initialiser of an automatic variable of type `ty`,
+  /// converting each expression in it to the type of what it initialises.
+  fn resolve_initializer(&mut self, init: &mut Initializer, ty: &Type) {
+    if self
+      .report(check_initializer_shape(ty, init, self.symbols.types()))
+      .is_none()
+    {
+      return;
+    }
+    match (init, ty) {
+      // the shape check only lets string literals initialise arrays
+      (Initializer::Single(exp), Type::Array(..)) => self.resolve_object(exp),
+      (Initializer::Single(exp), _) => {
+        self.resolve_expression(exp);
+        self.report(convert_by_assignment(exp, ty, self.symbols.types()));
+      }
+      (Initializer::Compound(initializers, _), Type::Array(element, _)) => {
+        for init in initializers {
+          self.resolve_initializer(init, element);
+        }
+      }
+      (Initializer::Compound(initializers, _), _) if ty.is_aggregate() => {
+        // members are initialised in order, the shape check ensures each
+        // initialiser has a member
+        let members: Vec<Type> = self
+          .symbols
+          .types()
+          .layout(ty)
+          .map(|layout| {
+            layout
+              .members
+              .iter()
+              .map(|member| member.ty.clone())
+              .collect()
+          })
+          .unwrap_or_default();
+        for (init, member) in initializers.iter_mut().zip(members.iter()) {
+          self.resolve_initializer(init, member);
+        }
+      }
+      (Initializer::Compound(..), _) => unreachable!("initialiser shape was checked"),
+    }
+  }
+
+  fn resolve_statement(&mut self, st: &mut Statement) {
+    match &mut st.kind {
+      StatementKind::Return(exp) => {
+        self.resolve_expression(exp);
+        let return_type = self.return_type.clone();
+        self.report(convert_by_assignment(
+          exp,
+          &return_type,
+          self.symbols.types(),
+        ));
+      }
+      StatementKind::Exp(exp) => self.resolve_expression(exp),
+      StatementKind::Declare(declaration) => self.resolve_local_declaration(declaration),
+      StatementKind::DeclareStruct(declaration) => self.declare_struct(declaration),
+      StatementKind::If {
+        condition,
+        then,
+        otherwise,
+      } => {
+        self.resolve_condition(condition);
+        self.resolve_statement(then);
+        if let Some(otherwise) = otherwise {
+          self.resolve_statement(otherwise);
+        }
+      }
+      StatementKind::Compound(statements) => {
+        self.enter_scope();
+        for st in statements {
+          self.resolve_statement(st);
+        }
+        self.scopes.pop();
+      }
+      StatementKind::While { condition, body } | StatementKind::DoWhile { body, condition } => {
+        self.resolve_condition(condition);
+        self.resolve_loop_body(body);
+      }
+      StatementKind::For {
+        init,
+        condition,
+        post,
+        body,
+      } => {
+        // a declaration in the init clause is scoped to the loop
+        self.enter_scope();
+        if let Some(init) = init {
+          self.resolve_statement(init);
+        }
+        if let Some(condition) = condition {
+          self.resolve_condition(condition);
+        }
+        if let Some(post) = post {
+          self.resolve_expression(post);
+        }
+        self.resolve_loop_body(body);
+        self.scopes.pop();
+      }
+      StatementKind::Break | StatementKind::Continue if self.loop_depth == 0 => {
+        let keyword = match st.kind {
+          StatementKind::Break => "break",
+          _ => "continue",
+        };
+        self.diagnostics.push(
+          SyntaxError::new_semantic_error(format!("{} statement not within a loop", keyword))
+            .with_span(st.span)
+            .with_code("E0404"),
+        );
+      }
+      StatementKind::Break | StatementKind::Continue | StatementKind::Null => {}
+    }
+  }
+
+  fn resolve_loop_body(&mut self, body: &mut Statement) {
+    self.loop_depth += 1;
+    self.resolve_statement(body);
+    self.loop_depth -= 1;
+  }
+
+  /// Record the error of a failed check, returning the result of a passed one.
+  fn report<T>(&mut self, result: Result<T, SyntaxError>) -> Option<T> {
+    result.map_err(|err| self.diagnostics.push(err)).ok()
+  }
+
+  /// Unique name and type of the variable `name` refers to at `span`.
+  fn resolve_variable(&mut self, name: &str, span: Span) -> Option<(Rc<String>, Type)> {
+    match self.lookup(name) {
+      None => {
+        self.diagnostics.push(
+          SyntaxError::new_semantic_error(format!("variable not declared {}", name))
+            .with_span(span)
+            .with_code("E0425")
+            .with_help(format!("declare it first with `int {};`", name)),
+        );
+        None
+      }
+      Some(entry) if entry.has_linkage && self.is_function(name) => {
+        self.diagnostics.push(
+          SyntaxError::new_semantic_error(format!("function {} used as a variable", name))
+            .with_span(span)
+            .with_code("E0411")
+            .with_note(format!("{} is declared at {}", name, entry.span)),
+        );
+        None
+      }
+      Some(entry) => Some((entry.unique, entry.ty)),
+    }
+  }
+
+  /// Resolve `exp` used for its value, where arrays decay to pointers.
+  fn resolve_expression(&mut self, exp: &mut Expression) {
+    self.resolve_object(exp);
+    if exp.ty().is_aggregate() {
+      // the value of a structure is copied, which needs its size
+      self.require_complete(exp.ty(), exp.span);
+    }
+    decay(exp);
+  }
+
+  /// Resolve `exp` tested for being non-zero, which requires a scalar.
+  fn resolve_condition(&mut self, exp: &mut Expression) {
+    self.resolve_expression(exp);
+    if !exp.ty().is_scalar() {
+      self.diagnostics.push(
+        SyntaxError::new_semantic_error(format!("{} used where a scalar is required", exp.ty()))
+          .with_span(exp.span)
+          .with_code("E0416"),
+      );
+    }
+  }
+
+  /// Resolve the names used in `exp` and annotate it with its type, erroneous
+  /// expressions are given type `int` to carry on checking.
+  fn resolve_object(&mut self, exp: &mut Expression) {
+    let span = exp.span;
+    let ty = match &mut exp.kind {
+      ExpressionKind::Const(_, ty) => ty.clone(),
+      ExpressionKind::ConstFloat(_, ty) => ty.clone(),
+      // the array includes the terminating null character
+      ExpressionKind::String(val) => Type::Array(Box::new(Type::Char), val.len() + 1),
+      ExpressionKind::Var { name } => match self.resolve_variable(name, span) {
+        Some((unique, ty)) => {
+          *name = unique;
+          ty
+        }
+        None => Type::Int,
+      },
+      ExpressionKind::Assign { target, exp } => {
+        self.resolve_object(target);
+        self.resolve_expression(exp);
+        if self.check_assignable(target) {
+          self.report(convert_by_assignment(
+            exp,
+            target.ty(),
+            self.symbols.types(),
+          ));
+        }
+        target.ty().clone()
+      }
+      ExpressionKind::CompoundAssign {
+        op,
+        target,
+        exp,
+        operation_type,
+        ..
+      } => {
+        self.resolve_object(target);
+        self.resolve_expression(exp);
+        if self.check_assignable(target) {
+          // the operation is typed as if the value of the target was its
+          // left operand
+          let mut value = Expression::new(ExpressionKind::Const(0, Type::Int), target.span)
+            .with_type(target.ty().clone());
+          let ty = self.report(binary_type(*op, &mut value, exp, self.symbols.types()));
+          match ty {
+            // the result is assigned back, pointers only to pointers
+            Some(ty) if ty.is_pointer() != target.ty().is_pointer() => {
+              self.diagnostics.push(
+                SyntaxError::new_semantic_error(format!(
+                  "cannot convert {} to {}",
+                  ty,
+                  target.ty()
+                ))
+                .with_span(span)
+                .with_code("E0417"),
+              );
+            }
+            Some(_) => *operation_type = value.ty,
+            None => {}
+          }
+        }
+        target.ty().clone()
+      }
+      ExpressionKind::Comma { exp1, exp2 } => {
+        self.resolve_expression(exp1);
+        self.resolve_expression(exp2);
+        exp2.ty().clone()
+      }
+      ExpressionKind::Unary { op, exp } => {
+        if let UnaryOperator::AddressOf = op {
+          self.resolve_object(exp);
+        } else {
+          self.resolve_expression(exp);
+        }
+        self.report(unary_type(*op, exp)).unwrap_or(Type::Int)
+      }
+      ExpressionKind::Binary { exp1, op, exp2 } => {
+        self.resolve_expression(exp1);
+        self.resolve_expression(exp2);
+        self
+          .report(binary_type(*op, exp1, exp2, self.symbols.types()))
+          .unwrap_or(Type::Int)
+      }
+      ExpressionKind::Conditional {
+        condition,
+        then,
+        otherwise,
+      } => {
+        self.resolve_condition(condition);
+        self.resolve_expression(then);
+        self.resolve_expression(otherwise);
+        self
+          .report(conditional_type(then, otherwise, self.symbols.types()))
+          .unwrap_or(Type::Int)
+      }
+      ExpressionKind::FunctionCall { name, args } => {
+        for arg in args.iter_mut() {
+          self.resolve_expression(arg);
+        }
+        let (params, return_type) = self.check_call(name, args.len(), span);
+        // arguments are converted as if assigned to the parameters, any
+        // further ones only undergo the default argument promotions
+        for (idx, arg) in args.iter_mut().enumerate() {
+          if let Some(ty) = params.get(idx) {
+            self.report(convert_by_assignment(arg, ty, self.symbols.types()));
+          } else {
+            promote_argument(arg);
+          }
+        }
+        return_type
+      }
+      ExpressionKind::Cast { target, exp } => {
+        *target = self.resolve_type(target, span);
+        self.resolve_expression(exp);
+        // pointers only convert to and from integers
+        let floating_pointer = (target.is_pointer() && exp.ty().is_floating())
+          || (target.is_floating() && exp.ty().is_pointer());
+        if !target.is_scalar() || !exp.ty().is_scalar() || floating_pointer {
+          self.diagnostics.push(
+            SyntaxError::new_semantic_error(format!("cannot cast {} to {}", exp.ty(), target))
+              .with_span(span)
+              .with_code("E0417"),
+          );
+        }
+        target.clone()
+      }
+      ExpressionKind::Subscript { exp, index } => {
+        self.resolve_expression(exp);
+        self.resolve_expression(index);
+        self
+          .report(subscript_type(exp, index, self.symbols.types()))
+          .unwrap_or(Type::Int)
+      }
+      ExpressionKind::Member { exp, member } => {
+        self.resolve_object(exp);
+        self.member_type(exp, member, span).unwrap_or(Type::Int)
+      }
+      ExpressionKind::SizeOf(exp) => {
+        self.resolve_object(exp);
+        self.require_complete(exp.ty(), exp.span);
+        Type::ULong
+      }
+      ExpressionKind::SizeOfType(ty) => {
+        *ty = self.resolve_type(ty, span);
+        self.require_complete(ty, span);
+        Type::ULong
+      }
+    };
+    exp.ty = Some(ty);
+  }
+
+  /// Check that `target` designates an object which may be assigned to.
+  fn check_assignable(&mut self, target: &Expression) -> bool {
+    if target.is_lvalue() && !target.ty().is_array() {
+      return true;
+    }
+    self.diagnostics.push(
+      SyntaxError::new_semantic_error("expression is not assignable".to_string())
+        .with_span(target.span)
+        .with_code("E0413")
+        .with_help(
+          "only variables, array elements and dereferenced pointers can be assigned to".to_string(),
+        ),
+    );
+    false
+  }
+
+  /// Check that `name` names a function taking `arity` arguments, returning
+  /// its parameter and return types. Functions not declared at all are left
+  /// for the linker to find and assumed to return `int`.
+  fn check_call(&mut self, name: &str, arity: usize, span: Span) -> (Vec<Type>, Type) {
+    if let Some(entry) = self.lookup(name) {
+      if !entry.has_linkage || !self.is_function(name) {
+        self.diagnostics.push(
+          SyntaxError::new_semantic_error(format!("called object {} is not a function", name))
+            .with_span(span)
+            .with_code("E0410")
+            .with_note(format!("{} is declared at {}", name, entry.span)),
+        );
+        return (vec![], Type::Int);
+      }
+    }
+
+    let Some(Symbol {
+      kind:
+        SymbolKind::Function {
+          params,
+          variadic,
+          return_type,
+          ..
+        },
+      span: declared,
+      ..
+    }) = self.symbols.get(name)
+    else {
+      return (vec![], Type::Int);
+    };
+
+    if params.len() != arity && !(*variadic && arity > params.len()) {
+      let error = SyntaxError::new_semantic_error(format!(
+        "function {} takes {}{} argument(s) but {} were supplied",
+        name,
+        if *variadic { "at least " } else { "" },
+        params.len(),
+        arity
+      ))
+      .with_span(span)
+      .with_code("E0403")
+      .with_note(format!("{} is declared at {}", name, declared));
+      let return_type = return_type.clone();
+      self.diagnostics.push(error);
+      return (vec![], return_type);
+    }
+    (params.clone(), return_type.clone())
+  }
+}
@@ -0,0 +1,219 @@
+use std::rc::Rc;
+
+use crate::{
+  ast::{model::*, Type},
+  utility::{Span, SyntaxError},
+};
+
+use super::{Analyzer, StructLayout};
+
+/// What a structure or union tag visible in a scope refers to.
+#[derive(Clone)]
+pub(super) struct TagEntry {
+  /// Tag of the type in the [`super::TypeTable`], unique within the
+  /// translation unit for tags declared in a block.
+  unique: Rc<String>,
+  kind: StructKind,
+  span: Span,
+}
+
+impl Analyzer {
+  fn lookup_tag(&self, tag: &str) -> Option<TagEntry> {
+    self
+      .scopes
+      .iter()
+      .rev()
+      .find_map(|scope| scope.tags.get(tag))
+      .cloned()
+  }
+
+  /// Declare `tag` in the innermost scope unless it already is, returning the
+  /// unique tag of the type it names.
+  fn declare_tag(&mut self, kind: StructKind, tag: &Rc<String>, span: Span) -> Option<Rc<String>> {
+    let file_scope = self.scopes.len() == 1;
+    let previous = self.scopes.last()?.tags.get(tag.as_str()).cloned();
+    match previous {
+      Some(entry) if entry.kind == kind => Some(entry.unique),
+      Some(entry) => {
+        self
+          .diagnostics
+          .push(Self::tag_kind_error(kind, tag, span, &entry));
+        None
+      }
+      None => {
+        let unique = if file_scope {
+          Rc::clone(tag)
+        } else {
+          self.unique_name(tag)
+        };
+        let entry = TagEntry {
+          unique: Rc::clone(&unique),
+          kind,
+          span,
+        };
+        self.scopes.last_mut()?.tags.insert(tag.to_string(), entry);
+        Some(unique)
+      }
+    }
+  }
+
+  fn tag_kind_error(kind: StructKind, tag: &str, span: Span, previous: &TagEntry) -> SyntaxError {
+    let tag = Rc::new(tag.to_string());
+    SyntaxError::new_semantic_error(format!(
+      "use of {} with a tag previously declared as {}",
+      kind.with_tag(Rc::clone(&tag)),
+      previous.kind.with_tag(tag)
+    ))
+    .with_span(span)
+    .with_code("E0419")
+    .with_note(format!("previously declared at {}", previous.span))
+  }
+
+  /// Declare the tag of a structure or union and record its layout when the
+  /// declaration lists the members.
+  pub(super) fn declare_struct(&mut self, declaration: &mut StructDeclaration) {
+    let Some(unique) = self.declare_tag(declaration.kind, &declaration.tag, declaration.span)
+    else {
+      return;
+    };
+    let Some(members) = &mut declaration.members else {
+      return;
+    };
+
+    let ty = declaration.kind.with_tag(Rc::clone(&unique));
+    if let Some(previous) = self.symbols.types().layout(&ty) {
+      let error = SyntaxError::new_semantic_error(format!("redefinition of {}", ty))
+        .with_span(declaration.span)
+        .with_code("E0420")
+        .with_note(format!("previously defined at {}", previous.span));
+      self.diagnostics.push(error);
+      return;
+    }
+
+    let mut resolved: Vec<(Rc<String>, Type)> = vec![];
+    for member in members.iter_mut() {
+      member.ty = self.resolve_type(&member.ty, member.span);
+      // this also rejects a structure containing itself
+      if !self.require_complete(&member.ty, member.span) {
+        return;
+      }
+      // an array too large was reported when its type was resolved
+      if self.symbols.types().checked_size(&member.ty).is_none() {
+        return;
+      }
+      if let Some((name, _)) = resolved.iter().find(|(name, _)| *name == member.name) {
+        self.diagnostics.push(
+          SyntaxError::new_semantic_error(format!("duplicate member {} in {}", name, ty))
+            .with_span(member.span)
+            .with_code("E0422"),
+        );
+        return;
+      }
+      resolved.push((Rc::clone(&member.name), member.ty.clone()));
+    }
+
+    let Some(layout) = StructLayout::new(
+      declaration.kind,
+      resolved,
+      declaration.span,
+      self.symbols.types(),
+    ) else {
+      self.diagnostics.push(too_large(&ty, declaration.span));
+      return;
+    };
+    self.symbols.types_mut().insert(&unique, layout);
+  }
+
+  /// `ty` with the tags it names replaced by their unique tags, a tag which is
+  /// not visible is declared as an incomplete type in the innermost scope.
+  pub(super) fn resolve_type(&mut self, ty: &Type, span: Span) -> Type {
+    match ty {
+      Type::Pointer(pointee) => Type::Pointer(Box::new(self.resolve_type(pointee, span))),
+      Type::Array(element, len) => {
+        let element = self.resolve_type(element, span);
+        let types = self.symbols.types();
+        // an element too large was already reported
+        let too_large_array = types.is_complete(&element)
+          && types
+            .checked_size(&element)
+            .is_some_and(|size| size.checked_mul(*len as i64).is_none());
+        let array = Type::Array(Box::new(element), *len);
+        if too_large_array {
+          self.diagnostics.push(too_large(&array, span));
+        }
+        array
+      }
+      Type::Struct(tag) | Type::Union(tag) => {
+        let kind = match ty {
+          Type::Union(_) => StructKind::Union,
+          _ => StructKind::Struct,
+        };
+        let unique = match self.lookup_tag(tag) {
+          Some(entry) if entry.kind == kind => Some(entry.unique),
+          Some(entry) => {
+            self
+              .diagnostics
+              .push(Self::tag_kind_error(kind, tag, span, &entry));
+            None
+          }
+          None => self.declare_tag(kind, tag, span),
+        };
+        kind.with_tag(unique.unwrap_or_else(|| Rc::clone(tag)))
+      }
+      ty => ty.clone(),
+    }
+  }
+
+  /// Check that objects of type `ty` can be created, reporting an error
+  /// naming the incomplete type otherwise.
+  pub(super) fn require_complete(&mut self, ty: &Type, span: Span) -> bool {
+    if self.symbols.types().is_complete(ty) {
+      return true;
+    }
+    self.diagnostics.push(
+      SyntaxError::new_semantic_error(format!("{} is an incomplete type", ty))
+        .with_span(span)
+        .with_code("E0421")
+        .with_help("define its members before it is used".to_string()),
+    );
+    false
+  }
+
+  /// Type of the member `name` of `exp`, which must be a structure or union.
+  pub(super) fn member_type(&mut self, exp: &Expression, name: &str, span: Span) -> Option<Type> {
+    let ty = exp.ty();
+    if !ty.is_aggregate() {
+      self.diagnostics.push(
+        SyntaxError::new_semantic_error(format!(
+          "request for member {} in a value of type {}",
+          name, ty
+        ))
+        .with_span(span)
+        .with_code("E0423"),
+      );
+      return None;
+    }
+    if !self.require_complete(ty, exp.span) {
+      return None;
+    }
+    match self.symbols.types().member(ty, name) {
+      Some(member) => Some(member.ty.clone()),
+      None => {
+        let layout = self.symbols.types().layout(ty)?;
+        let error = SyntaxError::new_semantic_error(format!("{} has no member named {}", ty, name))
+          .with_span(span)
+          .with_code("E0424")
+          .with_note(format!("{} is defined at {}", ty, layout.span));
+        self.diagnostics.push(error);
+        None
+      }
+    }
+  }
+}
+
+/// Error for an object of type `ty` whose size does not fit in an `i64`.
+fn too_large(ty: &Type, span: Span) -> SyntaxError {
+  SyntaxError::new_semantic_error(format!("size of {} is too large", ty))
+    .with_span(span)
+    .with_code("E0428")
+}
@@ -0,0 +1,110 @@
+use std::{collections::HashMap, rc::Rc};
+
+use super::TypeTable;
+use crate::{ast::Type, utility::Span};
+
+/// Piece of the initial value of a variable with static storage duration,
+/// laid out one after the other in memory.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StaticInit {
+  /// Value of a scalar type, already converted to it, floating values as
+  /// the bits of their representation.
+  Scalar(Type, i64),
+  /// Characters of a string literal initialising an array of `char`.
+  String(Rc<Vec<u8>>),
+  /// Address of a string literal, which is placed in read-only memory.
+  StringPointer(Rc<Vec<u8>>),
+  /// Address of the variable with static storage named by the label, plus a
+  /// number of bytes.
+  Address(Rc<String>, i64),
+  /// Number of zero bytes.
+  Zero(i64),
+}
+
+impl StaticInit {
+  pub fn is_zero(&self) -> bool {
+    matches!(self, StaticInit::Zero(_) | StaticInit::Scalar(_, 0))
+  }
+}
+
+/// Initial value of a variable with static storage duration.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InitialValue {
+  /// Declared without an initialiser and without `extern`, zero unless
+  /// another declaration in the file supplies a value.
+  Tentative,
+  Initial(Vec<StaticInit>),
+  /// Only declared with `extern`, the storage lives in another file.
+  NoInitializer,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolKind {
+  Function {
+    params: Vec<Type>,
+    /// Whether further arguments may follow those for `params`.
+    variadic: bool,
+    return_type: Type,
+    defined: bool,
+  },
+  Variable {
+    ty: Type,
+    init: InitialValue,
+  },
+}
+
+/// Entity declared at file scope or with `extern` inside a block.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+  pub kind: SymbolKind,
+  /// Whether the symbol has external linkage and is visible to other files.
+  pub global: bool,
+  /// Most relevant declaration, the definition once one has been seen.
+  pub span: Span,
+}
+
+/// Symbols with linkage keyed by name, remembering the order they were first
+/// declared in so the generated assembly follows the source. Also holds the
+/// layouts of the structures the symbols are typed with.
+#[derive(Default)]
+pub struct SymbolTable {
+  symbols: HashMap<String, Symbol>,
+  order: Vec<String>,
+  types: TypeTable,
+}
+
+impl SymbolTable {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn get(&self, name: &str) -> Option<&Symbol> {
+    self.symbols.get(name)
+  }
+
+  pub fn get_mut(&mut self, name: &str) -> Option<&mut Symbol> {
+    self.symbols.get_mut(name)
+  }
+
+  pub fn insert(&mut self, name: &str, symbol: Symbol) {
+    if self.symbols.insert(name.to_string(), symbol).is_none() {
+      self.order.push(name.to_string());
+    }
+  }
+
+  pub fn types(&self) -> &TypeTable {
+    &self.types
+  }
+
+  pub fn types_mut(&mut self) -> &mut TypeTable {
+    &mut self.types
+  }
+
+  /// Symbols in the order of their first declaration.
+  pub fn iter(&self) -> impl Iterator<Item = (&String, &Symbol)> {
+    self
+      .order
+      .iter()
+      .filter_map(|name| self.symbols.get_key_value(name))
+  }
+}
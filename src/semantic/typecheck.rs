@@ -0,0 +1,374 @@
+use crate::{
+  ast::{model::*, Type},
+  lex::{BinaryOperator, UnaryOperator},
+  utility::{Span, SyntaxError},
+};
+
+use super::{evaluate_constant, Constant, TypeTable};
+
+/// Convert `exp` to `target`, wrapping it in a cast unless it already has
+/// that type.
+pub(super) fn convert_to(exp: &mut Expression, target: &Type) {
+  if exp.ty.as_ref() == Some(target) {
+    return;
+  }
+  let span = exp.span;
+  let inner = std::mem::replace(
+    exp,
+    Expression::new(ExpressionKind::Const(0, Type::Int), span),
+  );
+  *exp = Expression::new(
+    ExpressionKind::Cast {
+      target: target.clone(),
+      exp: Box::new(inner),
+    },
+    span,
+  )
+  .with_type(target.clone());
+}
+
+/// Turn `exp` of array type into a pointer to its first element, as happens
+/// to arrays anywhere but as the operand of `&` or `sizeof`.
+pub(super) fn decay(exp: &mut Expression) {
+  let Some(Type::Array(element, _)) = &exp.ty else {
+    return;
+  };
+  let pointer = Type::Pointer(element.clone());
+  let span = exp.span;
+  let inner = std::mem::replace(
+    exp,
+    Expression::new(ExpressionKind::Const(0, Type::Int), span),
+  );
+  // the address of an array is that of its first element
+  *exp = Expression::new(
+    ExpressionKind::Unary {
+      op: UnaryOperator::AddressOf,
+      exp: Box::new(inner),
+    },
+    span,
+  )
+  .with_type(pointer);
+}
+
+/// Apply the default argument promotions to an argument without a parameter
+/// type to convert it to, which also turn `float` into `double`.
+pub(super) fn promote_argument(exp: &mut Expression) {
+  let ty = match exp.ty() {
+    Type::Float => Type::Double,
+    ty if ty.is_integer() => ty.promote(),
+    _ => return,
+  };
+  convert_to(exp, &ty);
+}
+
+/// Check that `init` has the shape of a value of type `ty`, a brace enclosed
+/// list with at most one initialiser per element for arrays, per member for
+/// structures and for the first member of unions, and a single expression
+/// otherwise. Structures and unions may also be copied from an expression,
+/// and arrays of characters initialised with a string literal which fits
+/// them, except for its terminating null character.
+pub(super) fn check_initializer_shape(
+  ty: &Type,
+  init: &Initializer,
+  types: &TypeTable,
+) -> Result<(), SyntaxError> {
+  let message = match (ty, init) {
+    (Type::Array(_, len), Initializer::Compound(initializers, _)) if initializers.len() > *len => {
+      format!("too many initialisers for {}", ty)
+    }
+    (Type::Struct(_) | Type::Union(_), Initializer::Compound(initializers, _)) => {
+      let members = match types.layout(ty) {
+        Some(layout) if layout.kind == StructKind::Union => 1,
+        Some(layout) => layout.members.len(),
+        None => 0,
+      };
+      if initializers.len() <= members {
+        return Ok(());
+      }
+      format!("too many initialisers for {}", ty)
+    }
+    (Type::Array(element, len), Initializer::Single(exp))
+      if matches!(**element, Type::Char | Type::UChar) =>
+    {
+      match &exp.kind {
+        ExpressionKind::String(val) if val.len() <= *len => return Ok(()),
+        ExpressionKind::String(_) => format!("initialiser string for {} is too long", ty),
+        _ => format!(
+          "{} must be initialised with a brace enclosed list or a string literal",
+          ty
+        ),
+      }
+    }
+    (Type::Array(..), Initializer::Single(_)) => {
+      format!("{} must be initialised with a brace enclosed list", ty)
+    }
+    (Type::Array(..), Initializer::Compound(..)) | (_, Initializer::Single(_)) => return Ok(()),
+    (_, Initializer::Compound(..)) => format!("{} cannot be initialised with a list", ty),
+  };
+  Err(
+    SyntaxError::new_semantic_error(message)
+      .with_span(init.span())
+      .with_code("E0418"),
+  )
+}
+
+/// Error for arithmetic on a pointer to `pointee` when it is incomplete, as
+/// stepping over an element needs its size.
+fn check_pointee(pointee: &Type, span: Span, types: &TypeTable) -> Result<(), SyntaxError> {
+  if types.is_complete(pointee) {
+    return Ok(());
+  }
+  Err(
+    SyntaxError::new_semantic_error(format!(
+      "arithmetic on a pointer to the incomplete type {}",
+      pointee
+    ))
+    .with_span(span)
+    .with_code("E0421"),
+  )
+}
+
+/// Type of `exp[index]`, converting the integer operand to `long` like in
+/// the pointer addition it stands for.
+pub(super) fn subscript_type(
+  exp: &mut Expression,
+  index: &mut Expression,
+  types: &TypeTable,
+) -> Result<Type, SyntaxError> {
+  let span = exp.span.to(index.span);
+  match (exp.ty().pointee().cloned(), index.ty().pointee().cloned()) {
+    (Some(element), None) if index.ty().is_integer() => {
+      check_pointee(&element, span, types)?;
+      convert_to(index, &Type::Long);
+      Ok(element)
+    }
+    (None, Some(element)) if exp.ty().is_integer() => {
+      check_pointee(&element, span, types)?;
+      convert_to(exp, &Type::Long);
+      Ok(element)
+    }
+    _ => Err(
+      SyntaxError::new_semantic_error(format!(
+        "subscripted value of type {} is not an array or pointer",
+        exp.ty()
+      ))
+      .with_span(exp.span.to(index.span))
+      .with_code("E0415"),
+    ),
+  }
+}
+
+/// Whether `exp` is a null pointer constant, an integer constant expression
+/// with value 0 which converts to any pointer type.
+fn is_null_pointer(exp: &Expression, types: &TypeTable) -> bool {
+  exp.ty().is_integer() && evaluate_constant(exp, types) == Some(Constant::Int(0))
+}
+
+/// Convert `exp` to `target` as if it was assigned to an object of that type,
+/// which unlike a cast never turns an integer into a pointer or back.
+pub(super) fn convert_by_assignment(
+  exp: &mut Expression,
+  target: &Type,
+  types: &TypeTable,
+) -> Result<(), SyntaxError> {
+  let ty = exp.ty();
+  if ty == target
+    || (ty.is_arithmetic() && target.is_arithmetic())
+    || (target.is_pointer() && is_null_pointer(exp, types))
+  {
+    convert_to(exp, target);
+    return Ok(());
+  }
+  Err(
+    SyntaxError::new_semantic_error(format!("cannot convert {} to {}", ty, target))
+      .with_span(exp.span)
+      .with_code("E0417")
+      .with_help("add an explicit cast if the conversion is intended".to_string()),
+  )
+}
+
+fn invalid_operands(op: BinaryOperator, exp1: &Expression, exp2: &Expression) -> SyntaxError {
+  SyntaxError::new_semantic_error(format!(
+    "invalid operands of types {} and {} to {:?}",
+    exp1.ty(),
+    exp2.ty(),
+    op
+  ))
+  .with_span(exp1.span.to(exp2.span))
+  .with_code("E0416")
+}
+
+/// Type of a unary expression, promoting its operand where the operator
+/// works on its value.
+pub(super) fn unary_type(op: UnaryOperator, exp: &mut Expression) -> Result<Type, SyntaxError> {
+  match op {
+    UnaryOperator::LogicalNegation if exp.ty().is_scalar() => Ok(Type::Int),
+    UnaryOperator::LogicalNegation | UnaryOperator::Negation | UnaryOperator::BitwiseComplement
+      if !exp.ty().is_arithmetic()
+        || (op == UnaryOperator::BitwiseComplement && exp.ty().is_floating()) =>
+    {
+      Err(
+        SyntaxError::new_semantic_error(format!(
+          "invalid operand of type {} to {:?}",
+          exp.ty(),
+          op
+        ))
+        .with_span(exp.span)
+        .with_code("E0416"),
+      )
+    }
+    UnaryOperator::LogicalNegation => Ok(Type::Int),
+    UnaryOperator::Negation | UnaryOperator::BitwiseComplement => {
+      let ty = exp.ty().promote();
+      convert_to(exp, &ty);
+      Ok(ty)
+    }
+    UnaryOperator::AddressOf if exp.is_lvalue() => Ok(Type::Pointer(Box::new(exp.ty().clone()))),
+    UnaryOperator::AddressOf => Err(
+      SyntaxError::new_semantic_error("cannot take the address of an rvalue".to_string())
+        .with_span(exp.span)
+        .with_code("E0414"),
+    ),
+    UnaryOperator::Dereference => exp.ty().pointee().cloned().ok_or_else(|| {
+      SyntaxError::new_semantic_error(format!(
+        "indirection requires a pointer operand, not {}",
+        exp.ty()
+      ))
+      .with_span(exp.span)
+      .with_code("E0415")
+    }),
+  }
+}
+
+/// Type of a binary expression, converting arithmetic operands to their
+/// common type and the integer operand of pointer arithmetic to `long`.
+pub(super) fn binary_type(
+  op: BinaryOperator,
+  exp1: &mut Expression,
+  exp2: &mut Expression,
+  types: &TypeTable,
+) -> Result<Type, SyntaxError> {
+  if !exp1.ty().is_scalar() || !exp2.ty().is_scalar() {
+    return Err(invalid_operands(op, exp1, exp2));
+  }
+  if let BinaryOperator::And | BinaryOperator::Or = op {
+    return Ok(Type::Int);
+  }
+  let integer_only = matches!(
+    op,
+    BinaryOperator::Remainder
+      | BinaryOperator::BitwiseAnd
+      | BinaryOperator::BitwiseOr
+      | BinaryOperator::BitwiseXor
+      | BinaryOperator::ShiftLeft
+      | BinaryOperator::ShiftRight
+  );
+  if integer_only && (!exp1.ty().is_integer() || !exp2.ty().is_integer()) {
+    return Err(invalid_operands(op, exp1, exp2));
+  }
+  if let BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight = op {
+    // the operands are promoted on their own, the shift count does not
+    // affect the type of the result
+    let (ty1, ty2) = (exp1.ty().promote(), exp2.ty().promote());
+    convert_to(exp1, &ty1);
+    convert_to(exp2, &ty2);
+    return Ok(ty1);
+  }
+  if exp1.ty().is_pointer() || exp2.ty().is_pointer() {
+    return pointer_binary_type(op, exp1, exp2, types);
+  }
+
+  let common = exp1.ty().common(exp2.ty());
+  convert_to(exp1, &common);
+  convert_to(exp2, &common);
+  Ok(match op {
+    BinaryOperator::Equal
+    | BinaryOperator::NotEqual
+    | BinaryOperator::LessThan
+    | BinaryOperator::LessThanOrEqual
+    | BinaryOperator::GreaterThan
+    | BinaryOperator::GreaterThanOrEqual => Type::Int,
+    _ => common,
+  })
+}
+
+/// Type of a binary expression with at least one pointer operand.
+fn pointer_binary_type(
+  op: BinaryOperator,
+  exp1: &mut Expression,
+  exp2: &mut Expression,
+  types: &TypeTable,
+) -> Result<Type, SyntaxError> {
+  let (ty1, ty2) = (exp1.ty().clone(), exp2.ty().clone());
+  // neither a pointer nor the offset added to one may be floating
+  if ty1.is_floating() || ty2.is_floating() {
+    return Err(invalid_operands(op, exp1, exp2));
+  }
+  if let BinaryOperator::Addition | BinaryOperator::Minus = op {
+    let pointee = ty1.pointee().or(ty2.pointee()).unwrap_or(&ty1);
+    check_pointee(pointee, exp1.span.to(exp2.span), types)?;
+  }
+  match op {
+    BinaryOperator::Addition if ty1.is_pointer() && !ty2.is_pointer() => {
+      convert_to(exp2, &Type::Long);
+      Ok(ty1)
+    }
+    BinaryOperator::Addition if !ty1.is_pointer() && ty2.is_pointer() => {
+      convert_to(exp1, &Type::Long);
+      Ok(ty2)
+    }
+    BinaryOperator::Minus if !ty2.is_pointer() => {
+      convert_to(exp2, &Type::Long);
+      Ok(ty1)
+    }
+    // the difference of two pointers counts elements between them
+    BinaryOperator::Minus if ty1 == ty2 => Ok(Type::Long),
+    BinaryOperator::Equal
+    | BinaryOperator::NotEqual
+    | BinaryOperator::LessThan
+    | BinaryOperator::LessThanOrEqual
+    | BinaryOperator::GreaterThan
+    | BinaryOperator::GreaterThanOrEqual
+      if ty1 == ty2 =>
+    {
+      Ok(Type::Int)
+    }
+    BinaryOperator::Equal | BinaryOperator::NotEqual if is_null_pointer(exp1, types) => {
+      convert_to(exp1, &ty2);
+      Ok(Type::Int)
+    }
+    BinaryOperator::Equal | BinaryOperator::NotEqual if is_null_pointer(exp2, types) => {
+      convert_to(exp2, &ty1);
+      Ok(Type::Int)
+    }
+    _ => Err(invalid_operands(op, exp1, exp2)),
+  }
+}
+
+/// Type of a conditional expression, converting both branches to it.
+pub(super) fn conditional_type(
+  then: &mut Expression,
+  otherwise: &mut Expression,
+  types: &TypeTable,
+) -> Result<Type, SyntaxError> {
+  let (ty1, ty2) = (then.ty().clone(), otherwise.ty().clone());
+  let common = if ty1.is_arithmetic() && ty2.is_arithmetic() {
+    ty1.common(&ty2)
+  } else if ty1 == ty2 || (ty1.is_pointer() && is_null_pointer(otherwise, types)) {
+    ty1
+  } else if ty2.is_pointer() && is_null_pointer(then, types) {
+    ty2
+  } else {
+    return Err(
+      SyntaxError::new_semantic_error(format!(
+        "mismatched types {} and {} in conditional expression",
+        ty1, ty2
+      ))
+      .with_span(then.span.to(otherwise.span))
+      .with_code("E0416"),
+    );
+  };
+  convert_to(then, &common);
+  convert_to(otherwise, &common);
+  Ok(common)
+}
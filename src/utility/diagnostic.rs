@@ -0,0 +1,261 @@
+use std::{
+  fmt::Write,
+  io::{stderr, IsTerminal},
+};
+
+use super::{SourceMap, Span, SyntaxError};
+
+/// Number of errors reported before the compiler gives up on a file.
+pub const DEFAULT_ERROR_LIMIT: usize = 20;
+
+const RESET: &str = "\x1b[0m";
+const BOLD_RED: &str = "\x1b[1;31m";
+const BOLD_BLUE: &str = "\x1b[1;34m";
+const BOLD_CYAN: &str = "\x1b[1;36m";
+const BOLD: &str = "\x1b[1m";
+
+/// Errors collected while compiling, holding at most `limit` of them.
+#[derive(Debug)]
+pub struct Diagnostics {
+  errors: Vec<SyntaxError>,
+  limit: usize,
+}
+
+impl Diagnostics {
+  pub fn with_limit(limit: usize) -> Self {
+    Self {
+      errors: vec![],
+      limit: limit.max(1),
+    }
+  }
+
+  /// Record `error`, errors past the limit are dropped.
+  pub fn push(&mut self, error: SyntaxError) {
+    if !self.is_full() {
+      self.errors.push(error);
+    }
+  }
+
+  /// Whether the error limit has been reached and work should stop.
+  pub fn is_full(&self) -> bool {
+    self.errors.len() >= self.limit
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.errors.is_empty()
+  }
+
+  pub fn len(&self) -> usize {
+    self.errors.len()
+  }
+
+  pub fn iter(&self) -> std::slice::Iter<'_, SyntaxError> {
+    self.errors.iter()
+  }
+}
+
+impl From<SyntaxError> for Diagnostics {
+  fn from(error: SyntaxError) -> Self {
+    let mut diagnostics = Self::with_limit(DEFAULT_ERROR_LIMIT);
+    diagnostics.push(error);
+    diagnostics
+  }
+}
+
+/// Renders [`SyntaxError`] values as rustc/clang style diagnostics, quoting the
+/// offending source line and underlining the error span.
+///
+/// ```text
+/// error[E0201]: parenthesis not balanced
+///  --> main.c:4:16 (parser)
+///   |
+/// 4 |   return b - (a;
+///   |                ^
+///   = help: add the missing `)`
+/// ```
+pub struct DiagnosticRenderer<'a> {
+  sources: &'a SourceMap,
+  colour: bool,
+}
+
+impl<'a> DiagnosticRenderer<'a> {
+  /// Create a renderer which colours its output when stderr is a terminal.
+  pub fn new(sources: &'a SourceMap) -> Self {
+    Self {
+      sources,
+      colour: stderr().is_terminal(),
+    }
+  }
+
+  pub fn with_colour(mut self, colour: bool) -> Self {
+    self.colour = colour;
+    self
+  }
+
+  fn paint(&self, style: &str, text: &str) -> String {
+    if self.colour {
+      format!("{}{}{}", style, text, RESET)
+    } else {
+      text.to_string()
+    }
+  }
+
+  pub fn render(&self, error: &SyntaxError) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+      out,
+      "{}{}",
+      self.paint(BOLD_RED, &format!("error[{}]", error.code())),
+      self.paint(BOLD, &format!(": {}", error.message()))
+    );
+
+    let gutter_width = error
+      .span()
+      .map(|span| span.line.to_string().len())
+      .unwrap_or(1);
+    let gutter = " ".repeat(gutter_width);
+
+    if let Some(span) = error.span() {
+      let _ = writeln!(
+        out,
+        "{}{} {}:{} ({})",
+        gutter,
+        self.paint(BOLD_BLUE, "-->"),
+        self.sources.name(span.file),
+        span,
+        error.stage()
+      );
+
+      if let Some(line) = self.sources.line(span.file, span.line) {
+        let bar = self.paint(BOLD_BLUE, "|");
+        let _ = writeln!(out, "{} {}", gutter, bar);
+        let _ = writeln!(
+          out,
+          "{} {} {}",
+          self.paint(BOLD_BLUE, &span.line.to_string()),
+          bar,
+          line
+        );
+        let _ = writeln!(out, "{} {} {}", gutter, bar, self.underline(line, span));
+      }
+    } else {
+      let _ = writeln!(
+        out,
+        "{}{} ({})",
+        gutter,
+        self.paint(BOLD_BLUE, "-->"),
+        error.stage()
+      );
+    }
+
+    // the chain of includes reads like gcc's "In file included from"
+    for include in error
+      .span()
+      .map(|span| self.sources.include_stack(span.file))
+      .unwrap_or_default()
+    {
+      let _ = writeln!(
+        out,
+        "{} {} {}",
+        gutter,
+        self.paint(BOLD_BLUE, "="),
+        self.paint(
+          BOLD_CYAN,
+          &format!(
+            "note: in file included from {}:{}",
+            self.sources.name(include.file),
+            include
+          )
+        )
+      );
+    }
+    // like gcc, the invocations leading to a token copied out of a macro
+    for (name, invocation) in error
+      .span()
+      .map(|span| self.sources.expansion_stack(span))
+      .unwrap_or_default()
+    {
+      let _ = writeln!(
+        out,
+        "{} {} {}",
+        gutter,
+        self.paint(BOLD_BLUE, "="),
+        self.paint(
+          BOLD_CYAN,
+          &format!(
+            "note: in expansion of macro {} at {}:{}",
+            name,
+            self.sources.name(invocation.file),
+            invocation
+          )
+        )
+      );
+    }
+    for note in error.notes() {
+      let _ = writeln!(
+        out,
+        "{} {} {}",
+        gutter,
+        self.paint(BOLD_BLUE, "="),
+        self.paint(BOLD_CYAN, &format!("note: {}", note))
+      );
+    }
+    for help in error.help() {
+      let _ = writeln!(
+        out,
+        "{} {} {}",
+        gutter,
+        self.paint(BOLD_BLUE, "="),
+        self.paint(BOLD_CYAN, &format!("help: {}", help))
+      );
+    }
+
+    out
+  }
+
+  /// Render every error in `diagnostics` followed by a summary line.
+  pub fn render_all(&self, diagnostics: &Diagnostics) -> String {
+    let mut out = String::new();
+    for error in diagnostics.iter() {
+      out.push_str(&self.render(error));
+      out.push('\n');
+    }
+
+    let summary = match diagnostics.len() {
+      1 => "aborting due to previous error".to_string(),
+      count if diagnostics.is_full() => {
+        format!("aborting after {} errors, error limit reached", count)
+      }
+      count => format!("aborting due to {} previous errors", count),
+    };
+    let _ = writeln!(
+      out,
+      "{}{}",
+      self.paint(BOLD_RED, "error"),
+      self.paint(BOLD, &format!(": {}", summary))
+    );
+    out
+  }
+
+  /// Carets under the part of `line` covered by `span`, spans running past the
+  /// end of the line are clipped to it.
+  fn underline(&self, line: &str, span: Span) -> String {
+    let start = (span.column - 1).min(line.len());
+    let end = (start + (span.end - span.start)).min(line.len());
+
+    // keep tabs in the padding so carets line up with the quoted source
+    let padding: String = line
+      .get(..start)
+      .unwrap_or_default()
+      .chars()
+      .map(|c| if c == '\t' { '\t' } else { ' ' })
+      .collect();
+    let width = line
+      .get(start..end)
+      .map(|text| text.chars().count())
+      .unwrap_or(0)
+      .max(1);
+
+    format!("{}{}", padding, self.paint(BOLD_RED, &"^".repeat(width)))
+  }
+}
@@ -1,42 +1,148 @@
 use std::{error::Error, fmt};
 
-#[derive(Debug)]
-enum CompilerStage {
-  LEXER,
-  PARSER,
-  CODEGENERATOR,
+pub mod diagnostic;
+pub mod span;
+
+pub use diagnostic::*;
+pub use span::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompilerStage {
+  Lexer,
+  Preprocessor,
+  Parser,
+  Semantic,
+  CodeGenerator,
+}
+
+impl CompilerStage {
+  /// Error code used when the raising site does not provide a specific one.
+  fn default_code(&self) -> &'static str {
+    match self {
+      CompilerStage::Lexer => "E0100",
+      CompilerStage::Preprocessor => "E0500",
+      CompilerStage::Parser => "E0200",
+      CompilerStage::Semantic => "E0400",
+      CompilerStage::CodeGenerator => "E0300",
+    }
+  }
+}
+
+impl fmt::Display for CompilerStage {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CompilerStage::Lexer => write!(f, "lexer"),
+      CompilerStage::Preprocessor => write!(f, "preprocessor"),
+      CompilerStage::Parser => write!(f, "parser"),
+      CompilerStage::Semantic => write!(f, "semantic"),
+      CompilerStage::CodeGenerator => write!(f, "codegen"),
+    }
+  }
 }
 
 #[derive(Debug)]
-pub struct SyntaxError {
+struct ErrorDetails {
   message: String,
   level: CompilerStage,
+  span: Option<Span>,
+  code: Option<&'static str>,
+  notes: Vec<String>,
+  help: Vec<String>,
 }
 
+/// Error raised by any compiler stage, details are boxed to keep `Result`s small.
+#[derive(Debug)]
+pub struct SyntaxError(Box<ErrorDetails>);
+
 impl SyntaxError {
-  pub fn new_lex_error(message: String) -> Self {
-    SyntaxError {
+  fn new(message: String, level: CompilerStage) -> Self {
+    SyntaxError(Box::new(ErrorDetails {
       message,
-      level: CompilerStage::LEXER,
-    }
+      level,
+      span: None,
+      code: None,
+      notes: vec![],
+      help: vec![],
+    }))
+  }
+  pub fn new_lex_error(message: String) -> Self {
+    Self::new(message, CompilerStage::Lexer)
+  }
+  pub fn new_preprocess_error(message: String) -> Self {
+    Self::new(message, CompilerStage::Preprocessor)
   }
   pub fn new_parse_error(message: String) -> Self {
-    SyntaxError {
-      message,
-      level: CompilerStage::PARSER,
-    }
+    Self::new(message, CompilerStage::Parser)
+  }
+  pub fn new_semantic_error(message: String) -> Self {
+    Self::new(message, CompilerStage::Semantic)
   }
   pub fn new_codegen_error(message: String) -> Self {
-    SyntaxError {
-      message,
-      level: CompilerStage::CODEGENERATOR,
-    }
+    Self::new(message, CompilerStage::CodeGenerator)
+  }
+
+  /// Attach the location the error was raised at.
+  pub fn with_span(mut self, span: Span) -> Self {
+    self.0.span = Some(span);
+    self
+  }
+
+  /// Attach a stable error code, e.g. `E0201`.
+  pub fn with_code(mut self, code: &'static str) -> Self {
+    self.0.code = Some(code);
+    self
+  }
+
+  /// Attach a `note:` line giving extra context about the error.
+  pub fn with_note(mut self, note: String) -> Self {
+    self.0.notes.push(note);
+    self
+  }
+
+  /// Attach a `help:` line suggesting how to fix the error.
+  pub fn with_help(mut self, help: String) -> Self {
+    self.0.help.push(help);
+    self
+  }
+
+  pub fn message(&self) -> &str {
+    &self.0.message
+  }
+
+  pub fn stage(&self) -> CompilerStage {
+    self.0.level
+  }
+
+  /// Location of the error in source, if one is known.
+  pub fn span(&self) -> Option<Span> {
+    self.0.span
+  }
+
+  pub fn code(&self) -> &'static str {
+    self.0.code.unwrap_or_else(|| self.0.level.default_code())
+  }
+
+  pub fn notes(&self) -> &[String] {
+    &self.0.notes
+  }
+
+  pub fn help(&self) -> &[String] {
+    &self.0.help
   }
 }
 
 impl fmt::Display for SyntaxError {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "{:?} Error {}", self.level, self.message)
+    if let Some(span) = self.0.span {
+      write!(f, "{}: ", span)?;
+    }
+    write!(
+      f,
+      "{} error[{}] {}",
+      self.0.level,
+      self.code(),
+      self.0.message
+    )
   }
 }
 
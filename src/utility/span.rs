@@ -0,0 +1,164 @@
+use std::fmt;
+
+/// Handle to a file registered inside a [`SourceMap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FileId(pub usize);
+
+/// Handle to a macro expansion registered inside a [`SourceMap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ExpansionId(pub usize);
+
+/// Region of source text a token or syntax node was read from.
+///
+/// `start` and `end` are byte offsets into the file, `line` and `column`
+/// are 1-based and point at the first character of the region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+  pub file: FileId,
+  pub start: usize,
+  pub end: usize,
+  pub line: usize,
+  pub column: usize,
+  /// Expansion of the macro whose replacement list the region was copied
+  /// out of.
+  pub expansion: Option<ExpansionId>,
+}
+
+impl Span {
+  pub fn new(file: FileId, start: usize, end: usize, line: usize, column: usize) -> Self {
+    Self {
+      file,
+      start,
+      end,
+      line,
+      column,
+      expansion: None,
+    }
+  }
+
+  /// Span starting at `self` and stretching up to the end of `other`.
+  pub fn to(self, other: Span) -> Span {
+    Span {
+      end: other.end.max(self.end),
+      ..self
+    }
+  }
+
+  /// Zero width span placed right after `self`, used when input ends early.
+  pub fn after(self) -> Span {
+    Span {
+      start: self.end,
+      column: self.column + (self.end - self.start),
+      ..self
+    }
+  }
+
+  /// The same region copied into the macro expansion `expansion`.
+  pub fn in_expansion(self, expansion: ExpansionId) -> Span {
+    Span {
+      expansion: Some(expansion),
+      ..self
+    }
+  }
+}
+
+impl fmt::Display for Span {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}", self.line, self.column)
+  }
+}
+
+struct SourceFile {
+  name: String,
+  source: String,
+  /// Location of the `#include` which pulled the file in.
+  included_from: Option<Span>,
+}
+
+/// Macro named `name` being expanded for its invocation at `invocation`.
+struct Expansion {
+  name: String,
+  invocation: Span,
+}
+
+/// Owner of every source file handed to the compiler, [`Span`] values refer
+/// back to these files through their [`FileId`].
+#[derive(Default)]
+pub struct SourceMap {
+  files: Vec<SourceFile>,
+  expansions: Vec<Expansion>,
+}
+
+impl SourceMap {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn add_file(&mut self, name: String, source: String) -> FileId {
+    self.files.push(SourceFile {
+      name,
+      source,
+      included_from: None,
+    });
+    FileId(self.files.len() - 1)
+  }
+
+  /// Register a file read for the `#include` directive at `included_from`.
+  pub fn add_included_file(&mut self, name: String, source: String, included_from: Span) -> FileId {
+    self.files.push(SourceFile {
+      name,
+      source,
+      included_from: Some(included_from),
+    });
+    FileId(self.files.len() - 1)
+  }
+
+  pub fn name(&self, file: FileId) -> &str {
+    &self.files[file.0].name
+  }
+
+  pub fn source(&self, file: FileId) -> &str {
+    &self.files[file.0].source
+  }
+
+  /// Location of the `#include` directive `file` was read for, `None` for the
+  /// files handed to the compiler.
+  pub fn included_from(&self, file: FileId) -> Option<Span> {
+    self.files[file.0].included_from
+  }
+
+  /// Locations of the `#include` directives leading to `file`, innermost first.
+  pub fn include_stack(&self, file: FileId) -> Vec<Span> {
+    let mut stack = vec![];
+    let mut file = file;
+    while let Some(span) = self.included_from(file) {
+      stack.push(span);
+      file = span.file;
+    }
+    stack
+  }
+
+  /// Register the expansion of the macro `name` invoked at `invocation`.
+  pub fn add_expansion(&mut self, name: String, invocation: Span) -> ExpansionId {
+    self.expansions.push(Expansion { name, invocation });
+    ExpansionId(self.expansions.len() - 1)
+  }
+
+  /// Names and invocations of the macros `span` was expanded from,
+  /// innermost first.
+  pub fn expansion_stack(&self, span: Span) -> Vec<(&str, Span)> {
+    let mut stack = vec![];
+    let mut expansion = span.expansion;
+    while let Some(ExpansionId(idx)) = expansion {
+      let Expansion { name, invocation } = &self.expansions[idx];
+      stack.push((name.as_str(), *invocation));
+      expansion = invocation.expansion;
+    }
+    stack
+  }
+
+  /// Text of the 1-based `line` inside `file` without its line terminator.
+  pub fn line(&self, file: FileId, line: usize) -> Option<&str> {
+    self.source(file).lines().nth(line.checked_sub(1)?)
+  }
+}
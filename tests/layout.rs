@@ -0,0 +1,64 @@
+use std::rc::Rc;
+
+use compiler::{
+  analyze, ast::Type, parse, semantic::TypeTable, tokenize, utility::SourceMap, CompileOptions,
+};
+
+/// Layouts of the structures and unions declared in `source`.
+fn types(source: &str) -> TypeTable {
+  let options = CompileOptions::new("test.c");
+  let mut sources = SourceMap::new();
+  let file = sources.add_file("test.c".to_string(), source.to_string());
+  let tokens = tokenize(&mut sources, file, &options).unwrap();
+  let mut program = parse(&tokens, &options).unwrap();
+  analyze(&mut program, &options).unwrap().types().clone()
+}
+
+fn structure(tag: &str) -> Type {
+  Type::Struct(Rc::new(tag.to_string()))
+}
+
+/// Offsets of the members of `ty` in declaration order.
+fn offsets(types: &TypeTable, ty: &Type) -> Vec<i64> {
+  let layout = types.layout(ty).unwrap();
+  layout.members.iter().map(|member| member.offset).collect()
+}
+
+// the expected values are the ones gcc gives on x86-64
+
+#[test]
+fn members_are_aligned_for_their_type() {
+  let types = types(
+    "struct mixed { char c; double d; int i; };\n\
+     struct small { char a; short b; char c; int d; };\n",
+  );
+  let mixed = structure("mixed");
+  assert_eq!((types.size(&mixed), types.alignment(&mixed)), (24, 8));
+  assert_eq!(offsets(&types, &mixed), [0, 8, 16]);
+  let small = structure("small");
+  assert_eq!((types.size(&small), types.alignment(&small)), (12, 4));
+  assert_eq!(offsets(&types, &small), [0, 2, 4, 8]);
+}
+
+#[test]
+fn nested_structures_and_arrays() {
+  let types = types(
+    "struct mixed { char c; double d; int i; };\n\
+     struct outer { char c; struct mixed m; };\n\
+     struct arrays { char c[3]; long l; float f[3]; };\n",
+  );
+  let outer = structure("outer");
+  assert_eq!((types.size(&outer), types.alignment(&outer)), (32, 8));
+  assert_eq!(offsets(&types, &outer), [0, 8]);
+  let arrays = structure("arrays");
+  assert_eq!((types.size(&arrays), types.alignment(&arrays)), (32, 8));
+  assert_eq!(offsets(&types, &arrays), [0, 8, 16]);
+}
+
+#[test]
+fn union_members_share_their_offset() {
+  let types = types("union u { char c[5]; int i; };");
+  let union = Type::Union(Rc::new("u".to_string()));
+  assert_eq!((types.size(&union), types.alignment(&union)), (8, 4));
+  assert_eq!(offsets(&types, &union), [0, 0]);
+}
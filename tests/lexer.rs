@@ -0,0 +1,90 @@
+use compiler::{
+  lex::{string_tokenizer, IntegerKind, Token},
+  utility::FileId,
+};
+
+/// Token the integer literal `literal` is read as, or the code of the error
+/// reported.
+fn integer(literal: &str) -> Result<Token, &'static str> {
+  match string_tokenizer(literal, FileId(0)).as_deref() {
+    Ok([token]) => Ok(token.token.clone()),
+    Ok(tokens) => panic!("{} read as {} tokens", literal, tokens.len()),
+    Err(error) => Err(error.code()),
+  }
+}
+
+// the expected types are the ones C gives the literals on x86-64
+
+#[test]
+fn decimal_literals_stay_signed_without_a_suffix() {
+  use IntegerKind::*;
+  let cases = [
+    ("0", 0, Int),
+    ("2147483647", 2147483647, Int),
+    ("2147483648", 2147483648, Long),
+    ("4294967295", 4294967295, Long),
+    ("9223372036854775807", i64::MAX, Long),
+    ("1l", 1, Long),
+    ("1LL", 1, Long),
+    ("1u", 1, UInt),
+    ("4294967296U", 4294967296, ULong),
+    ("1ul", 1, ULong),
+    ("1LLU", 1, ULong),
+  ];
+  for (literal, value, kind) in cases {
+    assert_eq!(
+      integer(literal),
+      Ok(Token::Integer(value, kind)),
+      "{}",
+      literal
+    );
+  }
+}
+
+#[test]
+fn other_bases_promote_to_unsigned_types() {
+  use IntegerKind::*;
+  let cases = [
+    ("0x7fffffff", 0x7fffffff, Int),
+    ("0x80000000", 0x80000000, UInt),
+    ("0xFFFFFFFF", 0xffffffff, UInt),
+    ("0x100000000", 0x100000000, Long),
+    ("0x7fffffffffffffff", i64::MAX, Long),
+    ("0x8000000000000000", i64::MIN, ULong),
+    ("0xffffffffffffffff", -1, ULong),
+    ("0xffffffffL", 0xffffffff, Long),
+    ("0x8000000000000000l", i64::MIN, ULong),
+    ("017", 15, Int),
+    ("037777777777", 0xffffffff, UInt),
+    ("0b101", 5, Int),
+    ("0B11111111111111111111111111111111", 0xffffffff, UInt),
+    ("18446744073709551615u", -1, ULong),
+  ];
+  for (literal, value, kind) in cases {
+    assert_eq!(
+      integer(literal),
+      Ok(Token::Integer(value, kind)),
+      "{}",
+      literal
+    );
+  }
+}
+
+#[test]
+fn malformed_literals() {
+  for literal in ["08", "0779", "0b2", "0x", "1uu", "1lul", "1lL", "12abc"] {
+    assert_eq!(integer(literal), Err("E0105"), "{}", literal);
+  }
+}
+
+#[test]
+fn literals_too_large_for_their_type() {
+  for literal in [
+    "18446744073709551616",
+    "9223372036854775808",
+    "0x10000000000000000",
+    "9223372036854775808l",
+  ] {
+    assert_eq!(integer(literal), Err("E0106"), "{}", literal);
+  }
+}
@@ -0,0 +1,56 @@
+use compiler::{lex::Token, tokenize, utility::SourceMap, CompileOptions};
+
+/// Tokens left in `source` once it is preprocessed, or the codes of the
+/// errors reported.
+fn preprocess(source: &str) -> Result<Vec<Token>, Vec<&'static str>> {
+  let mut sources = SourceMap::new();
+  let file = sources.add_file("test.c".to_string(), source.to_string());
+  match tokenize(&mut sources, file, &CompileOptions::new("test.c")) {
+    Ok(tokens) => Ok(tokens.into_iter().map(|token| token.token).collect()),
+    Err(diagnostics) => Err(diagnostics.iter().map(|error| error.code()).collect()),
+  }
+}
+
+#[test]
+fn conditionals_nest_inside_skipped_groups() {
+  let source = "#ifdef UNDEFINED\n#if 1\n#elif 2\n#else\n#endif\nint x;\n#endif\n";
+  assert_eq!(preprocess(source), Ok(vec![]));
+}
+
+#[test]
+fn unterminated_conditional_inside_skipped_group() {
+  let source = "#ifdef UNDEFINED\n#if 1\n#endif\n";
+  assert_eq!(preprocess(source), Err(vec!["E0504"]));
+}
+
+#[test]
+fn if_is_reported_as_unsupported() {
+  assert_eq!(preprocess("#if 1\n#endif\n"), Err(vec!["E0501"]));
+  assert_eq!(
+    preprocess("#ifdef UNDEFINED\n#elif 1\n#endif\n"),
+    Err(vec!["E0501"])
+  );
+}
+
+#[test]
+fn expanded_tokens_remember_the_invocation() {
+  let source = "#define g 1\n#define f(a) a*g\nint x = f(2);\n";
+  let mut sources = SourceMap::new();
+  let file = sources.add_file("test.c".to_string(), source.to_string());
+  let tokens = tokenize(&mut sources, file, &CompileOptions::new("test.c")).unwrap();
+  let stacks: Vec<_> = tokens
+    .iter()
+    .map(|token| {
+      sources
+        .expansion_stack(token.span)
+        .into_iter()
+        .map(|(name, invocation)| (name.to_string(), invocation.line, invocation.column))
+        .collect::<Vec<_>>()
+    })
+    .collect();
+
+  // the argument keeps its own location, `*` and `g` come from `f`
+  let f = ("f".to_string(), 3, 9);
+  let g = ("g".to_string(), 2, 16);
+  assert_eq!(stacks[3..6], [vec![], vec![f.clone()], vec![g, f]]);
+}
@@ -0,0 +1,39 @@
+use compiler::{compile, CompileOptions};
+
+/// Codes of the errors reported compiling `source`, empty when it compiles.
+fn errors(source: &str) -> Vec<&'static str> {
+  match compile(source, &CompileOptions::new("test.c")) {
+    Ok(_) => vec![],
+    Err(diagnostics) => diagnostics.iter().map(|error| error.code()).collect(),
+  }
+}
+
+#[test]
+fn array_length_is_taken_from_the_initialiser() {
+  let source = "int a[] = {1, 2, 3};\nchar s[] = \"abc\";\n\
+                int main(void) { return sizeof a + sizeof s; }\n";
+  assert_eq!(errors(source), Vec::<&str>::new());
+  assert_eq!(errors("int main(void) { int a[]; return 0; }"), ["E0427"]);
+}
+
+#[test]
+fn objects_too_large_for_their_size() {
+  let source = "int a[4611686018427387904];\n\
+                int main(void) { return sizeof a; }\n";
+  assert_eq!(errors(source), ["E0428"]);
+  assert_eq!(errors("int b[2305843009213693952][4];"), ["E0428"]);
+  let source = "struct s { char a[9223372036854775807]; char b[2]; };";
+  assert_eq!(errors(source), ["E0428"]);
+}
+
+#[test]
+fn stack_frames_too_large() {
+  let source = "int main(void) { char a[3000000000]; return a[0]; }";
+  assert_eq!(errors(source), ["E0429"]);
+  let source = "struct big { char a[2000000000]; };\nstruct big get(void);";
+  assert_eq!(errors(source), ["E0429"]);
+  // blocks which are left give their room back
+  let source = "int main(void) {\n  { char a[1000000000]; a[0] = 0; }\n  \
+                { char b[1000000000]; b[0] = 0; }\n  return 0;\n}\n";
+  assert_eq!(errors(source), Vec::<&str>::new());
+}